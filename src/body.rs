@@ -1,12 +1,55 @@
 
 use crate::error::Error;
+use crate::json_patch::PatchOp;
 use rand::{distributions::Alphanumeric, thread_rng, Rng};
+use serde::Serialize;
 use std::collections::HashMap;
+use std::fmt;
 use std::fs;
 use std::fs::File;
+use std::io::{self, Read, Write};
 use std::path::Path;
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncRead, AsyncReadExt};
 use urlencoding::{decode, encode};
 
+/// Error of sending a body from an async reader without HttpClient
+pub const ASYNC_ONLY: &str = "Bodies from async readers can only be sent by HttpClient";
+
+/// Reader streamed as the body, shared between clones of the body as it can only be read once.
+#[derive(Clone)]
+pub struct BodyReader(Arc<Mutex<Box<dyn Read + Send>>>);
+
+impl fmt::Debug for BodyReader {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("BodyReader")
+    }
+}
+
+/// Async reader streamed as the body, only sent by HttpClient
+#[derive(Clone)]
+pub struct AsyncBodyReader(Arc<tokio::sync::Mutex<Box<dyn AsyncRead + Send + Unpin>>>);
+
+impl fmt::Debug for AsyncBodyReader {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("AsyncBodyReader")
+    }
+}
+
+/// File part of a multipart body held in memory, eg. a generated PDF
+#[derive(Clone, Debug)]
+pub struct FileBytes {
+    pub filename: String,
+    pub mime: String,
+    pub data: Vec<u8>,
+}
+
+// Source of the contents of a file part
+enum FilePart<'a> {
+    Disk(&'a String),
+    Memory(&'a FileBytes),
+}
+
 #[derive(Clone, Debug)]
 pub struct HttpBody {
     is_form_post: bool,
@@ -14,17 +57,21 @@ pub struct HttpBody {
     raw: Vec<u8>,
     boundary: String,
     files: HashMap<String, String>,
+    file_bytes: HashMap<String, FileBytes>,
+    part_headers: HashMap<String, Vec<(String, String)>>,
+    file: Option<String>,
+    reader: Option<BodyReader>,
+    async_reader: Option<AsyncBodyReader>,
+    reader_length: Option<u64>,
+    content_type: Option<String>,
+    headers: Vec<(String, String)>,
 }
 
 
 impl HttpBody {
     // Instantiate new body
     pub fn new(params: &HashMap<String, String>, raw: &[u8]) -> Self {
-        let boundary: String = thread_rng()
-            .sample_iter(&Alphanumeric)
-            .take(30)
-            .map(|c| c as char)
-            .collect();
+        let boundary = Self::random_boundary(&mut thread_rng());
 
         Self {
             is_form_post: params.keys().len() > 0 || raw.len() > 0,
@@ -32,6 +79,14 @@ impl HttpBody {
             raw: raw.clone().to_vec(),
             boundary,
             files: HashMap::new(),
+            file_bytes: HashMap::new(),
+            part_headers: HashMap::new(),
+            file: None,
+            reader: None,
+            async_reader: None,
+            reader_length: None,
+            content_type: None,
+            headers: Vec::new(),
         }
     }
 
@@ -75,6 +130,102 @@ impl HttpBody {
         Self::new(&HashMap::new(), data.as_bytes())
     }
 
+    /// Generate body by serializing value as JSON, sent with Content-Type: application/json
+    pub fn from_json<T: Serialize>(value: &T) -> Result<Self, Error> {
+        let data = match serde_json::to_vec(value) {
+            Ok(r) => r,
+            Err(e) => {
+                return Err(Error::Custom(format!(
+                    "Unable to serialize JSON body, {}",
+                    e
+                )));
+            }
+        };
+
+        let mut body = Self::from_raw(&data);
+        body.content_type = Some("application/json".to_string());
+        Ok(body)
+    }
+
+    /// Generate JSON Patch body from the given operations, sent with
+    /// Content-Type: application/json-patch+json
+    pub fn json_patch(ops: &[PatchOp]) -> Result<Self, Error> {
+        let mut body = Self::from_json(&ops)?;
+        body.content_type = Some("application/json-patch+json".to_string());
+        Ok(body)
+    }
+
+    /// Generate JSON Merge Patch body by serializing value, where null removes a member, sent
+    /// with Content-Type: application/merge-patch+json
+    pub fn json_merge_patch<T: Serialize>(value: &T) -> Result<Self, Error> {
+        let mut body = Self::from_json(value)?;
+        body.content_type = Some("application/merge-patch+json".to_string());
+        Ok(body)
+    }
+
+    /// Generate Prometheus remote-write body from an encoded protobuf WriteRequest, snappy
+    /// compressed and sent with the Content-Encoding, Content-Type and
+    /// X-Prometheus-Remote-Write-Version headers receivers require.  Requires the 'snappy' feature.
+    #[cfg(feature = "snappy")]
+    pub fn from_protobuf(message: &[u8]) -> Result<Self, Error> {
+        let data = match snap::raw::Encoder::new().compress_vec(message) {
+            Ok(r) => r,
+            Err(e) => {
+                return Err(Error::Custom(format!(
+                    "Unable to snappy compress body, {}",
+                    e
+                )));
+            }
+        };
+
+        let mut body = Self::from_raw(&data);
+        body.content_type = Some("application/x-protobuf".to_string());
+        body.headers = vec![
+            ("Content-Encoding".to_string(), "snappy".to_string()),
+            (
+                "X-Prometheus-Remote-Write-Version".to_string(),
+                "0.1.0".to_string(),
+            ),
+        ];
+        Ok(body)
+    }
+
+    /// Generate body from a file, which is streamed to the server instead of being read into
+    /// memory.
+    pub fn from_file(file_path: &str) -> Result<Self, Error> {
+        if !Path::new(&file_path).is_file() {
+            return Err(Error::FileNotExists(file_path.to_string()));
+        }
+
+        let mut body = Self::empty();
+        body.file = Some(file_path.to_string());
+        Ok(body)
+    }
+
+    /// Generate body from a reader, which is streamed to the server instead of being read into
+    /// memory.  Sent with a Content-Length if the length is given, otherwise with
+    /// Transfer-Encoding: chunked.  The reader is consumed by the first request sending the body.
+    pub fn from_reader(reader: impl Read + Send + 'static, length: Option<u64>) -> Self {
+        let mut body = Self::empty();
+        body.reader = Some(BodyReader(Arc::new(Mutex::new(Box::new(reader)))));
+        body.reader_length = length;
+        body
+    }
+
+    /// Generate body from an async reader, eg. a tokio File or socket, as per from_reader().
+    /// Only sent by HttpClient, HttpSyncClient fails to send it.
+    pub fn from_async_reader(
+        reader: impl AsyncRead + Send + Unpin + 'static,
+        length: Option<u64>,
+    ) -> Self {
+        let mut body = Self::empty();
+        body.async_reader = Some(AsyncBodyReader(Arc::new(tokio::sync::Mutex::new(
+            Box::new(reader),
+        ))));
+        body.reader_length = length;
+        body
+    }
+
     /// Add post parameter
     pub fn set_param(&mut self, key: &str, value: &str) {
         *self
@@ -94,13 +245,39 @@ impl HttpBody {
             .files
             .entry(param_name.to_string())
             .or_insert(file_path.to_string()) = file_path.to_string();
+        self.is_form_post = true;
 
         Ok(())
     }
 
-    /// Format body for HTTP message
+    /// Upload contents held in memory as a file part of a multipart body, eg. a rendered PDF,
+    /// with the given file name and mime type
+    pub fn add_file_bytes(&mut self, param_name: &str, filename: &str, mime: &str, bytes: &[u8]) {
+        self.file_bytes.insert(
+            param_name.to_string(),
+            FileBytes {
+                filename: filename.to_string(),
+                mime: mime.to_string(),
+                data: bytes.to_vec(),
+            },
+        );
+        self.is_form_post = true;
+    }
+
+    /// Set header of the multipart section of the given parameter or file, eg.
+    /// Content-Transfer-Encoding or Content-ID, replacing any of the same name.  A Content-Type
+    /// set this way replaces the one guessed for files.  Only used by multipart bodies, ie. those
+    /// with files.
+    pub fn set_part_header(&mut self, param_name: &str, key: &str, value: &str) {
+        let headers = self.part_headers.entry(param_name.to_string()).or_default();
+        headers.retain(|(name, _)| !name.eq_ignore_ascii_case(key));
+        headers.push((key.to_string(), value.to_string()));
+    }
+
+    /// Format body for HTTP message.  File parts of multipart bodies are left out, as they are
+    /// streamed to the server via write_stream().
     pub fn format(&self) -> Vec<u8> {
-        if !self.files.is_empty() {
+        if self.is_multipart() {
             return self.format_multipart();
         } else if self.raw.len() > 0 {
             return self.raw.clone();
@@ -109,7 +286,7 @@ impl HttpBody {
         }
 
         let body = self
-            .params
+            .sorted_params()
             .iter()
             .map(|(key, value)| format!("{}={}", key, encode(value)))
             .collect::<Vec<String>>()
@@ -118,55 +295,253 @@ impl HttpBody {
         body.as_bytes().to_vec()
     }
 
-    /// Format multipart message, used for uploading files
+    /// Format parameter parts of multipart message, used for uploading files
     fn format_multipart(&self) -> Vec<u8> {
-
-        // Go through params
         let mut body: Vec<u8> = Vec::new();
-        for (key, value) in self.params.iter() {
-            let section = format!(
-                "--{}\r\nContent-Disposition: form-data; name=\"{}\"\r\n\r\n{}\r\n",
-                self.boundary, key, value
-            );
-            body.extend_from_slice(section.as_bytes());
+        for (key, value) in self.sorted_params() {
+            let disposition = format!("form-data; name=\"{}\"", key);
+            body.extend_from_slice(self.part_header(key, &disposition, None).as_bytes());
+            body.extend_from_slice(format!("{}\r\n", value).as_bytes());
         }
+        body
+    }
 
-        // Go through files
-        for (key, filepath) in self.files.iter() {
-            let (filename, mime_type, contents) = self.get_file_info(filepath);
-            let section = format!("--{}\r\nContent-Disposition: form-data; name=\"{}\"; filename=\"{}\"\r\nContent-Type: {}\r\n\r\n", self.boundary, key, filename, mime_type);
-            body.extend_from_slice(section.as_bytes());
-            body.extend_from_slice(&contents);
-            body.extend_from_slice("\r\n".as_bytes());
+    // Stream file parts of multipart message and its closing boundary, one chunk at a time
+    fn write_multipart_files(&self, writer: &mut dyn Write) -> io::Result<()> {
+        for (key, part) in self.file_parts() {
+            writer.write_all(self.file_part_header(key, &part).as_bytes())?;
+            match part {
+                FilePart::Disk(file_path) => {
+                    let mut fh = File::open(file_path)?;
+                    io::copy(&mut fh, writer)?;
+                }
+                FilePart::Memory(file) => writer.write_all(&file.data)?,
+            }
+            writer.write_all(b"\r\n")?;
         }
-        body.extend_from_slice(format!("--{}--\r\n", self.boundary).as_bytes());
+        writer.write_all(self.multipart_closing().as_bytes())
+    }
 
-        body
+    // Length of file parts of multipart message and its closing boundary
+    fn multipart_files_length(&self) -> u64 {
+        let mut length = self.multipart_closing().len() as u64;
+        for (key, part) in self.file_parts() {
+            let size = match part {
+                FilePart::Disk(file_path) => fs::metadata(file_path).map(|m| m.len()).unwrap_or(0),
+                FilePart::Memory(file) => file.data.len() as u64,
+            };
+            length += self.file_part_header(key, &part).len() as u64 + size + 2;
+        }
+        length
+    }
+
+    // Header of a file part, with its file name and mime type, guessed for files on disk
+    fn file_part_header(&self, key: &str, part: &FilePart) -> String {
+        let (filename, mime_type) = match part {
+            FilePart::Disk(file_path) => (
+                Path::new(file_path)
+                    .file_name()
+                    .map(|name| name.to_string_lossy().to_string())
+                    .unwrap_or_else(|| file_path.to_string()),
+                Self::guess_mime(file_path),
+            ),
+            FilePart::Memory(file) => (file.filename.clone(), file.mime.clone()),
+        };
+        let disposition = format!("form-data; name=\"{}\"; filename=\"{}\"", key, filename);
+        self.part_header(key, &disposition, Some(&mime_type))
+    }
+
+    // Header of a multipart section, followed by the custom headers of the part.  A custom
+    // Content-Type replaces the given one.
+    fn part_header(&self, key: &str, disposition: &str, content_type: Option<&str>) -> String {
+        let custom = self
+            .part_headers
+            .get(key)
+            .map(|headers| headers.as_slice())
+            .unwrap_or(&[]);
+        let mut header = format!(
+            "--{}\r\nContent-Disposition: {}\r\n",
+            self.boundary, disposition
+        );
+        if !custom
+            .iter()
+            .any(|(name, _)| name.eq_ignore_ascii_case("content-type"))
+        {
+            if let Some(content_type) = content_type {
+                header.push_str(&format!("Content-Type: {}\r\n", content_type));
+            }
+        }
+        for (name, value) in custom {
+            header.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        header.push_str("\r\n");
+        header
+    }
+
+    // Closing boundary of multipart message
+    fn multipart_closing(&self) -> String {
+        format!("--{}--\r\n", self.boundary)
     }
 
-    /// Get info for uploaded file
-    fn get_file_info(&self, filepath: &String) -> (String, String, Vec<u8>) {
-        // Get filename
-        let pos = filepath
-            .rfind('/')
-            .or_else(|| filepath.rfind('\\'))
-            .unwrap();
-        let filename = filepath[pos + 1..].to_string();
+    // Params sorted by name, so generated bodies are reproducible
+    fn sorted_params(&self) -> Vec<(&String, &String)> {
+        let mut params: Vec<(&String, &String)> = self.params.iter().collect();
+        params.sort();
+        params
+    }
 
-        // Get mime type
+    // File parts from disk and memory sorted by parameter name, so generated bodies are
+    // reproducible
+    fn file_parts(&self) -> Vec<(&String, FilePart<'_>)> {
+        let mut parts: Vec<(&String, FilePart)> = self
+            .files
+            .iter()
+            .map(|(key, file_path)| (key, FilePart::Disk(file_path)))
+            .chain(
+                self.file_bytes
+                    .iter()
+                    .map(|(key, file)| (key, FilePart::Memory(file))),
+            )
+            .collect();
+        parts.sort_by(|a, b| a.0.cmp(b.0));
+        parts
+    }
+
+    /// Guess mime type of file, defaults to application/octet-stream
+    pub fn guess_mime(filepath: &str) -> String {
         let mime_guess = mime_guess::from_path(filepath);
-        let mime_type = if mime_guess.count() > 0 {
+        if mime_guess.count() > 0 {
             mime_guess.first().unwrap().to_string()
         } else {
             "application/octet-stream".to_string()
+        }
+    }
+
+    /// Length of body in bytes, as sent within the Content-Length header
+    pub fn content_length(&self) -> u64 {
+        if let Some(file_path) = &self.file {
+            return fs::metadata(file_path).map(|m| m.len()).unwrap_or(0);
+        } else if let Some(length) = self.reader_length {
+            return length;
+        } else if self.is_multipart() {
+            return self.format().len() as u64 + self.multipart_files_length();
+        }
+        self.format().len() as u64
+    }
+
+    /// Write streamed portion of body to the server.  No-op unless body was created with
+    /// from_file() or from_reader(), the latter being written in chunks if its length is unknown,
+    /// or files were uploaded within a multipart body.
+    pub fn write_stream(&self, writer: &mut dyn Write) -> io::Result<()> {
+        if self.is_multipart() {
+            self.write_multipart_files(writer)?;
+        } else if let Some(file_path) = &self.file {
+            let mut fh = File::open(file_path)?;
+            io::copy(&mut fh, writer)?;
+        } else if let Some(BodyReader(reader)) = &self.reader {
+            let mut reader = reader
+                .lock()
+                .map_err(|_| io::Error::other("Body reader poisoned"))?;
+            let mut buffer = vec![0u8; 8192];
+            let mut written = 0;
+            loop {
+                let max = self.max_read(written, buffer.len());
+                let bytes_read = match reader.read(&mut buffer[..max]) {
+                    Ok(r) => r,
+                    Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                    Err(e) => return Err(e),
+                };
+                if bytes_read == 0 {
+                    break;
+                }
+                self.write_part(writer, &buffer[..bytes_read])?;
+                written += bytes_read as u64;
+            }
+            self.finish_stream(writer, written)?;
+        } else if self.async_reader.is_some() {
+            return Err(io::Error::other(ASYNC_ONLY));
+        }
+        Ok(())
+    }
+
+    /// Write streamed portion of body to the server as per write_stream(), reading bodies
+    /// created with from_async_reader() without blocking
+    pub async fn write_stream_async(&self, writer: &mut (dyn Write + Send)) -> io::Result<()> {
+        let AsyncBodyReader(reader) = match &self.async_reader {
+            Some(r) => r,
+            None => return self.write_stream(writer),
         };
+        let mut reader = reader.lock().await;
+        let mut buffer = vec![0u8; 8192];
+        let mut written = 0;
+        loop {
+            let max = self.max_read(written, buffer.len());
+            let bytes_read = reader.read(&mut buffer[..max]).await?;
+            if bytes_read == 0 {
+                break;
+            }
+            self.write_part(writer, &buffer[..bytes_read])?;
+            written += bytes_read as u64;
+        }
+        self.finish_stream(writer, written)
+    }
+
+    // Number of bytes to read next from the reader, stopping once the given length is reached
+    fn max_read(&self, written: u64, buffer_len: usize) -> usize {
+        match self.reader_length {
+            Some(length) => (length - written.min(length)).min(buffer_len as u64) as usize,
+            None => buffer_len,
+        }
+    }
 
-        let _file = File::open(filepath).unwrap();
-        let content =
-            fs::read(filepath).unwrap_or_else(|_| panic!("Unable to read file at, {}", filepath));
+    // Write data read from the reader, as a chunk if the length is unknown
+    fn write_part(&self, writer: &mut dyn Write, data: &[u8]) -> io::Result<()> {
+        if self.reader_length.is_some() {
+            return writer.write_all(data);
+        }
+        writer.write_all(format!("{:x}\r\n", data.len()).as_bytes())?;
+        writer.write_all(data)?;
+        writer.write_all(b"\r\n")
+    }
+
+    // End streamed body, failing if the reader ended before the given length
+    fn finish_stream(&self, writer: &mut dyn Write, written: u64) -> io::Result<()> {
+        match self.reader_length {
+            Some(length) if written < length => Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!("Body reader ended after {} of {} bytes", written, length),
+            )),
+            Some(_) => Ok(()),
+            None => writer.write_all(b"0\r\n\r\n"),
+        }
+    }
+
+    /// Whether or not body is streamed with Transfer-Encoding: chunked
+    pub fn is_chunked(&self) -> bool {
+        self.is_reader() && self.reader_length.is_none()
+    }
 
-        (filename, mime_type, content)
+    /// Whether or not body is streamed from a reader, so can only be sent once
+    pub fn is_reader(&self) -> bool {
+        self.reader.is_some() || self.async_reader.is_some()
     }
+
+    /// Whether or not part of the body is streamed via write_stream(), rather than sent within
+    /// the message
+    pub fn is_streamed(&self) -> bool {
+        self.is_reader() || self.file.is_some() || self.is_multipart()
+    }
+
+    /// Whether or not body is sent as multipart/form-data, as files were uploaded
+    pub fn is_multipart(&self) -> bool {
+        !self.files.is_empty() || !self.file_bytes.is_empty()
+    }
+
+    /// Whether or not body is streamed from an async reader, so can only be sent by HttpClient
+    pub fn is_async(&self) -> bool {
+        self.async_reader.is_some()
+    }
+
     /// Get is_form_post
     pub fn is_form_post(&self) -> bool {
         self.is_form_post
@@ -193,8 +568,49 @@ impl HttpBody {
         self.boundary.clone()
     }
 
+    /// Set multipart boundary
+    pub fn set_boundary(&mut self, boundary: &str) {
+        self.boundary = boundary.to_string();
+    }
+
+    /// Generate random multipart boundary from the given RNG
+    pub fn random_boundary<R: Rng>(rng: &mut R) -> String {
+        rng.sample_iter(&Alphanumeric)
+            .take(30)
+            .map(|c| c as char)
+            .collect()
+    }
+
+    /// Get Content-Type implied by the body, if any
+    pub fn content_type(&self) -> Option<String> {
+        self.content_type.clone()
+    }
+
+    /// Get headers other than Content-Type implied by the body, such as Content-Encoding
+    pub fn headers(&self) -> Vec<(String, String)> {
+        self.headers.clone()
+    }
+
+    /// Get file streamed as the body, if any
+    pub fn file(&self) -> Option<String> {
+        self.file.clone()
+    }
+
     /// Get uploaded files
     pub fn files(&self) -> HashMap<String, String> {
         self.files.clone()
     }
+
+    /// Get files uploaded from memory
+    pub fn file_bytes(&self) -> HashMap<String, FileBytes> {
+        self.file_bytes.clone()
+    }
+
+    /// Get custom headers of the multipart section of the given parameter or file
+    pub fn part_headers(&self, param_name: &str) -> Vec<(String, String)> {
+        self.part_headers
+            .get(param_name)
+            .cloned()
+            .unwrap_or_default()
+    }
 }
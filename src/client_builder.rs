@@ -1,27 +1,160 @@
 use base64::{engine::general_purpose::STANDARD, Engine as _};
-use rustls::{ClientConfig, RootCertStore};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+use rustls::crypto::CryptoProvider;
+use rustls::RootCertStore;
+use std::collections::HashMap;
+use std::net::IpAddr;
 use std::path::Path;
 use std::sync::Arc;
-use super::{CookieJar, HttpClient, HttpHeaders, HttpSyncClient, ProxyType};
-use crate::{tls_noverify, user_agent};
+use std::time::Duration;
+use url::Url;
+use super::{
+    ClientCert, CookieJar, CookieLimits, HttpBody, HttpClient, HttpHeaders, HttpRequest,
+    HttpSyncClient, NoProxy, ProxyType, Redaction, SystemProxy,
+};
+use crate::clock::{Clock, SystemClock};
+use crate::connector::{Connector, DefaultConnector};
+use crate::credentials::CredentialsProvider;
+use crate::hash::HashAlgorithm;
+use crate::locale::LocalePack;
+use crate::metrics::RequestStats;
+use crate::rate_limit::RateLimit;
+use crate::resolver::{CachingResolver, IpFamily, Resolver, SystemResolver};
+use crate::response::{BodyTransformer, HeaderDecision, HttpResponse, ParseMode};
+use crate::retry::RetryPolicy;
+use crate::error::Error;
+use crate::{crypto, tls_noverify, upload, user_agent, wire_log};
+use crate::timings::Phase;
+
+/// Failures upon which HttpClient::get_auto() retries over http after https fails
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AutoFallback {
+    /// Never fall back, only try https
+    Never,
+    /// Fall back when the TLS handshake fails or times out
+    Tls,
+    /// Fall back when the TLS handshake fails, or the connection is refused or times out
+    Connect,
+}
+
+impl AutoFallback {
+    /// Whether or not the error from the https attempt warrants trying http
+    pub fn matches(&self, err: &Error) -> bool {
+        let tls = match err {
+            Error::Tls(_) => true,
+            Error::Timeout(e) => e.phase == Phase::Tls,
+            _ => false,
+        };
+        let connect = match err {
+            Error::NoConnect(_) => true,
+            Error::Timeout(e) => e.phase == Phase::Connect,
+            Error::Proxy(e) => !e.failure.is_proxy_failure(),
+            _ => false,
+        };
+
+        match self {
+            AutoFallback::Never => false,
+            AutoFallback::Tls => tls,
+            AutoFallback::Connect => tls || connect,
+        }
+    }
+}
+
+/// Which requests may be sent as TLS 1.3 early data (0-RTT) when resuming a session with a
+/// server that allows it.  Early data can be replayed by an attacker, so only requests safe to
+/// repeat should be sent that way.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EarlyData {
+    /// Always complete the handshake before sending a request
+    Never,
+    /// Send requests of safe methods without a streamed body as early data, as per RFC 8470
+    /// section 4 and RFC 9110 section 9.2.1
+    SafeOnly,
+}
+
+impl EarlyData {
+    /// Whether or not request may be sent as early data
+    pub fn allows(&self, req: &HttpRequest) -> bool {
+        match self {
+            EarlyData::Never => false,
+            EarlyData::SafeOnly => {
+                let method = req.method.to_uppercase();
+                ["GET", "HEAD", "OPTIONS", "TRACE"].contains(&method.as_str())
+                    && !req.body.is_streamed()
+                    && !req.headers.has_lower("expect")
+            }
+        }
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct HttpClientConfig {
     pub tls_config: Arc<rustls::ClientConfig>,
+    pub crypto_provider: Arc<CryptoProvider>,
     pub user_agent: Option<String>,
+    pub locale: Option<LocalePack>,
+    pub host_locales: HashMap<String, LocalePack>,
     pub headers: HttpHeaders,
     pub cookie: CookieJar,
+    pub jars: HashMap<String, CookieJar>,
     pub follow_location: bool,
+    pub max_redirects: Option<usize>,
+    pub redirect_cache_size: usize,
+    pub redirect_cache_ttl: u64,
     pub timeout: u64,
+    pub ttfb_timeout: Option<u64>,
+    pub read_timeout: Option<u64>,
+    pub write_timeout: Option<u64>,
+    pub probe_idle: bool,
+    pub max_in_flight: usize,
+    pub max_in_flight_per_host: usize,
+    pub rate_limit: Option<RateLimit>,
+    pub rate_limit_per_host: Option<RateLimit>,
+    pub host_rate_limits: HashMap<String, RateLimit>,
+    pub retry: Option<RetryPolicy>,
+    pub credentials: Option<Arc<dyn CredentialsProvider>>,
+    pub expect_continue: Option<u64>,
+    pub on_headers: Option<fn(&HttpResponse) -> HeaderDecision>,
+    pub on_request_complete: Option<fn(&RequestStats)>,
+    pub on_upload_progress: Option<fn(u64, u64)>,
+    pub wire_log: Option<fn(&str)>,
+    pub wire_log_body_limit: usize,
+    pub upload_chunk_size: usize,
+    pub upload_retries: usize,
+    pub body_transformers: Vec<BodyTransformer>,
+    pub alpn_hosts: HashMap<String, Vec<String>>,
+    pub sni_hosts: HashMap<String, String>,
+    pub hash_body: Option<HashAlgorithm>,
+    pub transcode_downloads: bool,
+    pub rng: Option<StdRng>,
+    pub default_query: Vec<(String, String)>,
+    pub base_url: Option<String>,
+    pub schemes: HashMap<String, u16>,
+    pub redaction: Redaction,
+    pub parse_mode: ParseMode,
+    pub strict: bool,
+    pub normalize_reason: bool,
+    pub resolver: Arc<dyn Resolver>,
+    pub connector: Arc<dyn Connector>,
+    pub clock: Arc<dyn Clock>,
+    pub ip_family: IpFamily,
+    pub local_address: Option<IpAddr>,
+    pub auto_fallback: AutoFallback,
+    pub early_data: EarlyData,
     pub proxy_type: ProxyType,
     pub proxy_host: String,
     pub proxy_port: u16,
     pub proxy_user: String,
     pub proxy_password: String,
+    pub no_proxy: NoProxy,
 }
 
 pub struct HttpClientBuilder {
     config: HttpClientConfig,
+    random_user_agent: bool,
+    locale_packs: Vec<LocalePack>,
 }
 
 impl Default for HttpClientBuilder {
@@ -33,18 +166,39 @@ impl Default for HttpClientBuilder {
 impl HttpClientBuilder {
     pub fn new() -> Self {
         Self {
-            config: HttpClientConfig::default()
+            config: HttpClientConfig::default(),
+            random_user_agent: false,
+            locale_packs: Vec::new(),
         }
     }
 
     /// Finish building, and return asynchronous HTTP client
     pub fn build_async(&mut self) -> HttpClient {
-        HttpClient::new(&self.config)
+        HttpClient::new(&self.finish())
     }
 
     /// Finish building, and return blocking synchronous HTTP client
     pub fn build_sync(&mut self) -> HttpSyncClient {
-        HttpSyncClient::new(&self.config)
+        HttpSyncClient::new(&self.finish())
+    }
+
+    /// Final config, with the random picks of browser() and locale_packs() drawn from the RNG,
+    /// so they follow seed() wherever it was called
+    fn finish(&self) -> HttpClientConfig {
+        let mut config = self.config.clone();
+        let mut thread_rng = rand::thread_rng();
+        let mut rng: &mut dyn rand::RngCore = match config.rng.as_mut() {
+            Some(rng) => rng,
+            None => &mut thread_rng,
+        };
+
+        if self.random_user_agent && config.user_agent.is_none() {
+            config.user_agent = Some(user_agent::random_with(&mut rng));
+        }
+        if let Some(pack) = self.locale_packs.choose(&mut rng) {
+            config.locale = Some(pack.clone());
+        }
+        config
     }
 
     /// Will always follow Location headers it encounters
@@ -53,45 +207,405 @@ impl HttpClientBuilder {
         self
     }
 
+    /// Follow Location headers, but give up after max redirects with Error::TooManyRedirects
+    pub fn max_redirects(mut self, max: usize) -> Self {
+        self.config.follow_location = true;
+        self.config.max_redirects = Some(max);
+        self
+    }
+
+    /// Remember targets of permanent 301 / 308 redirects for up to ttl_seconds, and send later
+    /// requests for the same URL directly to the target.  Holds at most capacity URLs.
+    pub fn cache_redirects(mut self, capacity: usize, ttl_seconds: u64) -> Self {
+        self.config.redirect_cache_size = capacity;
+        self.config.redirect_cache_ttl = ttl_seconds;
+        self
+    }
+
     // Set timeout limit in seconds
     pub fn timeout(mut self, seconds: u64) -> Self {
         self.config.timeout = seconds;
         self
     }
 
+    /// Fail with Error::TtfbTimeout if the first byte of the response does not arrive within
+    /// the given seconds of sending the request
+    pub fn ttfb_timeout(mut self, seconds: u64) -> Self {
+        self.config.ttfb_timeout = Some(seconds);
+        self
+    }
+
+    /// Fail with Error::Timeout if the server sends nothing for the given seconds while the
+    /// response is being read.  Overridden per request by HttpRequest::with_read_timeout().
+    pub fn read_timeout(mut self, seconds: u64) -> Self {
+        self.config.read_timeout = Some(seconds);
+        self
+    }
+
+    /// Fail with Error::Timeout if the server accepts nothing for the given seconds while the
+    /// request is being sent
+    pub fn write_timeout(mut self, seconds: u64) -> Self {
+        self.config.write_timeout = Some(seconds);
+        self
+    }
+
+    /// Send Expect: 100-continue with request bodies, and wait up to timeout_ms milliseconds for
+    /// the server to respond with 100 Continue before sending the body anyway.
+    pub fn expect_continue(mut self, timeout_ms: u64) -> Self {
+        self.config.expect_continue = Some(timeout_ms);
+        self
+    }
+
+    /// Callback executed as soon as response headers are parsed, before the body is read.
+    /// Returning HeaderDecision::Abort closes the connection and returns Error::Aborted.
+    pub fn on_headers(mut self, callback: fn(&HttpResponse) -> HeaderDecision) -> Self {
+        self.config.on_headers = Some(callback);
+        self
+    }
+
+    /// Callback executed once each request completes or fails, after any retries, with its
+    /// status, duration, attempts and bytes transferred, eg. to feed metrics
+    pub fn on_request_complete(mut self, callback: fn(&RequestStats)) -> Self {
+        self.config.on_request_complete = Some(callback);
+        self
+    }
+
+    /// Callback executed with the exact bytes of each request and response as they go over the
+    /// wire, formatted as lines prefixed with "> " and "< ", for debugging.  Bodies are cut off
+    /// after wire_log_body_limit() bytes.  Authorization, Proxy-Authorization, Cookie and
+    /// Set-Cookie values are always masked, along with anything else masked via redaction().
+    pub fn wire_log(mut self, callback: fn(&str)) -> Self {
+        self.config.wire_log = Some(callback);
+        self
+    }
+
+    /// Log exact request and response bytes to stderr, as per wire_log()
+    pub fn wire_log_stderr(mut self) -> Self {
+        self.config.wire_log = Some(wire_log::stderr);
+        self
+    }
+
+    /// Number of body bytes logged per request and response, defaults to 1024
+    pub fn wire_log_body_limit(mut self, bytes: usize) -> Self {
+        self.config.wire_log_body_limit = bytes;
+        self
+    }
+
+    /// Callback executed with the bytes uploaded so far and the total size as each chunk of
+    /// upload_resumable() is accepted
+    pub fn on_upload_progress(mut self, callback: fn(u64, u64)) -> Self {
+        self.config.on_upload_progress = Some(callback);
+        self
+    }
+
+    /// Size of each chunk sent by upload_resumable(), defaults to 8 MiB.  Services such as Google
+    /// Cloud Storage require a multiple of 256 KiB.
+    pub fn upload_chunk_size(mut self, size: usize) -> Self {
+        self.config.upload_chunk_size = size.max(1);
+        self
+    }
+
+    /// Number of times upload_resumable() retries a failed chunk before giving up, defaults to 5
+    pub fn upload_retries(mut self, retries: usize) -> Self {
+        self.config.upload_retries = retries;
+        self
+    }
+
+    /// Add transformer to the response body pipeline.  Transformers run in the order added on the
+    /// full body, after decompression and charset decoding, for both regular requests and
+    /// downloads.  Downloads are only charset decoded if transcode_downloads() is set, and bodies
+    /// read incrementally via send_stream() or download_and_extract() bypass transformers.
+    pub fn body_transformer(mut self, transformer: BodyTransformer) -> Self {
+        self.config.body_transformers.push(transformer);
+        self
+    }
+
+    /// Offer ALPN protocols during the TLS handshake, in order of preference, eg. ["http/1.1"].
+    /// None are offered by default.  Only http/1.1 is spoken, so other protocols such as h2 are
+    /// left out rather than failing with servers which pick them.  The negotiated protocol is
+    /// available via HttpResponse::alpn_protocol().
+    pub fn alpn_protocols(mut self, protocols: &[&str]) -> Self {
+        let mut tls_config = (*self.config.tls_config).clone();
+        tls_config.alpn_protocols = spoken_protocols(protocols)
+            .iter()
+            .map(|p| p.as_bytes().to_vec())
+            .collect();
+        self.config.tls_config = Arc::new(tls_config);
+        self
+    }
+
+    /// Override the ALPN protocols offered during the TLS handshake with a specific host, eg.
+    /// pin a misbehaving host to ["http/1.1"].  As with alpn_protocols(), protocols other than
+    /// http/1.1 are left out.  The negotiated protocol is available via
+    /// HttpResponse::alpn_protocol().
+    pub fn alpn_host(mut self, host: &str, protocols: &[&str]) -> Self {
+        self.config
+            .alpn_hosts
+            .insert(host.to_lowercase(), spoken_protocols(protocols));
+        self
+    }
+
+    /// Send a different server name in the TLS handshake with a specific host, which the
+    /// certificate is verified against instead.  HttpRequest::with_sni() overrides it per request.
+    pub fn sni_host(mut self, host: &str, server_name: &str) -> Self {
+        self.config
+            .sni_hosts
+            .insert(host.to_string(), server_name.to_string());
+        self
+    }
+
+    /// Hash response bodies while they are read or downloaded, available via
+    /// HttpResponse::body_hash().  The hash covers the body after decompression, before charset
+    /// conversion and transformers.
+    pub fn hash_body(mut self, algorithm: HashAlgorithm) -> Self {
+        self.config.hash_body = Some(algorithm);
+        self
+    }
+
+    /// Transcode downloaded text files declaring a charset other than UTF-8, such as
+    /// Shift_JIS or windows-1252, to UTF-8 while they are downloaded, before body transformers
+    pub fn transcode_downloads(mut self) -> Self {
+        self.config.transcode_downloads = true;
+        self
+    }
+
+    /// Allow an additional URL scheme besides http and https, eg. a custom internal scheme
+    /// resolved by your own connection logic.  Requests are sent as plain HTTP/1.1.
+    pub fn allow_scheme(mut self, scheme: &str, default_port: u16) -> Self {
+        self.config
+            .schemes
+            .insert(scheme.to_lowercase(), default_port);
+        self
+    }
+
+    /// How strictly response headers are parsed, ParseMode::Strict fails with
+    /// Error::MalformedHeader on folded or malformed header lines
+    pub fn parse_mode(mut self, mode: ParseMode) -> Self {
+        self.config.parse_mode = mode;
+        self
+    }
+
+    /// Replace the reason phrase of responses with the standard one of their status code, eg.
+    /// for appliances sending localized or non-ASCII reasons
+    pub fn normalize_reason(mut self) -> Self {
+        self.config.normalize_reason = true;
+        self
+    }
+
+    /// Validate each request before it is sent and each response header once received against
+    /// RFC 9110 and RFC 9112, failing with Error::Nonconformant listing all violations found.
+    /// Also parses response headers with ParseMode::Strict.
+    pub fn strict(mut self) -> Self {
+        self.config.strict = true;
+        self.config.parse_mode = ParseMode::Strict;
+        self
+    }
+
+    /// Resolve host names with the given resolver instead of the system resolver
+    pub fn resolver<R: Resolver + 'static>(mut self, resolver: R) -> Self {
+        self.config.resolver = Arc::new(resolver);
+        self
+    }
+
+    /// Open connections with the given connector instead of over TCP and rustls
+    pub fn connector<C: Connector + 'static>(mut self, connector: C) -> Self {
+        self.config.connector = Arc::new(connector);
+        self
+    }
+
+    /// Tell time with the given clock instead of the system clock, for cookie expiry, cached
+    /// redirects and DNS records, delays between retries and RequestStats, eg. a MockClock
+    /// within tests.  Call before dns_cache().
+    pub fn clock<C: Clock + 'static>(mut self, clock: C) -> Self {
+        self.config.clock = Arc::new(clock);
+        self.config.cookie.set_clock(Arc::clone(&self.config.clock));
+        for jar in self.config.jars.values_mut() {
+            jar.set_clock(Arc::clone(&self.config.clock));
+        }
+        self
+    }
+
+    /// Connect to IPv4 addresses of a host before its IPv6 ones
+    pub fn prefer_ipv4(mut self) -> Self {
+        self.config.ip_family = IpFamily::PreferIpv4;
+        self
+    }
+
+    /// Connect to IPv6 addresses of a host before its IPv4 ones
+    pub fn prefer_ipv6(mut self) -> Self {
+        self.config.ip_family = IpFamily::PreferIpv6;
+        self
+    }
+
+    /// Only connect to IPv4 addresses, eg. on hosts with broken IPv6 routes
+    pub fn ipv4_only(mut self) -> Self {
+        self.config.ip_family = IpFamily::Ipv4Only;
+        self
+    }
+
+    /// Bind outgoing connections to the given source address before connecting, eg. on
+    /// multi-homed servers.  Only addresses of the same family as the local address are tried.
+    pub fn local_address(mut self, addr: IpAddr) -> Self {
+        self.config.local_address = Some(addr);
+        self
+    }
+
+    /// Cache resolved addresses of up to max_entries host / port pairs for the TTL of their
+    /// records, or the given TTL if unknown as with the system resolver.  Wraps the resolver set
+    /// via resolver(), so call after it.
+    pub fn dns_cache(mut self, ttl: Duration, max_entries: usize) -> Self {
+        let inner = Arc::clone(&self.config.resolver);
+        self.config.resolver = Arc::new(
+            CachingResolver::new(inner, ttl, max_entries)
+                .with_clock(Arc::clone(&self.config.clock)),
+        );
+        self
+    }
+
+    /// Failures upon which HttpClient::get_auto() falls back from https to http, defaults to
+    /// AutoFallback::Connect
+    pub fn auto_fallback(mut self, fallback: AutoFallback) -> Self {
+        self.config.auto_fallback = fallback;
+        self
+    }
+
+    /// Send requests allowed by the policy as TLS 1.3 early data when resuming a session,
+    /// saving a round trip.  Requests the server rejects with 425 Too Early, or whose early data
+    /// it ignores, are sent again once the handshake completed.  Defaults to EarlyData::Never.
+    pub fn early_data(mut self, policy: EarlyData) -> Self {
+        self.config.early_data = policy;
+        self
+    }
+
+    /// Rules for masking secrets within diagnostic output, defaults to redacting the
+    /// Authorization and Proxy-Authorization headers
+    pub fn redaction(mut self, redaction: Redaction) -> Self {
+        self.config.redaction = redaction;
+        self
+    }
+
+    /// Save the cookie jar file at most once per interval instead of after every response that
+    /// sets cookies, eg. for high volume crawls.  Call close() to save remaining changes.
+    pub fn cookie_save_every(mut self, interval: Duration) -> Self {
+        self.config.cookie.set_save_every(Some(interval));
+        self
+    }
+
     /// Cookie jar file, will be auto-maintained unless you change auto-update to false via CookieJar::set_auto_update(bool) method.
     pub fn cookie_jar(mut self, jar_file: &str) -> Self {
         if !Path::new(&jar_file).exists() {
             self.config.cookie.set_jar_file(jar_file);
             self.config.cookie.set_auto_update(true);
         } else {
+            let (limits, save_every) =
+                (self.config.cookie.limits(), self.config.cookie.save_every());
             self.config.cookie = CookieJar::from_file(jar_file, true).unwrap();
+            self.config.cookie.set_limits(limits);
+            self.config.cookie.set_save_every(save_every);
+            self.config.cookie.set_clock(Arc::clone(&self.config.clock));
         }
         self
     }
 
     /// Set cookies from contents / lines of a Netscape formatted cookies.txt file
     pub fn cookie_string(mut self, cookie_str: &str) -> Self {
+        let (limits, save_every) = (self.config.cookie.limits(), self.config.cookie.save_every());
         self.config.cookie = CookieJar::from_string(&cookie_str.to_string());
+        self.config.cookie.set_limits(limits);
+        self.config.cookie.set_save_every(save_every);
+        self.config.cookie.set_clock(Arc::clone(&self.config.clock));
+        self
+    }
+
+    /// Register a cookie jar under the given name, selected per request via
+    /// HttpRequest::use_jar() to keep the sessions of many users apart within one client
+    pub fn named_jar(mut self, name: &str, mut jar: CookieJar) -> Self {
+        jar.set_clock(Arc::clone(&self.config.clock));
+        self.config.jars.insert(name.to_string(), jar);
+        self
+    }
+
+    /// Cap the size of the cookie jar, evicting least recently used cookies once full
+    pub fn cookie_limits(mut self, limits: CookieLimits) -> Self {
+        self.config.cookie.set_limits(limits);
         self
     }
 
     /// Do not verify SSL certificates
     pub fn noverify_ssl(mut self) -> Self {
+        let mut tls_config = self.rebuild_tls_config(crypto::webpki_roots());
+        tls_config.dangerous().set_certificate_verifier(Arc::new(
+            tls_noverify::NoCertificateVerification::new((*self.config.crypto_provider).clone()),
+        ));
+        self.config.tls_config = Arc::new(tls_config);
+        self
+    }
+
+    /// Use the given rustls crypto provider for TLS instead of the default from the 'ring' or
+    /// 'aws-lc-rs' feature, eg. a FIPS validated build.  Resets the trusted roots to the bundled
+    /// Mozilla roots, so call before noverify_ssl(), native_roots() or root_certificates().
+    pub fn crypto_provider(mut self, provider: CryptoProvider) -> Self {
+        self.config.crypto_provider = Arc::new(provider);
+        self.config.tls_config = Arc::new(self.rebuild_tls_config(crypto::webpki_roots()));
+        self
+    }
+
+    /// Present client certificate to servers requiring mutual TLS, loaded via
+    /// ClientCert::from_pem_files() or similar
+    pub fn client_cert(mut self, cert: ClientCert) -> Self {
+        let mut tls_config = (*self.config.tls_config).clone();
+        tls_config.client_auth_cert_resolver = Arc::new(cert);
+        self.config.tls_config = Arc::new(tls_config);
+        self
+    }
+
+    /// Verify servers against the given root certificates only, eg. of a private CA loaded via
+    /// crypto::roots_from_pem().  Required for https when built without the 'webpki-roots'
+    /// feature, unless native_roots() is used.
+    pub fn root_certificates(mut self, root_store: RootCertStore) -> Self {
+        self.config.tls_config = Arc::new(self.rebuild_tls_config(root_store));
+        self
+    }
+
+    /// Verify servers against the certificate store of the operating system instead of the bundled
+    /// Mozilla roots, falling back to the bundled roots if the system store is empty or unreadable,
+    /// as on minimal musl / distroless images.
+    #[cfg(feature = "native-roots")]
+    pub fn native_roots(mut self) -> Self {
         // Initialize root store
         let mut root_store = RootCertStore::empty();
-        root_store.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+        let native = rustls_native_certs::load_native_certs();
+        root_store.add_parsable_certificates(native.certs);
+        if root_store.is_empty() {
+            root_store = crypto::webpki_roots();
+        }
 
-        // Create config
-        let mut tls_config = ClientConfig::builder()
-            .with_root_certificates(root_store)
-            .with_no_client_auth();
+        self.config.tls_config = Arc::new(self.rebuild_tls_config(root_store));
+        self
+    }
 
-        tls_config.dangerous().set_certificate_verifier(Arc::new(
-            tls_noverify::NoCertificateVerification::new(rustls::crypto::ring::default_provider()),
-        ));
+    /// Resolve relative URLs of requests against a base URL, eg. "https://api.example.com/v1"
+    /// so get("/users") requests "https://api.example.com/v1/users".  Absolute URLs are sent
+    /// as they are.
+    pub fn base_url(mut self, url: &str) -> Self {
+        self.config.base_url = Some(url.to_string());
+        self
+    }
 
-        self.config.tls_config = Arc::new(tls_config);
+    /// Add query parameter to every request, eg. an api_key.  Parameters already within the URL of
+    /// a request take precedence, and HttpRequest::without_default_query() omits it for a request.
+    pub fn default_query_param(mut self, key: &str, value: &str) -> Self {
+        self.config.default_query.retain(|(name, _)| name != key);
+        self.config
+            .default_query
+            .push((key.to_string(), value.to_string()));
+        self
+    }
+
+    /// Seed the randomness of generated requests, such as multipart boundaries and the user agent
+    /// picked by browser(), so they are reproducible byte-for-byte.
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.config.rng = Some(StdRng::seed_from_u64(seed));
         self
     }
 
@@ -101,6 +615,31 @@ impl HttpClientBuilder {
         self
     }
 
+    /// Send the Accept-Language and other headers of the locale pack with every request,
+    /// overriding the default Accept-Language of browser()
+    pub fn locale(mut self, pack: LocalePack) -> Self {
+        self.locale_packs.clear();
+        self.config.locale = Some(pack);
+        self
+    }
+
+    /// Pick one of the locale packs at random for the session, the same way browser() picks
+    /// its user agent, so rotating browser profiles also rotate their locale
+    pub fn locale_packs(mut self, packs: &[LocalePack]) -> Self {
+        self.locale_packs = packs.to_vec();
+        self
+    }
+
+    /// Send the headers of the locale pack to the given host and its subdomains instead of
+    /// those of locale(), eg. host_locale("de", LocalePack::from_tag("de-DE")) for all sites
+    /// under .de
+    pub fn host_locale(mut self, host: &str, pack: LocalePack) -> Self {
+        self.config
+            .host_locales
+            .insert(host.trim_start_matches('.').to_lowercase(), pack);
+        self
+    }
+
     /// Set base headers to more closely emulate a web browser.
     pub fn browser(mut self) -> Self {
         // Create headers
@@ -113,11 +652,97 @@ impl HttpClientBuilder {
         self.config.headers.set("Accept-Encoding", "identity");
         self.config.headers.set("Connection", "close");
 
+        // User agent, picked when the client is built
+        self.random_user_agent = true;
+        self.config.follow_location = true;
+        self.config.parse_mode = ParseMode::Lenient;
+        self
+    }
+
+    /// Keep connections open after each response, and reuse them for later requests to the
+    /// same host.
+    pub fn keep_alive(mut self) -> Self {
+        self.config.headers.set("Connection", "keep-alive");
+        self
+    }
+
+    /// Cap the number of requests in flight at once across all clones of the client.  Further
+    /// requests wait their turn in FIFO order.  Streams of send_stream() and connections of
+    /// upgrade() count as in flight until dropped.
+    pub fn max_in_flight(mut self, max: usize) -> Self {
+        self.config.max_in_flight = max;
+        self
+    }
+
+    /// Cap the number of requests in flight at once to each host, by the host of the request
+    /// URL, across all clones of the client.  Requests wait for a slot of their host before one
+    /// of max_in_flight(), so a busy host does not hold up requests to others.
+    pub fn max_in_flight_per_host(mut self, max: usize) -> Self {
+        self.config.max_in_flight_per_host = max;
+        self
+    }
+
+    /// Limit the rate of requests across all hosts with a token bucket shared by all clones of
+    /// the client.  Requests over the rate sleep, or await within the async client, until their
+    /// turn.  Redirects followed count as requests.
+    pub fn rate_limit(mut self, limit: RateLimit) -> Self {
+        self.config.rate_limit = Some(limit);
+        self
+    }
+
+    /// Limit the rate of requests to each host separately, eg. so a crawler does not hammer any
+    /// one site.  Applies on top of rate_limit().
+    pub fn rate_limit_per_host(mut self, limit: RateLimit) -> Self {
+        self.config.rate_limit_per_host = Some(limit);
+        self
+    }
+
+    /// Limit the rate of requests to the given host, instead of that of rate_limit_per_host()
+    pub fn host_rate_limit(mut self, host: &str, limit: RateLimit) -> Self {
+        self.config
+            .host_rate_limits
+            .insert(host.to_lowercase(), limit);
+        self
+    }
+
+    /// Retry failed requests as per the policy, with exponential backoff between attempts.
+    /// Requests with bodies streamed from a reader are never retried.
+    pub fn retry(mut self, policy: RetryPolicy) -> Self {
+        self.config.retry = Some(policy);
+        self
+    }
+
+    /// Check idle pooled connections are still open before reusing them, reducing failed first
+    /// requests after the server or a NAT gateway dropped the connection.
+    pub fn probe_idle_connections(mut self) -> Self {
+        self.config.probe_idle = true;
+        self
+    }
+
+    /// Preset for machine-to-machine API calls, the counterpart of browser().  Reuses
+    /// connections, accepts JSON and gzip, sends a fixed user agent and follows at most 3
+    /// redirects.
+    pub fn api(mut self) -> Self {
+        // Create headers
+        self.config.headers = HttpHeaders::new();
+        self.config.headers.set("Accept", "application/json");
+        if cfg!(feature = "brotli") {
+            self.config
+                .headers
+                .set("Accept-Encoding", "gzip, deflate, br");
+        } else {
+            self.config.headers.set("Accept-Encoding", "gzip, deflate");
+        }
+        self.config.headers.set("Connection", "keep-alive");
+
         // User agent
-        if self.config.user_agent.is_none() {
-            self.config.user_agent = Some(user_agent::random());
+        if self.config.user_agent.is_none() && !self.random_user_agent {
+            self.config.user_agent = Some(format!("atlas-http/{}", env!("CARGO_PKG_VERSION")));
         }
+        self.config.timeout = 10;
         self.config.follow_location = true;
+        self.config.max_redirects = Some(3);
+        self.config.probe_idle = true;
         self
     }
 
@@ -137,6 +762,26 @@ impl HttpClientBuilder {
         self
     }
 
+    /// Define bearer token authentication, eg. an OAuth access token.  An empty token disables
+    /// authentication, and HttpRequest::bearer() overrides the token for a single request.
+    pub fn bearer_auth(mut self, token: &str) -> Self {
+        self.config.headers.delete_lower("authorization");
+        if !token.is_empty() {
+            self.config
+                .headers
+                .set("Authorization", &format!("Bearer {}", token));
+        }
+        self
+    }
+
+    /// Ask provider for credentials when a request is answered with 401 Unauthorized, retrying
+    /// the request once with them, eg. after refreshing an expired token.  Requests with bodies
+    /// streamed from readers are not retried.
+    pub fn credentials_provider<P: CredentialsProvider + 'static>(mut self, provider: P) -> Self {
+        self.config.credentials = Some(Arc::new(provider));
+        self
+    }
+
     /// Send requests over the Tor network.
     pub fn tor(mut self) -> Self {
         self.config.proxy_host = "127.0.0.1".to_string();
@@ -178,32 +823,196 @@ impl HttpClientBuilder {
         self.config.proxy_type = proxy_type;
         self
     }
+
+    /// Connect directly to the given hosts, domain suffixes and CIDR ranges instead of going
+    /// through the proxy, eg. &["localhost", ".internal", "10.0.0.0/8"]
+    pub fn no_proxy(mut self, entries: &[&str]) -> Self {
+        for entry in entries {
+            self.config.no_proxy.add(entry);
+        }
+        self
+    }
+
+    /// Use the proxy configured for the system, from the HTTPS_PROXY / HTTP_PROXY / ALL_PROXY and
+    /// NO_PROXY environment variables, or the WinINet settings on Windows with the 'system-proxy'
+    /// feature.  Requests connect directly if no proxy is configured.
+    pub fn system_proxy(mut self) -> Self {
+        let proxy = match SystemProxy::detect() {
+            Some(proxy) => proxy,
+            None => return self,
+        };
+
+        self.config.proxy_type = proxy.proxy_type;
+        self = self.proxy(&proxy.host, &proxy.port);
+        if !proxy.user.is_empty() {
+            self = self.proxy_auth(&proxy.user, &proxy.password);
+        }
+        for entry in proxy.no_proxy.iter() {
+            self.config.no_proxy.add(entry);
+        }
+        self
+    }
+
+    // New TLS config of the crypto provider trusting root_store, carrying over the client
+    // certificate and ALPN protocols of the current config
+    fn rebuild_tls_config(&self, root_store: RootCertStore) -> rustls::ClientConfig {
+        let mut tls_config =
+            crypto::client_config(Arc::clone(&self.config.crypto_provider), root_store);
+        tls_config.client_auth_cert_resolver =
+            Arc::clone(&self.config.tls_config.client_auth_cert_resolver);
+        tls_config.alpn_protocols = self.config.tls_config.alpn_protocols.clone();
+        tls_config
+    }
+}
+
+impl HttpClientConfig {
+    /// Type of proxy requests to the URL go through, ProxyType::None if no proxy is configured or
+    /// the host is excluded via no_proxy
+    pub fn proxy_for(&self, uri: &Url) -> ProxyType {
+        let host = uri.host_str().unwrap_or("");
+        if self.proxy_host.is_empty() || self.no_proxy.matches(host) {
+            return ProxyType::None;
+        }
+        self.proxy_type
+    }
+
+    /// Locale pack for requests to host, that of the host or its closest parent domain if any,
+    /// otherwise that of the client
+    pub fn locale_for(&self, host: &str) -> Option<&LocalePack> {
+        let mut domain = host.to_lowercase();
+        loop {
+            if let Some(pack) = self.host_locales.get(&domain) {
+                return Some(pack);
+            }
+            match domain.split_once('.') {
+                Some((_, parent)) => domain = parent.to_string(),
+                None => return self.locale.as_ref(),
+            }
+        }
+    }
+
+    /// Cookie jar of the given name, or the default jar if None
+    pub fn jar(&self, name: Option<&str>) -> Option<&CookieJar> {
+        match name {
+            Some(name) => self.jars.get(name),
+            None => Some(&self.cookie),
+        }
+    }
+
+    /// Resolve URL of a request against the base URL, leaving absolute URLs as they are.  The
+    /// path is appended to that of the base URL, so "/users" against
+    /// "https://api.example.com/v1" becomes "https://api.example.com/v1/users".
+    pub fn resolve_url(&self, url: &str) -> String {
+        let base = match &self.base_url {
+            Some(r) if Url::parse(url).is_err() => r.trim_end_matches('/'),
+            _ => return url.to_string(),
+        };
+
+        match url.chars().next() {
+            None => base.to_string(),
+            Some('/') | Some('?') | Some('#') => format!("{}{}", base, url),
+            Some(_) => format!("{}/{}", base, url),
+        }
+    }
+
+    /// Request as the clients send it, with its URL resolved against the base URL and, if
+    /// seeded, its multipart boundary drawn from the RNG.  Shared by sending, dry_run() and
+    /// to_curl(), so all show the same bytes.
+    pub fn outgoing(&mut self, req: &HttpRequest) -> HttpRequest {
+        let mut req = req.clone();
+        req.url = self.resolve_url(&req.url);
+        if let Some(rng) = self.rng.as_mut() {
+            req.body.set_boundary(&HttpBody::random_boundary(rng));
+        }
+        req
+    }
+
+    /// Mutable cookie jar of the given name, or the default jar if None
+    pub fn jar_mut(&mut self, name: Option<&str>) -> Option<&mut CookieJar> {
+        match name {
+            Some(name) => self.jars.get_mut(name),
+            None => Some(&mut self.cookie),
+        }
+    }
+}
+
+// ALPN protocols the client speaks out of those given, ie. only http/1.1 as HTTP/2 and later
+// are not supported
+fn spoken_protocols(protocols: &[&str]) -> Vec<String> {
+    protocols
+        .iter()
+        .filter(|p| p.eq_ignore_ascii_case("http/1.1"))
+        .map(|p| p.to_lowercase())
+        .collect()
 }
 
 impl Default for HttpClientConfig {
     fn default() -> HttpClientConfig {
 
-        // Initialize root store
-        let mut root_store = RootCertStore::empty();
-        root_store.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
-
         // Create config
-        let tls_config = ClientConfig::builder()
-            .with_root_certificates(root_store)
-            .with_no_client_auth();
+        let crypto_provider = crypto::default_provider();
+        let tls_config =
+            crypto::client_config(Arc::clone(&crypto_provider), crypto::webpki_roots());
 
         HttpClientConfig {
             tls_config: Arc::new(tls_config),
+            crypto_provider,
             user_agent: None,
+            locale: None,
+            host_locales: HashMap::new(),
             headers: HttpHeaders::from_vec(&vec!["Connection: close".to_string()]),
             cookie: CookieJar::new(),
+            jars: HashMap::new(),
             follow_location: false,
+            max_redirects: None,
+            redirect_cache_size: 0,
+            redirect_cache_ttl: 0,
             timeout: 5,
+            ttfb_timeout: None,
+            read_timeout: None,
+            write_timeout: None,
+            probe_idle: false,
+            max_in_flight: 0,
+            max_in_flight_per_host: 0,
+            rate_limit: None,
+            rate_limit_per_host: None,
+            host_rate_limits: HashMap::new(),
+            retry: None,
+            credentials: None,
+            expect_continue: None,
+            on_headers: None,
+            on_request_complete: None,
+            on_upload_progress: None,
+            wire_log: None,
+            wire_log_body_limit: wire_log::DEFAULT_BODY_LIMIT,
+            upload_chunk_size: upload::DEFAULT_CHUNK_SIZE,
+            upload_retries: 5,
+            body_transformers: Vec::new(),
+            alpn_hosts: HashMap::new(),
+            sni_hosts: HashMap::new(),
+            hash_body: None,
+            transcode_downloads: false,
+            rng: None,
+            default_query: Vec::new(),
+            base_url: None,
+            schemes: HashMap::new(),
+            redaction: Redaction::default(),
+            parse_mode: ParseMode::Standard,
+            strict: false,
+            normalize_reason: false,
+            resolver: Arc::new(SystemResolver),
+            connector: Arc::new(DefaultConnector),
+            clock: Arc::new(SystemClock),
+            ip_family: IpFamily::Any,
+            local_address: None,
+            auto_fallback: AutoFallback::Connect,
+            early_data: EarlyData::Never,
             proxy_type: ProxyType::None,
             proxy_host: String::new(),
             proxy_port: 0,
             proxy_user: String::new(),
             proxy_password: String::new(),
+            no_proxy: NoProxy::default(),
         }
 
     }
@@ -0,0 +1,103 @@
+use crate::crypto;
+use crate::error::Error;
+use rustls::client::ResolvesClientCert;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::sign::CertifiedKey;
+use rustls::SignatureScheme;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+/// Client certificate and private key presented to servers requiring mutual TLS
+#[derive(Clone, Debug)]
+pub struct ClientCert {
+    key: Arc<CertifiedKey>,
+}
+
+impl ClientCert {
+    /// Load from PEM encoded certificate chain and private key, which may be PKCS#8, PKCS#1 or SEC1
+    pub fn from_pem(cert_pem: &[u8], key_pem: &[u8]) -> Result<Self, Error> {
+        let certs = rustls_pemfile::certs(&mut &cert_pem[..])
+            .collect::<Result<Vec<CertificateDer<'static>>, _>>()
+            .map_err(|e| Error::Tls(format!("Invalid client certificate, {}", e)))?;
+
+        let key = rustls_pemfile::private_key(&mut &key_pem[..])
+            .map_err(|e| Error::Tls(format!("Invalid client private key, {}", e)))?
+            .ok_or(Error::Tls("No private key found within PEM".to_string()))?;
+
+        Self::new(certs, key)
+    }
+
+    /// Load from PEM encoded certificate chain and private key files.  Both may be the same file.
+    pub fn from_pem_files(cert_file: &str, key_file: &str) -> Result<Self, Error> {
+        Self::from_pem(&read_file(cert_file)?, &read_file(key_file)?)
+    }
+
+    /// Load from PKCS#12 / PFX archive containing the certificate chain and private key
+    #[cfg(feature = "pkcs12")]
+    pub fn from_pkcs12(der: &[u8], password: &str) -> Result<Self, Error> {
+        let store = p12_keystore::KeyStore::from_pkcs12(der, password)
+            .map_err(|e| Error::Tls(format!("Invalid PKCS#12 archive, {}", e)))?;
+        let (_alias, chain) = store.private_key_chain().ok_or(Error::Tls(
+            "No private key found within PKCS#12 archive".to_string(),
+        ))?;
+
+        let certs = chain
+            .chain()
+            .iter()
+            .map(|cert| CertificateDer::from(cert.as_der().to_vec()))
+            .collect();
+        let key = PrivateKeyDer::Pkcs8(chain.key().to_vec().into());
+
+        Self::new(certs, key)
+    }
+
+    /// Load from PKCS#12 / PFX file
+    #[cfg(feature = "pkcs12")]
+    pub fn from_pkcs12_file(filename: &str, password: &str) -> Result<Self, Error> {
+        Self::from_pkcs12(&read_file(filename)?, password)
+    }
+
+    /// Create from DER encoded certificate chain, leaf first, and private key
+    pub fn new(
+        certs: Vec<CertificateDer<'static>>,
+        key: PrivateKeyDer<'static>,
+    ) -> Result<Self, Error> {
+        if certs.is_empty() {
+            return Err(Error::Tls("No client certificate found".to_string()));
+        }
+
+        let signing_key = crypto::default_provider()
+            .key_provider
+            .load_private_key(key)
+            .map_err(|e| Error::Tls(format!("Unsupported client private key, {}", e)))?;
+
+        Ok(Self {
+            key: Arc::new(CertifiedKey::new(certs, signing_key)),
+        })
+    }
+}
+
+impl ResolvesClientCert for ClientCert {
+    fn resolve(
+        &self,
+        _root_hint_subjects: &[&[u8]],
+        sigschemes: &[SignatureScheme],
+    ) -> Option<Arc<CertifiedKey>> {
+        // Only offer the certificate if the server accepts its signature scheme
+        self.key.key.choose_scheme(sigschemes)?;
+        Some(Arc::clone(&self.key))
+    }
+
+    fn has_certs(&self) -> bool {
+        true
+    }
+}
+
+// Read contents of certificate or key file
+fn read_file(filename: &str) -> Result<Vec<u8>, Error> {
+    if !Path::new(filename).exists() {
+        return Err(Error::FileNotExists(filename.to_string()));
+    }
+    fs::read(filename).map_err(|e| Error::Tls(format!("Unable to read {}, {}", filename, e)))
+}
@@ -1,20 +1,46 @@
 #![allow(clippy::large_enum_variant)]
 
-use super::{HttpBody, HttpClientConfig, HttpRequest, HttpResponse, ProxyType};
-use crate::error::{Error, FileNotCreatedError, InvalidResponseError};
-use rustls::pki_types::ServerName;
-use std::fs::File;
-use std::io::{BufRead, BufReader, Read, Write};
-use std::net::{TcpStream, ToSocketAddrs};
-use std::path::Path;
+use super::{
+    CookieJar, DownloadCache, HttpBody, HttpClientConfig, HttpRequest, HttpResponse, HttpStream,
+};
+use crate::body;
+use crate::extract::{self, ArchiveKind};
+use crate::connection::{self, Connection, ConnectionPool};
+use crate::limiter::{HostLimiter, Limiter};
+use crate::pinned::PinnedConnection;
+use crate::rate_limit::RateLimiter;
+use crate::redirect_cache::{self, RedirectCache};
+use crate::request_builder::RequestBuilder;
+use crate::codec;
+use crate::conformance;
+use crate::credentials::AuthChallenge;
+use crate::error::{BatchError, Error, FileNotCreatedError};
+use crate::grpc_web::{self, GrpcWebResponse};
+use crate::hash::HashAlgorithm;
+use crate::metrics::RequestStats;
+use crate::response::{HeaderDecision, RedirectHop};
+use crate::s3::{ListObjects, S3Object, S3};
+use crate::stream::ResponseStream;
+use crate::timings::Timings;
+use crate::upgrade::Upgraded;
+use crate::upload::{self, UploadStatus};
+use crate::validators::Validators;
+use crate::wire_log;
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
 use std::sync::Arc;
-use std::time::Duration;
 use url::Url;
-use crate::socks5;
 
 #[derive(Debug, Clone)]
 pub struct HttpSyncClient {
     config: HttpClientConfig,
+    pool: ConnectionPool,
+    redirects: RedirectCache,
+    rate_limiter: RateLimiter,
+    limiter: Limiter,
+    host_limiter: HostLimiter,
 }
 
 
@@ -22,6 +48,12 @@ impl HttpSyncClient {
     pub fn new(config: &HttpClientConfig) -> Self {
         Self {
             config: config.clone(),
+            pool: ConnectionPool::default(),
+            redirects: RedirectCache::new(config.redirect_cache_size, config.redirect_cache_ttl)
+                .with_clock(Arc::clone(&config.clock)),
+            rate_limiter: RateLimiter::new(config),
+            limiter: Limiter::new(config.max_in_flight),
+            host_limiter: HostLimiter::new(config.max_in_flight_per_host),
         }
     }
 
@@ -30,36 +62,492 @@ impl HttpSyncClient {
         self.send_request(req, &String::new())
     }
 
+    /// Send requests one after another, returning their responses in order.  Every request is
+    /// sent even if earlier ones fail, and all failures are returned together within
+    /// Error::Batch, each with its index and request.
+    pub fn send_all(&mut self, reqs: &[HttpRequest]) -> Result<Vec<HttpResponse>, Error> {
+        let mut results = Vec::new();
+        for req in reqs {
+            let summary = format!("{} {}", req.method, self.config.redaction.url(&req.url));
+            results.push((summary, self.send_request(req, &String::new())));
+        }
+        BatchError::collect(results)
+    }
+
+    /// Send request and stream the body of its response into writer, leaving the body of the
+    /// returned response empty.  Retries as per the retry policy happen before any of the body
+    /// is written.
+    pub fn send_to<W: Write>(
+        &mut self,
+        req: &HttpRequest,
+        writer: &mut W,
+    ) -> Result<HttpResponse, Error> {
+        let started = self.config.clock.instant();
+        let (result, attempts) = self.send_to_retried(req, writer);
+        if let Some(on_request_complete) = self.config.on_request_complete {
+            let elapsed = self
+                .config
+                .clock
+                .instant()
+                .saturating_duration_since(started);
+            on_request_complete(&RequestStats::new(req, &result, attempts, elapsed));
+        }
+        result
+    }
+
+    /// Send request and return once the response header arrives, leaving the body to be read
+    /// incrementally from the returned stream, eg. server-sent events.  Works the same through
+    /// HTTP and SOCKS5 proxies.  Body transformers need the full body, so are not applied.
+    pub fn send_stream(&mut self, req: &HttpRequest) -> Result<ResponseStream, Error> {
+        let host_permit = self
+            .host_limiter
+            .acquire(&self.config.resolve_url(&req.url));
+        let permit = self.limiter.acquire();
+        let (res, conn) = self.open(req)?;
+        let reader: Box<dyn Read + Send> = if res.has_body() {
+            codec::stream_reader(conn, &res.headers())
+        } else {
+            Box::new(std::io::empty())
+        };
+        Ok(ResponseStream::new(res, reader).with_permit((host_permit, permit)))
+    }
+
+    /// Send request asking to switch to protocol, eg. "websocket", and return the connection
+    /// once the server responds with 101 Switching Protocols, to speak that protocol over it.
+    /// Any other response fails with Error::UnexpectedStatus.
+    pub fn upgrade(&mut self, req: &HttpRequest, protocol: &str) -> Result<Upgraded, Error> {
+        let req = Upgraded::request(req, protocol);
+        let host_permit = self
+            .host_limiter
+            .acquire(&self.config.resolve_url(&req.url));
+        let permit = self.limiter.acquire();
+        let (res, conn) = self.open(&req)?;
+        Ok(Upgraded::new(&req.url, protocol, res, conn.reader)?.with_permit((host_permit, permit)))
+    }
+
+    /// Open a new connection to the origin of the URL, outside of the pool, over which
+    /// requests are sent with PinnedConnection::send_on() so they all reach the same server
+    pub fn open_connection(&self, origin: &str) -> Result<PinnedConnection, Error> {
+        PinnedConnection::open(&self.config, origin)
+    }
+
+    /// Send requests over a stream established elsewhere to the origin of the URL, eg. from a
+    /// listener or custom tunnel, with the headers, cookies and parsing of this client
+    pub fn wrap_stream<S: HttpStream + 'static>(
+        &self,
+        origin: &str,
+        stream: S,
+    ) -> Result<PinnedConnection, Error> {
+        PinnedConnection::from_stream(&self.config, origin, stream)
+    }
+
+    /// Close idle pooled connections and save the cookie jars, so short-lived programs exit
+    /// without leaving sockets half open.  The client remains usable afterwards.
+    pub fn close(&mut self) -> Result<(), Error> {
+        self.pool.drain();
+        for jar in self.config.jars.values_mut() {
+            jar.save_jar()?;
+        }
+        self.config.cookie.save_jar()
+    }
+
+    /// Register a cookie jar under the given name, replacing any existing jar of that name,
+    /// selected per request via HttpRequest::use_jar()
+    pub fn add_jar(&mut self, name: &str, mut jar: CookieJar) {
+        jar.set_clock(Arc::clone(&self.config.clock));
+        self.config.jars.insert(name.to_string(), jar);
+    }
+
+    /// Remove cookie jar of the given name, returning it
+    pub fn remove_jar(&mut self, name: &str) -> Option<CookieJar> {
+        self.config.jars.remove(name)
+    }
+
+    /// Get cookie jar of the given name
+    pub fn jar(&self, name: &str) -> Option<&CookieJar> {
+        self.config.jars.get(name)
+    }
+
+    /// Validate and generate the exact bytes that would be sent for a request, without
+    /// opening a connection.  With seed(), this is the multipart boundary the next request is
+    /// sent with.  Uploaded files are read into the body, while bodies streamed from a reader
+    /// can only be sent once, so are left out.
+    pub fn dry_run(&self, req: &HttpRequest) -> Result<Vec<u8>, Error> {
+        // Draw from a copy of the seeded RNG, so the request is then sent with the same boundary
+        let mut config = self.config.clone();
+        let (_uri, _port, effective, mut message) = config.outgoing(req).prepare(&config)?;
+        if !effective.body.is_reader() {
+            effective
+                .body
+                .write_stream(&mut message)
+                .map_err(Error::Io)?;
+        }
+        Ok(message)
+    }
+
+    /// Format request as a curl command, exactly as it would be sent including client headers
+    /// and cookies, with secrets masked as per the client's redaction rules.
+    pub fn to_curl(&self, req: &HttpRequest) -> Result<String, Error> {
+        let mut config = self.config.clone();
+        let (_uri, _port, effective, _message) = config.outgoing(req).prepare(&config)?;
+        Ok(effective.to_curl_redacted(&config.redaction))
+    }
+
     /// Download a file
     pub fn download(&mut self, url: &str, dest_file: &str) -> Result<HttpResponse, Error> {
         let req = HttpRequest::new("GET", url, &vec![], &HttpBody::empty());
         self.send_request(&req, &dest_file.to_string())
     }
 
+    /// Download a file into writer instead of onto disk, eg. a hasher, socket or encrypting sink.
+    /// Text is transcoded to UTF-8 only if transcode_downloads() is set.
+    pub fn download_to<W: Write>(
+        &mut self,
+        url: &str,
+        writer: &mut W,
+    ) -> Result<HttpResponse, Error> {
+        let req = HttpRequest::new("GET", url, &vec![], &HttpBody::empty());
+        self.send_to(&req, writer)
+    }
+
+    /// Download each (url, dest_file) pair one after another, returning their responses in order.
+    /// Failed downloads do not stop the others, and are all returned within Error::Batch.
+    pub fn download_all(&mut self, downloads: &[(&str, &str)]) -> Result<Vec<HttpResponse>, Error> {
+        let mut results = Vec::new();
+        for (url, dest_file) in downloads {
+            let summary = format!("GET {} -> {}", self.config.redaction.url(url), dest_file);
+            results.push((summary, self.download(url, dest_file)));
+        }
+        BatchError::collect(results)
+    }
+
+    /// Download a file into a content-addressed cache, revalidating any previously downloaded
+    /// copy via ETag / Last-Modified.  Returns path to the cached file.
+    pub fn download_cached(
+        &mut self,
+        url: &str,
+        cache: &mut DownloadCache,
+    ) -> Result<PathBuf, Error> {
+        let headers = cache.conditional_headers(url);
+        let req = HttpRequest::new(
+            "GET",
+            url,
+            &headers.iter().map(|h| h.as_str()).collect(),
+            &HttpBody::empty(),
+        );
+
+        // Files are named by their SHA-256 hash
+        let tmp_file = cache.tmp_path();
+        let res = self.send_hashed(&req, &tmp_file, Some(HashAlgorithm::Sha256));
+
+        cache.complete(url, &res?, &tmp_file)
+    }
+
+    /// Download a file only if it has changed since it was last downloaded, as per the ETag,
+    /// Last-Modified and Content-Length recorded within a sidecar file next to it.  Uses a
+    /// conditional GET if an ETag or Last-Modified date was recorded, otherwise a HEAD request.
+    /// Returns None if the file is unchanged.
+    pub fn download_if_changed(
+        &mut self,
+        url: &str,
+        dest_file: &str,
+    ) -> Result<Option<HttpResponse>, Error> {
+        let validators = Validators::load(dest_file);
+
+        // Compare against HEAD if only the Content-Length is known
+        let mut headers = Vec::new();
+        match &validators {
+            Some(v) if v.is_conditional() => headers = v.conditional_headers(),
+            Some(v) => {
+                let head = self.head(url)?;
+                if (200..300).contains(&head.status_code()) && v.matches(&head) {
+                    return Ok(None);
+                }
+            }
+            None => {}
+        }
+
+        // Download into temporary file, only replacing the existing file once complete
+        let req = HttpRequest::new(
+            "GET",
+            url,
+            &headers.iter().map(|h| h.as_str()).collect(),
+            &HttpBody::empty(),
+        );
+        let tmp_file = format!("{}.part", dest_file);
+        let res = self.send_request(&req, &tmp_file);
+        let res = match res {
+            Ok(r) if (200..300).contains(&r.status_code()) => r,
+            Ok(r) => {
+                let _ = fs::remove_file(&tmp_file);
+                if r.status_code() == 304 {
+                    return Ok(None);
+                }
+                return Err(Error::UnexpectedStatus(r.status_code(), url.to_string()));
+            }
+            Err(e) => {
+                let _ = fs::remove_file(&tmp_file);
+                return Err(e);
+            }
+        };
+
+        if let Err(e) = fs::rename(&tmp_file, dest_file) {
+            return Err(Error::FileNotCreated(FileNotCreatedError {
+                filename: dest_file.to_string(),
+                error: e.to_string(),
+            }));
+        }
+        Validators::from_response(&res).save(dest_file)?;
+
+        Ok(Some(res))
+    }
+
+    /// Download a zip / tar / tar.gz archive, streaming it directly into a directory without
+    /// first writing the archive to disk.  Zip and tar support require the 'zip' and 'tar'
+    /// features.  Body transformers are not applied to the archive.
+    pub fn download_and_extract(
+        &mut self,
+        url: &str,
+        dest_dir: &str,
+    ) -> Result<HttpResponse, Error> {
+        let req = HttpRequest::new("GET", url, &vec![], &HttpBody::empty());
+        let _host_permit = self
+            .host_limiter
+            .acquire(&self.config.resolve_url(&req.url));
+        let _permit = self.limiter.acquire();
+        let (res, mut conn) = self.open(&req)?;
+        if !(200..300).contains(&res.status_code()) {
+            return Err(Error::UnexpectedStatus(res.status_code(), url.to_string()));
+        }
+
+        // Get archive type
+        let headers = res.headers();
+        let kind = match ArchiveKind::detect(url, &headers) {
+            Some(r) => r,
+            None => {
+                return Err(Error::Extract(format!(
+                    "Unable to determine archive type of {}",
+                    url
+                )));
+            }
+        };
+
+        // Extract
+        let mut body_reader = codec::body_reader(&mut conn, &headers, None, false);
+        extract::extract(&mut body_reader, kind, Path::new(dest_dir))?;
+
+        Ok(res)
+    }
+
     /// Send GET request
     pub fn get(&mut self, url: &str) -> Result<HttpResponse, Error> {
         let req = HttpRequest::new("GET", url, &Vec::new(), &HttpBody::empty());
         self.send_request(&req, &String::new())
     }
 
+    /// Send GET request to a host or URL over https, falling back to http if the https attempt
+    /// fails as per the auto_fallback setting.  Any scheme within the URL is ignored, and
+    /// HttpResponse::scheme() reports which one succeeded.
+    pub fn get_auto(&mut self, host_or_url: &str) -> Result<HttpResponse, Error> {
+        let target = match host_or_url.split_once("://") {
+            Some((_, rest)) => rest,
+            None => host_or_url,
+        };
+
+        let err = match self.get(&format!("https://{}", target)) {
+            Ok(res) => return Ok(res),
+            Err(e) => e,
+        };
+        if !self.config.auto_fallback.matches(&err) {
+            return Err(err);
+        }
+        self.get(&format!("http://{}", target))
+    }
+
     /// Send POST request
     pub fn post(&mut self, url: &str, body: &HttpBody) -> Result<HttpResponse, Error> {
         let req = HttpRequest::new("POST", url, &Vec::new(), body);
         self.send_request(&req, &String::new())
     }
 
+    /// Send POST request with additional headers, eg. vec!["Content-Type: application/json"]
+    pub fn post_with(
+        &mut self,
+        url: &str,
+        headers: &Vec<&str>,
+        body: &HttpBody,
+    ) -> Result<HttpResponse, Error> {
+        let req = HttpRequest::new("POST", url, headers, body);
+        self.send_request(&req, &String::new())
+    }
+
     /// Send PUT request
     pub fn put(&mut self, url: &str, data: &[u8]) -> Result<HttpResponse, Error> {
         let req = HttpRequest::new("PUT", url, &Vec::new(), &HttpBody::from_raw(data));
         self.send_request(&req, &String::new())
     }
 
+    /// Send PUT request, streaming the contents of a local file as the body
+    pub fn put_file(&mut self, url: &str, file_path: &str) -> Result<HttpResponse, Error> {
+        let req = HttpRequest::new("PUT", url, &Vec::new(), &HttpBody::from_file(file_path)?);
+        self.send_request(&req, &String::new())
+    }
+
+    /// Upload local file via the resumable upload protocol of Google Cloud Storage and similar
+    /// services.  A session is initiated with a POST to the URL, then the file is sent in chunks
+    /// with Content-Range to the session URL, retrying failed chunks from the offset the server
+    /// reports.  Returns the response to the final chunk.
+    pub fn upload_resumable(&mut self, url: &str, file_path: &str) -> Result<HttpResponse, Error> {
+        let mut fh =
+            File::open(file_path).map_err(|_| Error::FileNotExists(file_path.to_string()))?;
+        let total = fh.metadata().map_err(Error::Io)?.len();
+
+        // Initiate session
+        let req = upload::initiate_request(url, total, &HttpBody::guess_mime(file_path));
+        let res = self.send_request(&req, &String::new())?;
+        let session = upload::session_url(url, &res)?;
+
+        // Upload chunks, until the server reports the upload complete
+        let (mut offset, mut failures) = (0, 0);
+        loop {
+            let chunk = upload::read_chunk(&mut fh, offset, self.config.upload_chunk_size)?;
+            let req = upload::chunk_request(&session, &chunk, offset, total);
+            let mut result = self.send_request(&req, &String::new());
+
+            // Ask server how much it received if the chunk failed
+            if let Err(e) = &result {
+                failures += 1;
+                if failures > self.config.upload_retries || !upload::is_retryable(e) {
+                    return result;
+                }
+                std::thread::sleep(self.config.clock.wait(upload::retry_delay(failures)));
+                let req = upload::status_request(&session, total);
+                result = self.send_request(&req, &String::new());
+            }
+
+            let res = match result {
+                Ok(r) => r,
+                Err(_) => continue,
+            };
+            let received = match upload::status(&session, &res) {
+                Ok(UploadStatus::Complete) => {
+                    if let Some(callback) = self.config.on_upload_progress {
+                        callback(total, total);
+                    }
+                    return Ok(res);
+                }
+                Ok(UploadStatus::Incomplete(received)) => received,
+                Err(e) if upload::is_retryable(&e) && failures < self.config.upload_retries => {
+                    failures += 1;
+                    std::thread::sleep(self.config.clock.wait(upload::retry_delay(failures)));
+                    continue;
+                }
+                Err(e) => return Err(e),
+            };
+
+            // Chunks not accepted count as failures, so a stalled upload ends
+            if received > offset {
+                failures = 0;
+            } else if !chunk.is_empty() {
+                failures += 1;
+                if failures > self.config.upload_retries {
+                    return Err(Error::Custom(format!(
+                        "Resumable upload to {} made no progress",
+                        session
+                    )));
+                }
+            }
+            offset = received.min(total);
+            if let Some(callback) = self.config.on_upload_progress {
+                callback(offset, total);
+            }
+        }
+    }
+
+    /// Send unary gRPC-web call with a serialized protobuf message, and parse the framed response
+    pub fn grpc_web(&mut self, url: &str, message: &[u8]) -> Result<GrpcWebResponse, Error> {
+        let req = grpc_web::request(url, message);
+        let res = self.send_request(&req, &String::new())?;
+        GrpcWebResponse::from_response(&res)
+    }
+
+    /// Download object from an S3-compatible object store
+    pub fn s3_get_object(
+        &mut self,
+        s3: &S3,
+        bucket: &str,
+        key: &str,
+    ) -> Result<HttpResponse, Error> {
+        let req = s3.get_object_request(bucket, key)?;
+        S3::check(self.send_request(&req, &String::new())?)
+    }
+
+    /// Upload object to an S3-compatible object store, bodies from HttpBody::from_file() are
+    /// streamed from disk
+    pub fn s3_put_object(
+        &mut self,
+        s3: &S3,
+        bucket: &str,
+        key: &str,
+        body: &HttpBody,
+    ) -> Result<HttpResponse, Error> {
+        let req = s3.put_object_request(bucket, key, body)?;
+        S3::check(self.send_request(&req, &String::new())?)
+    }
+
+    /// List all objects within a bucket whose keys start with prefix, following continuation
+    /// tokens across pages
+    pub fn s3_list_objects(
+        &mut self,
+        s3: &S3,
+        bucket: &str,
+        prefix: &str,
+    ) -> Result<Vec<S3Object>, Error> {
+        let mut objects = Vec::new();
+        let mut token: Option<String> = None;
+        loop {
+            let req = s3.list_objects_request(bucket, prefix, token.as_deref())?;
+            let res = S3::check(self.send_request(&req, &String::new())?)?;
+            let page = ListObjects::from_response(&res)?;
+            objects.extend(page.objects);
+
+            token = match page.continuation_token {
+                Some(r) if page.is_truncated => Some(r),
+                _ => return Ok(objects),
+            };
+        }
+    }
+
+    /// Send PATCH request, eg. with a body from HttpBody::json_merge_patch()
+    pub fn patch(&mut self, url: &str, body: &HttpBody) -> Result<HttpResponse, Error> {
+        let req = HttpRequest::new("PATCH", url, &Vec::new(), body);
+        self.send_request(&req, &String::new())
+    }
+
+    /// Start request of any method, eg. TRACE or PROPFIND, with headers, query parameters, body
+    /// and timeouts set fluently before calling send()
+    pub fn request(&mut self, method: &str, url: &str) -> RequestBuilder<&mut Self> {
+        RequestBuilder::new(self, method, url)
+    }
+
     /// Send DELETE request
     pub fn delete(&mut self, url: &str) -> Result<HttpResponse, Error> {
         let req = HttpRequest::new("DELETE", url, &Vec::new(), &HttpBody::empty());
         self.send_request(&req, &String::new())
     }
 
+    /// Send DELETE request with additional headers and a body, as required by some REST APIs.
+    /// Pass HttpBody::empty() for no body.
+    pub fn delete_with(
+        &mut self,
+        url: &str,
+        headers: &Vec<&str>,
+        body: &HttpBody,
+    ) -> Result<HttpResponse, Error> {
+        let req = HttpRequest::new("DELETE", url, headers, body);
+        self.send_request(&req, &String::new())
+    }
+
     /// Send OPTIONS request
     pub fn options(&mut self, url: &str) -> Result<HttpResponse, Error> {
         let req = HttpRequest::new("OPTIONS", url, &Vec::new(), &HttpBody::empty());
@@ -72,35 +560,140 @@ impl HttpSyncClient {
         self.send_request(&req, &String::new())
     }
 
-    // Send request, used internally by the other methods.
+    // Send request, used internally by the other methods.  Retries as per the retry policy.
     fn send_request(
         &mut self,
         req: &HttpRequest,
         dest_file: &String,
     ) -> Result<HttpResponse, Error> {
-        // Prepare uri and http message
-        let (uri, port, message) = req.prepare(&self.config)?;
+        self.send_hashed(req, dest_file, self.config.hash_body)
+    }
+
+    // Send request as per send_request(), hashing the body with the given algorithm instead of
+    // that of the client
+    fn send_hashed(
+        &mut self,
+        req: &HttpRequest,
+        dest_file: &String,
+        hash_body: Option<HashAlgorithm>,
+    ) -> Result<HttpResponse, Error> {
+        let started = self.config.clock.instant();
+        let (result, attempts) = self.send_retried(req, dest_file, hash_body);
+        if let Some(on_request_complete) = self.config.on_request_complete {
+            let elapsed = self
+                .config
+                .clock
+                .instant()
+                .saturating_duration_since(started);
+            on_request_complete(&RequestStats::new(req, &result, attempts, elapsed));
+        }
+        result
+    }
+
+    // Send request as per the retry policy, returning the final result and number of attempts
+    fn send_retried(
+        &mut self,
+        req: &HttpRequest,
+        dest_file: &String,
+        hash_body: Option<HashAlgorithm>,
+    ) -> (Result<HttpResponse, Error>, usize) {
+        let policy = match &self.config.retry {
+            Some(r) if !req.body.is_reader() => r.clone(),
+            _ => return (self.send_attempt(req, dest_file, hash_body), 1),
+        };
 
-        // Connect
-        let mut reader = self.connect(&uri, &port, &message)?;
+        let mut attempt = 1;
+        loop {
+            let result = self.send_attempt(req, dest_file, hash_body);
+            match policy.delay(attempt, &result) {
+                Some(delay) => std::thread::sleep(self.config.clock.wait(delay)),
+                None => return (result, attempt),
+            }
+            attempt += 1;
+        }
+    }
 
-        // Read header
-        let mut res = HttpResponse::read_header(&mut reader, req, dest_file)?;
-        self.config.cookie.update_jar(&res.headers());
+    // Send request as per the retry policy until a response is kept, then write its body into
+    // writer, returning the final result and number of attempts
+    fn send_to_retried(
+        &mut self,
+        req: &HttpRequest,
+        writer: &mut dyn Write,
+    ) -> (Result<HttpResponse, Error>, usize) {
+        let _host_permit = self
+            .host_limiter
+            .acquire(&self.config.resolve_url(&req.url));
+        let _permit = self.limiter.acquire();
+        let policy = self.config.retry.clone().filter(|_| !req.body.is_reader());
+
+        let mut attempt = 1;
+        let (mut res, mut conn) = loop {
+            let (head, conn) = match self.open(req) {
+                Ok((res, conn)) => (Ok(res), Some(conn)),
+                Err(e) => (Err(e), None),
+            };
+            if let Some(delay) = policy
+                .as_ref()
+                .and_then(|policy| policy.delay(attempt, &head))
+            {
+                std::thread::sleep(self.config.clock.wait(delay));
+                attempt += 1;
+                continue;
+            }
+            match (head, conn) {
+                (Ok(res), Some(conn)) => break (res, conn),
+                (head, _) => return (head, attempt),
+            }
+        };
 
-        // Check follow location
-        if self.config.follow_location && res.headers().has_lower("location") {
-            let redirect_req = HttpRequest::new(
-                "GET",
-                res.headers().get_lower("location").unwrap().as_str(),
-                &vec![],
-                &HttpBody::empty(),
-            );
-            res = self.send_request(&redirect_req, dest_file)?;
+        // Write body
+        let written = res
+            .write_body(
+                &mut conn,
+                writer,
+                self.config.hash_body,
+                self.config.transcode_downloads,
+                &self.config.body_transformers,
+            )
+            .and_then(|_| writer.flush().map_err(|e| Error::NoWrite(e.to_string())));
+        if let Err(e) = written {
+            return (Err(e.with_timings(conn.started, &conn.timings)), attempt);
         }
+        res.set_transfer(conn.sent, conn.received);
+        res.set_timings(Timings {
+            total: conn.started.elapsed(),
+            ..conn.timings
+        });
+        self.pool.release(conn, &res);
+
+        (Ok(res), attempt)
+    }
+
+    // Send a single attempt of a request
+    fn send_attempt(
+        &mut self,
+        req: &HttpRequest,
+        dest_file: &String,
+        hash_body: Option<HashAlgorithm>,
+    ) -> Result<HttpResponse, Error> {
+        let _host_permit = self
+            .host_limiter
+            .acquire(&self.config.resolve_url(&req.url));
+        let _permit = self.limiter.acquire();
+        let (mut res, mut conn) = self.open(req)?;
 
         // Return if not downloading a file
         if dest_file.is_empty() {
+            res.read_body_hashed(&mut conn, hash_body)
+                .map_err(|e| e.with_timings(conn.started, &conn.timings))?;
+            wire_log::response_body(&self.config, &res);
+            res.set_transfer(conn.sent, conn.received);
+            res.set_timings(Timings {
+                total: conn.started.elapsed(),
+                ..conn.timings
+            });
+            self.pool.release(conn, &res);
+            res.transform_body(&self.config.body_transformers);
             return Ok(res);
         }
 
@@ -115,76 +708,277 @@ impl HttpSyncClient {
                 }));
             }
         };
+        res.write_body(
+            &mut conn,
+            &mut fh,
+            hash_body,
+            self.config.transcode_downloads,
+            &self.config.body_transformers,
+        )
+        .map_err(|e| e.with_timings(conn.started, &conn.timings))?;
+        res.set_transfer(conn.sent, conn.received);
+        res.set_timings(Timings {
+            total: conn.started.elapsed(),
+            ..conn.timings
+        });
+        self.pool.release(conn, &res);
 
-        // Save file
-        let mut buffer = [0u8; 2048];
+        Ok(res)
+    }
+
+    // Send request and read the response header, following redirects if enabled.  The body is
+    // left unread within the returned connection.
+    fn open(&mut self, req: &HttpRequest) -> Result<(HttpResponse, Connection), Error> {
+        let mut req = self.config.outgoing(req);
+        let mut redirects = 0;
+        let mut hops: Vec<(String, String)> = Vec::new();
+        let mut chain: Vec<RedirectHop> = Vec::new();
+        let (mut bytes_sent, mut bytes_received) = (0, 0);
+        let mut too_early = false;
+        let mut authenticated = false;
+        let started = Instant::now();
+        let deadline = req.timeouts.total.map(|total| started + total);
         loop {
-            let bytes_read = match reader.read(&mut buffer) {
-                Ok(r) => r,
-                Err(e) => {
-                    return Err(Error::NoRead(InvalidResponseError {
-                        url: req.url.clone(),
-                        response: e.to_string(),
-                    }));
+            // Skip known permanent redirects
+            if let Some(target) = self.redirects.get(&req.url) {
+                req.url = target;
+            }
+
+            // Prepare uri and http message
+            let (uri, port, effective, message) = req.prepare(&self.config)?;
+            if let Some(jar) = self.config.jar_mut(req.jar.as_deref()) {
+                jar.touch(&uri);
+            }
+            if self.config.strict {
+                let violations = conformance::check_request(&effective);
+                if !violations.is_empty() {
+                    return Err(Error::Nonconformant(req.url.clone(), violations));
                 }
-            };
+            }
+            if effective.body.is_async() {
+                return Err(Error::Custom(body::ASYNC_ONLY.to_string()));
+            }
 
-            if bytes_read == 0 {
-                break;
+            // Wait for a token of the rate limits, if any
+            let delay = self
+                .rate_limiter
+                .reserve(uri.host_str().unwrap_or(""), self.config.clock.instant());
+            if !delay.is_zero() {
+                std::thread::sleep(self.config.clock.wait(delay));
             }
-            fh.write_all(&buffer).unwrap();
-        }
 
-        Ok(res)
-    }
+            // Connect and send request
+            let mut conn = self.pool.checkout(
+                &self.config,
+                &uri,
+                &port,
+                req.sni.as_deref(),
+                &req.timeouts,
+                started,
+                deadline,
+            )?;
 
-    // Connect to remote server
-    pub fn connect(&self, uri: &Url, port: &u16, message: &Vec<u8>) -> Result<Box<dyn BufRead>, Error> {
-        // Prepare uri
-        let hostname =
-            if self.config.proxy_type != ProxyType::None && !self.config.proxy_host.is_empty() {
-                format!("{}:{}", self.config.proxy_host, self.config.proxy_port)
+            // Send as TLS 1.3 early data if allowed, which completes the handshake of a resumed
+            // session, otherwise as usual
+            let handshake_timeout = req
+                .timeouts
+                .connect
+                .unwrap_or(Duration::from_secs(self.config.timeout));
+            let sent_early = connection::write_early(
+                &mut conn,
+                &message,
+                !too_early && self.config.early_data.allows(&effective),
+                handshake_timeout,
+                &req.url,
+            )?;
+            let expect_continue = if effective.headers.has_lower("expect") {
+                self.config.expect_continue.map(Duration::from_millis)
             } else {
-                format!("{}:{}", &uri.host_str().unwrap(), port)
+                None
+            };
+            let mut sent = match sent_early {
+                true => Ok(()),
+                false => connection::write_request(
+                    &mut conn,
+                    &message,
+                    &effective.body,
+                    expect_continue,
+                    &req.url,
+                ),
             };
-        let mut address = hostname.to_socket_addrs().unwrap();
-        let addr = address.next().unwrap();
+            let alpn_protocol = conn.reader.get_ref().alpn_protocol();
+            let tls_info = conn.reader.get_ref().tls_info();
+            if sent.is_ok() {
+                wire_log::request(&self.config, &message);
+            }
 
-        // Open tcp stream
-        let mut sock =
-            match TcpStream::connect_timeout(&addr, Duration::from_secs(self.config.timeout)) {
+            // Wait for first byte of response
+            if let (Ok(()), Some(ttfb)) = (&sent, self.config.ttfb_timeout) {
+                let timeout = Duration::from_secs(ttfb);
+                sent = connection::wait_first_byte(&mut conn, timeout, &req.url);
+            }
+
+            // Read header, retrying on a new connection if the server closed an idle pooled one.
+            // Bodies streamed from a reader can not be sent twice, and other requests are only
+            // sent again if idempotent or not written at all, as the server may have acted on them.
+            let head = sent.and_then(|_| {
+                HttpResponse::read_head_mode(&mut conn, &req, self.config.parse_mode)
+            });
+            let mut res = match head {
                 Ok(r) => r,
-                Err(_e) => {
-                    return Err(Error::NoConnect(hostname.clone()));
+                Err(Error::TtfbTimeout(url)) => return Err(Error::TtfbTimeout(url)),
+                Err(e @ Error::Timeout(_)) => return Err(e.with_timings(started, &conn.timings)),
+                Err(_)
+                    if conn.reused
+                        && !effective.body.is_reader()
+                        && (effective.is_idempotent() || conn.sent == 0) =>
+                {
+                    continue
                 }
+                Err(e) => return Err(e),
             };
-        sock.set_nodelay(true).unwrap();
+            wire_log::response_head(&self.config, &res);
 
-        // SOCKs5 connection, if needed
-        if self.config.proxy_type == ProxyType::SOCKS5 {
-            socks5::connect(&mut sock, &self.config, uri, port);
-        }
+            // Replay request the server deems too early once the handshake completed, RFC 8470
+            if sent_early && res.status_code() == 425 {
+                too_early = true;
+                bytes_sent += conn.sent;
+                bytes_received += conn.received;
+                continue;
+            }
 
-        // Connect over SSL, if needed
-        if uri.scheme() == "https" && self.config.proxy_type != ProxyType::HTTP {
-            let dns_name = ServerName::try_from(uri.host_str().unwrap())
-                .unwrap()
-                .to_owned();
-            let conn = rustls::ClientConnection::new(Arc::clone(&self.config.tls_config), dns_name)
-                .unwrap();
+            // Retry once without Expect if the server refuses the expectation, RFC 9110 section
+            // 10.1.1.  Bodies streamed from a reader are only resent if they were held back.
+            if res.status_code() == 417
+                && req.expect_continue
+                && effective.headers.has_lower("expect")
+                && (conn.must_close || !effective.body.is_reader())
+            {
+                req = req.without_expect_continue();
+                bytes_sent += conn.sent;
+                bytes_received += conn.received;
+                continue;
+            }
+            if self.config.strict {
+                let violations = conformance::check_response(&res);
+                if !violations.is_empty() {
+                    return Err(Error::Nonconformant(req.url.clone(), violations));
+                }
+            }
+            if self.config.normalize_reason {
+                res.normalize_reason();
+            }
+            res.set_effective_request(&effective);
+            res.set_alpn_protocol(alpn_protocol);
+            res.set_tls_info(tls_info);
 
-            let mut tls_stream = rustls::StreamOwned::new(conn, sock);
-            tls_stream.flush().unwrap();
-            tls_stream.write_all(message).unwrap();
+            // Allow callback to abort before body is read
+            if let Some(on_headers) = self.config.on_headers {
+                if on_headers(&res) == HeaderDecision::Abort {
+                    return Err(Error::Aborted(req.url.clone()));
+                }
+            }
+            if let Some(jar) = self.config.jar_mut(req.jar.as_deref()) {
+                match req.save_cookies {
+                    true => jar.update_jar(&res.headers()),
+                    false => jar.update_jar_unsaved(&res.headers()),
+                }
+            }
 
-            let reader = BufReader::with_capacity(2048, tls_stream);
-            return Ok(Box::new(reader));
-        }
+            // Retry once with credentials of the provider if unauthorized
+            if res.status_code() == 401 && !authenticated && !effective.body.is_reader() {
+                let challenge = AuthChallenge::from_response(&req.url, &res);
+                let credentials = self
+                    .config
+                    .credentials
+                    .as_ref()
+                    .and_then(|provider| provider.credentials(&challenge));
+                if let Some(credentials) = credentials {
+                    authenticated = true;
+                    req = req.with_header("Authorization", &credentials.header_value());
+                    bytes_sent += conn.sent;
+                    bytes_received += conn.received;
+                    continue;
+                }
+            }
+
+            // Check follow location
+            if self.config.follow_location && res.headers().has_lower("location") {
+                let location = res.headers().get_lower("location").unwrap();
+                let location = redirect_cache::resolve(&req.url, &location);
 
-        // Get reader
-        sock.write_all(message).unwrap();
-        let reader = BufReader::with_capacity(2048, sock);
+                // Detect redirect loops, before the limit so they are reported as such
+                if let Some(cycle) = redirect_cache::find_cycle(&hops, &req.url, &location) {
+                    return Err(Error::RedirectLoop(cycle));
+                }
+                hops.push((req.url.clone(), location.clone()));
+                chain.push(RedirectHop {
+                    url: req.url.clone(),
+                    status: res.status_code(),
+                    location: location.clone(),
+                    set_cookie: res.headers().get_lower_vec("set-cookie"),
+                });
+
+                redirects += 1;
+                if self.config.max_redirects.is_some_and(|max| redirects > max) {
+                    return Err(Error::TooManyRedirects(req.url.clone()));
+                }
+                if res.status_code() == 301 || res.status_code() == 308 {
+                    self.redirects.insert(&req.url, &location);
+                }
+                let (timeouts, save_cookies) = (req.timeouts, req.save_cookies);
+                let (sni, jar) = (req.sni.clone(), req.jar.clone());
+                let mut omit_query = req.omit_query.clone();
+                let target = Url::parse(&location).ok();
+                let same_host = target
+                    .as_ref()
+                    .is_some_and(|target| target.host_str() == uri.host_str());
+                let same_origin = target.is_some_and(|target| target.origin() == uri.origin());
 
-        Ok(Box::new(reader))
+                // Credentials of the provider are only sent on to the same origin
+                let authorization = match authenticated && same_origin {
+                    true => req.headers.get_lower("authorization"),
+                    false => None,
+                };
+                authenticated = false;
+                if !same_host {
+                    // Default query parameters, such as API keys, are not sent to other hosts
+                    omit_query.extend(self.config.default_query.iter().map(|(key, _)| key.clone()));
+                }
+                req = HttpRequest::new("GET", location.as_str(), &vec![], &HttpBody::empty());
+                req.timeouts = timeouts;
+                req.save_cookies = save_cookies;
+                req.jar = jar;
+                req.omit_query = omit_query;
+                if same_host {
+                    req.sni = sni;
+                }
+                if let Some(authorization) = authorization {
+                    req = req.with_header("Authorization", &authorization);
+                }
+                bytes_sent += conn.sent;
+                bytes_received += conn.received;
+                continue;
+            }
+
+            // Bytes of the redirects count towards the final response
+            res.set_redirects(chain);
+            conn.sent += bytes_sent;
+            conn.received += bytes_received;
+            return Ok((res, conn));
+        }
+    }
+
+    // Connect to remote server
+    pub fn connect(&self, uri: &Url, port: &u16) -> Result<Box<dyn HttpStream>, Error> {
+        let timeout = Duration::from_secs(self.config.timeout);
+        self.config.connector.connect(
+            &self.config,
+            uri,
+            *port,
+            None,
+            timeout,
+            &mut Timings::default(),
+        )
     }
 }
@@ -0,0 +1,112 @@
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Source of time for cookie expiry, the freshness of cached redirects and DNS records, delays
+/// between retries and the durations within RequestStats.  Set via HttpClientBuilder::clock(),
+/// eg. a MockClock to test time-dependent behavior without sleeping.
+pub trait Clock: fmt::Debug + Send + Sync {
+    /// Current wall clock time
+    fn now(&self) -> SystemTime;
+
+    /// Current monotonic time
+    fn instant(&self) -> Instant;
+
+    /// Called before waiting out a delay, such as the backoff between retries, returning how
+    /// long to actually wait.  Defaults to the full delay.
+    fn wait(&self, delay: Duration) -> Duration {
+        delay
+    }
+
+    /// Current wall clock time in seconds since the epoch
+    fn unix_secs(&self) -> u64 {
+        self.now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0)
+    }
+}
+
+impl<C: Clock + ?Sized> Clock for Arc<C> {
+    fn now(&self) -> SystemTime {
+        (**self).now()
+    }
+
+    fn instant(&self) -> Instant {
+        (**self).instant()
+    }
+
+    fn wait(&self, delay: Duration) -> Duration {
+        (**self).wait(delay)
+    }
+}
+
+/// Clock of the operating system
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+
+    fn instant(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// Clock which only moves when advanced, including by the delays it is asked to wait, which
+/// then return immediately.  Clones share the same time.
+#[derive(Debug, Clone)]
+pub struct MockClock {
+    start: SystemTime,
+    base: Instant,
+    offset: Arc<Mutex<Duration>>,
+}
+
+impl Default for MockClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MockClock {
+    /// Create clock starting at the current time
+    pub fn new() -> Self {
+        Self::at(SystemTime::now())
+    }
+
+    /// Create clock starting at the given wall clock time
+    pub fn at(start: SystemTime) -> Self {
+        Self {
+            start,
+            base: Instant::now(),
+            offset: Arc::new(Mutex::new(Duration::ZERO)),
+        }
+    }
+
+    /// Move the clock forward
+    pub fn advance(&self, duration: Duration) {
+        *self.offset.lock().unwrap() += duration;
+    }
+
+    /// Time the clock was advanced by since it was created
+    pub fn elapsed(&self) -> Duration {
+        *self.offset.lock().unwrap()
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> SystemTime {
+        self.start + self.elapsed()
+    }
+
+    fn instant(&self) -> Instant {
+        self.base + self.elapsed()
+    }
+
+    fn wait(&self, delay: Duration) -> Duration {
+        self.advance(delay);
+        Duration::ZERO
+    }
+}
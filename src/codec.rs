@@ -0,0 +1,501 @@
+use super::HttpHeaders;
+use crate::hash::{HashAlgorithm, HashingReader};
+use flate2::read::{GzDecoder, ZlibDecoder};
+use std::io::{BufRead, Read, Write};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{ready, Context, Poll};
+use tokio::io::{AsyncBufRead, AsyncRead, ReadBuf};
+
+/// Decodes a body sent with Transfer-Encoding: chunked, stopping at the last chunk.
+pub struct ChunkedDecoder<R: BufRead> {
+    inner: R,
+    remaining: usize,
+    done: bool,
+}
+
+impl<R: BufRead> ChunkedDecoder<R> {
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            remaining: 0,
+            done: false,
+        }
+    }
+
+    /// Read size line of next chunk, and trailers after the last chunk
+    fn next_chunk(&mut self) -> std::io::Result<()> {
+        let mut line = String::new();
+        self.inner.read_line(&mut line)?;
+        let size = line.trim().split(';').next().unwrap_or("").trim();
+        self.remaining = usize::from_str_radix(size, 16).map_err(|_| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("Invalid chunk size line: {}", line.trim()),
+            )
+        })?;
+
+        if self.remaining == 0 {
+            self.done = true;
+            loop {
+                let mut trailer = String::new();
+                if self.inner.read_line(&mut trailer)? == 0 || trailer.trim().is_empty() {
+                    break;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<R: BufRead> Read for ChunkedDecoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.done || buf.is_empty() {
+            return Ok(0);
+        }
+
+        if self.remaining == 0 {
+            self.next_chunk()?;
+            if self.done {
+                return Ok(0);
+            }
+        }
+
+        let max = buf.len().min(self.remaining);
+        let bytes_read = self.inner.read(&mut buf[..max])?;
+        if bytes_read == 0 {
+            return Err(std::io::ErrorKind::UnexpectedEof.into());
+        }
+        self.remaining -= bytes_read;
+
+        // Consume CRLF at end of chunk
+        if self.remaining == 0 {
+            let mut crlf = String::new();
+            self.inner.read_line(&mut crlf)?;
+        }
+
+        Ok(bytes_read)
+    }
+}
+
+/// Reader over the body of a response, handling chunked / Content-Length framing.
+pub enum FramedReader<R: BufRead> {
+    Chunked(ChunkedDecoder<R>),
+    Length(std::io::Take<R>),
+    Close(R),
+}
+
+impl<R: BufRead> Read for FramedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self {
+            Self::Chunked(reader) => reader.read(buf),
+            Self::Length(reader) => reader.read(buf),
+            Self::Close(reader) => reader.read(buf),
+        }
+    }
+}
+
+/// Get reader over the body of a response, handling chunked / Content-Length framing.
+pub fn framed_reader<R: BufRead>(reader: R, headers: &HttpHeaders) -> FramedReader<R> {
+    let is_chunked = headers
+        .get_lower_line("transfer-encoding")
+        .map(|te| te.to_lowercase().contains("chunked"))
+        .unwrap_or(false);
+
+    if is_chunked {
+        return FramedReader::Chunked(ChunkedDecoder::new(reader));
+    } else if let Some(length) = headers
+        .get_lower("content-length")
+        .and_then(|len| len.trim().parse::<u64>().ok())
+    {
+        return FramedReader::Length(reader.take(length));
+    }
+
+    FramedReader::Close(reader)
+}
+
+/// Reader over the body of a response as handed out by the clients: framing removed,
+/// decompressed according to the Content-Encoding header, hashed if an algorithm is given, and
+/// converted to UTF-8 from the charset declared for a text body if charset is set.
+pub struct BodyReader<R: BufRead> {
+    inner: CharsetReader<HashingReader<Decompressor<FramedReader<R>>>>,
+}
+
+impl<R: BufRead> BodyReader<R> {
+    /// Hex digest of the decompressed body read so far, None if not hashing
+    pub fn digest(&self) -> Option<String> {
+        self.inner.inner.digest()
+    }
+}
+
+impl<R: BufRead> Read for BodyReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.inner.read(buf)
+    }
+}
+
+/// Get reader over the body of a response, as per BodyReader.  Used for every response body, so
+/// that all are decoded the same way.
+pub fn body_reader<R: BufRead>(
+    reader: R,
+    headers: &HttpHeaders,
+    algorithm: Option<HashAlgorithm>,
+    charset: bool,
+) -> BodyReader<R> {
+    let encoding = headers.get_lower("content-encoding").unwrap_or_default();
+    let decompressed = decompressor(&encoding, framed_reader(reader, headers));
+    let hashed = HashingReader::new(decompressed, algorithm);
+    BodyReader {
+        inner: CharsetReader::new(hashed, headers, charset),
+    }
+}
+
+/// Owned reader over the decoded body of a response, as per body_reader(), for streaming the
+/// body incrementally.  Reads return as soon as any data is available.
+pub fn stream_reader<'a, R: BufRead + Send + 'a>(
+    reader: R,
+    headers: &HttpHeaders,
+) -> Box<dyn Read + Send + 'a> {
+    Box::new(body_reader(reader, headers, None, true))
+}
+
+/// Wrap body reader with decompressor according to the Content-Encoding header.  Unknown
+/// encodings are passed through as is, as is br unless the 'brotli' feature is enabled.
+pub fn decoded_reader<'a, R: Read + 'a>(reader: R, headers: &HttpHeaders) -> Box<dyn Read + 'a> {
+    let encoding = headers.get_lower("content-encoding").unwrap_or_default();
+    Box::new(decompressor(&encoding, reader))
+}
+
+/// Reader decompressing the body according to its Content-Encoding
+pub enum Decompressor<R: Read> {
+    Gzip(GzDecoder<R>),
+    Deflate(ZlibDecoder<R>),
+    #[cfg(feature = "brotli")]
+    Brotli(brotli_decompressor::Decompressor<R>),
+    Identity(R),
+}
+
+impl<R: Read> Read for Decompressor<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self {
+            Self::Gzip(reader) => reader.read(buf),
+            Self::Deflate(reader) => reader.read(buf),
+            #[cfg(feature = "brotli")]
+            Self::Brotli(reader) => reader.read(buf),
+            Self::Identity(reader) => reader.read(buf),
+        }
+    }
+}
+
+/// Wrap reader with decompressor for the given Content-Encoding value, such as "gzip".  Unknown
+/// encodings are passed through as is.
+pub fn decompressor<R: Read>(encoding: &str, reader: R) -> Decompressor<R> {
+    match encoding.trim().to_lowercase().as_str() {
+        "gzip" | "x-gzip" => Decompressor::Gzip(GzDecoder::new(reader)),
+        "deflate" => Decompressor::Deflate(ZlibDecoder::new(reader)),
+        #[cfg(feature = "brotli")]
+        "br" => Decompressor::Brotli(brotli_decompressor::Decompressor::new(reader, 4096)),
+        _ => Decompressor::Identity(reader),
+    }
+}
+
+/// Reader transcoding text from the charset declared within the Content-Type header to UTF-8 as
+/// it is read.  Passes bytes through as is when disabled, for non-text bodies, and when no
+/// charset, UTF-8 or an unknown charset is declared.
+pub struct CharsetReader<R: Read> {
+    inner: R,
+    decoder: Option<encoding_rs::Decoder>,
+    output: Vec<u8>,
+    pos: usize,
+    done: bool,
+}
+
+impl<R: Read> CharsetReader<R> {
+    pub fn new(inner: R, headers: &HttpHeaders, enabled: bool) -> Self {
+        let decoder = match enabled {
+            true => charset_decoder(headers),
+            false => None,
+        };
+        Self {
+            inner,
+            decoder,
+            output: Vec::new(),
+            pos: 0,
+            done: false,
+        }
+    }
+
+    /// Whether or not the body is being transcoded
+    pub fn is_transcoding(&self) -> bool {
+        self.decoder.is_some()
+    }
+}
+
+impl<R: Read> Read for CharsetReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let decoder = match self.decoder.as_mut() {
+            Some(r) => r,
+            None => return self.inner.read(buf),
+        };
+
+        // Decode next chunk once the previous one is handed out, flushing any partial
+        // character at the end of the body
+        while self.pos == self.output.len() && !self.done {
+            let mut chunk = [0u8; 8192];
+            let bytes_read = self.inner.read(&mut chunk)?;
+            self.done = bytes_read == 0;
+            self.output = decode(decoder, &chunk[..bytes_read], self.done).into_bytes();
+            self.pos = 0;
+        }
+
+        let len = buf.len().min(self.output.len() - self.pos);
+        buf[..len].copy_from_slice(&self.output[self.pos..self.pos + len]);
+        self.pos += len;
+        Ok(len)
+    }
+}
+
+// Decoder to UTF-8 from the charset declared for a text body, if any other than UTF-8
+fn charset_decoder(headers: &HttpHeaders) -> Option<encoding_rs::Decoder> {
+    text_charset(headers)
+        .and_then(|charset| encoding_rs::Encoding::for_label(charset.as_bytes()))
+        .filter(|encoding| *encoding != encoding_rs::UTF_8)
+        .map(|encoding| encoding.new_decoder_without_bom_handling())
+}
+
+// Decode bytes to UTF-8, keeping any partial character at the end within the decoder unless last
+fn decode(decoder: &mut encoding_rs::Decoder, buf: &[u8], last: bool) -> String {
+    let mut output = String::with_capacity(
+        decoder
+            .max_utf8_buffer_length(buf.len())
+            .unwrap_or(buf.len() * 3 + 16),
+    );
+    let (_result, _read, _replaced) = decoder.decode_to_string(buf, &mut output, last);
+    output
+}
+
+// Charset declared for a text body, if any
+fn text_charset(headers: &HttpHeaders) -> Option<String> {
+    let content_type = headers.get_lower_line("content-type")?.to_lowercase();
+    let mime = content_type
+        .split(';')
+        .next()
+        .unwrap_or("")
+        .trim()
+        .to_string();
+    let is_text = mime.starts_with("text/")
+        || ["xml", "json", "javascript", "csv"]
+            .iter()
+            .any(|kind| mime.contains(kind));
+    if !is_text {
+        return None;
+    }
+
+    content_type
+        .split(';')
+        .filter_map(|part| part.trim().strip_prefix("charset="))
+        .next()
+        .map(|charset| charset.trim_matches('"').to_string())
+}
+
+#[derive(Debug)]
+enum ChunkState {
+    Size,
+    Data(usize),
+    DataEnd,
+    Trailers,
+    Done,
+}
+
+/// Async counterpart of ChunkedDecoder, decoding a chunked body from an AsyncBufRead.
+pub struct AsyncChunkedDecoder<R: AsyncBufRead + Unpin> {
+    inner: R,
+    state: ChunkState,
+    line: Vec<u8>,
+}
+
+impl<R: AsyncBufRead + Unpin> AsyncChunkedDecoder<R> {
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            state: ChunkState::Size,
+            line: Vec::new(),
+        }
+    }
+
+    // Read next line into self.line, which is cleared by the caller once processed
+    fn poll_line(&mut self, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        loop {
+            let buf = ready!(Pin::new(&mut self.inner).poll_fill_buf(cx))?;
+            if buf.is_empty() {
+                return Poll::Ready(Err(std::io::ErrorKind::UnexpectedEof.into()));
+            }
+            match buf.iter().position(|b| *b == b'\n') {
+                Some(pos) => {
+                    self.line.extend_from_slice(&buf[..=pos]);
+                    Pin::new(&mut self.inner).consume(pos + 1);
+                    return Poll::Ready(Ok(()));
+                }
+                None => {
+                    let len = buf.len();
+                    self.line.extend_from_slice(buf);
+                    Pin::new(&mut self.inner).consume(len);
+                }
+            }
+        }
+    }
+}
+
+impl<R: AsyncBufRead + Unpin> AsyncRead for AsyncChunkedDecoder<R> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        let this = self.get_mut();
+        loop {
+            match this.state {
+                ChunkState::Size => {
+                    ready!(this.poll_line(cx))?;
+                    let line = String::from_utf8_lossy(&this.line).to_string();
+                    this.line.clear();
+                    let size = line.trim().split(';').next().unwrap_or("").trim();
+                    let size = usize::from_str_radix(size, 16).map_err(|_| {
+                        std::io::Error::new(
+                            std::io::ErrorKind::InvalidData,
+                            format!("Invalid chunk size line: {}", line.trim()),
+                        )
+                    })?;
+                    this.state = if size == 0 {
+                        ChunkState::Trailers
+                    } else {
+                        ChunkState::Data(size)
+                    };
+                }
+                ChunkState::Data(remaining) => {
+                    if buf.remaining() == 0 {
+                        return Poll::Ready(Ok(()));
+                    }
+                    let data = ready!(Pin::new(&mut this.inner).poll_fill_buf(cx))?;
+                    if data.is_empty() {
+                        return Poll::Ready(Err(std::io::ErrorKind::UnexpectedEof.into()));
+                    }
+                    let len = data.len().min(remaining).min(buf.remaining());
+                    buf.put_slice(&data[..len]);
+                    Pin::new(&mut this.inner).consume(len);
+                    this.state = if len == remaining {
+                        ChunkState::DataEnd
+                    } else {
+                        ChunkState::Data(remaining - len)
+                    };
+                    return Poll::Ready(Ok(()));
+                }
+                ChunkState::DataEnd => {
+                    // Consume CRLF at end of chunk
+                    ready!(this.poll_line(cx))?;
+                    this.line.clear();
+                    this.state = ChunkState::Size;
+                }
+                ChunkState::Trailers => {
+                    ready!(this.poll_line(cx))?;
+                    if this.line.iter().all(|b| b.is_ascii_whitespace()) {
+                        this.state = ChunkState::Done;
+                    }
+                    this.line.clear();
+                }
+                ChunkState::Done => return Poll::Ready(Ok(())),
+            }
+        }
+    }
+}
+
+// Output of a push based decompressor
+#[derive(Clone, Default)]
+struct Sink(Arc<Mutex<Vec<u8>>>);
+
+impl Write for Sink {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if let Ok(mut output) = self.0.lock() {
+            output.extend_from_slice(buf);
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Async counterpart of decompressor(), decompressing an AsyncRead according to the given
+/// Content-Encoding value.  Unknown encodings are passed through as is.
+pub struct AsyncDecoder<R: AsyncRead + Unpin> {
+    inner: R,
+    decoder: Option<Box<dyn Write + Send>>,
+    output: Sink,
+    done: bool,
+}
+
+impl<R: AsyncRead + Unpin> AsyncDecoder<R> {
+    pub fn new(encoding: &str, inner: R) -> Self {
+        let output = Sink::default();
+        let decoder: Option<Box<dyn Write + Send>> = match encoding.trim().to_lowercase().as_str() {
+            "gzip" | "x-gzip" => Some(Box::new(flate2::write::GzDecoder::new(output.clone()))),
+            "deflate" => Some(Box::new(flate2::write::ZlibDecoder::new(output.clone()))),
+            #[cfg(feature = "brotli")]
+            "br" => Some(Box::new(brotli_decompressor::DecompressorWriter::new(
+                output.clone(),
+                4096,
+            ))),
+            _ => None,
+        };
+
+        Self {
+            inner,
+            decoder,
+            output,
+            done: false,
+        }
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for AsyncDecoder<R> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        let this = self.get_mut();
+        if this.decoder.is_none() && !this.done {
+            return Pin::new(&mut this.inner).poll_read(cx, buf);
+        }
+
+        loop {
+            // Hand out decompressed data
+            if let Ok(mut output) = this.output.0.lock() {
+                if !output.is_empty() || this.done {
+                    let len = output.len().min(buf.remaining());
+                    buf.put_slice(&output[..len]);
+                    output.drain(..len);
+                    return Poll::Ready(Ok(()));
+                }
+            }
+
+            // Feed compressed data, finishing the stream once the reader is exhausted
+            let mut chunk = [0u8; 8192];
+            let mut chunk_buf = ReadBuf::new(&mut chunk);
+            ready!(Pin::new(&mut this.inner).poll_read(cx, &mut chunk_buf))?;
+            let mut decoder = match this.decoder.take() {
+                Some(r) => r,
+                None => return Poll::Ready(Ok(())),
+            };
+            if chunk_buf.filled().is_empty() {
+                decoder.flush()?;
+                drop(decoder);
+                this.done = true;
+            } else {
+                decoder.write_all(chunk_buf.filled())?;
+                this.decoder = Some(decoder);
+            }
+        }
+    }
+}
@@ -0,0 +1,206 @@
+use super::{HttpHeaders, HttpRequest, HttpResponse};
+use url::Url;
+
+// Characters allowed within a token besides letters and digits, RFC 9110 section 5.6.2
+const TCHARS: &[u8] = b"!#$%&'*+-.^_`|~";
+
+/// Check request as it will be sent against RFC 9110 and RFC 9112, returning a description of
+/// each violation found.  Pass the effective request, eg. HttpResponse::effective_request(), so
+/// headers added by the client are checked as well.
+pub fn check_request(req: &HttpRequest) -> Vec<String> {
+    let mut violations = Vec::new();
+
+    // Method
+    if !is_token(&req.method) {
+        violations.push(format!(
+            "Method '{}' is not a token, RFC 9110 section 9.1",
+            req.method
+        ));
+    }
+
+    // URL
+    if req.url.chars().any(|c| c.is_whitespace() || c.is_control()) {
+        violations
+            .push("URL contains whitespace or control characters, RFC 3986 section 2".to_string());
+    }
+    match Url::parse(&req.url) {
+        Ok(uri) => {
+            if !["http", "https"].contains(&uri.scheme()) {
+                violations.push(format!(
+                    "URL scheme '{}' is not http or https, RFC 9110 section 4.2",
+                    uri.scheme()
+                ));
+            }
+            if uri.host_str().map_or(true, |host| host.is_empty()) {
+                violations.push("URL has no host, RFC 9110 section 4.2.1".to_string());
+            }
+            if !uri.username().is_empty() || uri.password().is_some() {
+                violations.push(
+                    "URL contains userinfo, which is deprecated for http and https, RFC 9110 \
+                     section 4.2.4"
+                        .to_string(),
+                );
+            }
+        }
+        Err(e) => violations.push(format!("URL is invalid, {}", e)),
+    }
+
+    // Headers
+    check_headers(&req.headers, &mut violations);
+    if !req.headers.has_lower("host") {
+        violations.push("No Host header, RFC 9112 section 3.2".to_string());
+    }
+
+    // Body / header consistency
+    let has_length = req.headers.has_lower("content-length");
+    let is_chunked = req
+        .headers
+        .get_lower("transfer-encoding")
+        .map_or(false, |te| te.to_lowercase().contains("chunked"));
+    if has_length && req.headers.has_lower("transfer-encoding") {
+        violations.push(
+            "Both Content-Length and Transfer-Encoding headers, RFC 9112 section 6.2".to_string(),
+        );
+    }
+    if let Some(length) = req.headers.get_lower("content-length") {
+        match length.trim().parse::<u64>() {
+            Ok(length) if !req.body.is_chunked() && length != req.body.content_length() => {
+                violations.push(format!(
+                    "Content-Length of {} does not match body of {} bytes, RFC 9110 section 8.6",
+                    length,
+                    req.body.content_length()
+                ))
+            }
+            Ok(_) => {}
+            Err(_) => violations.push(format!(
+                "Content-Length '{}' is not a number, RFC 9110 section 8.6",
+                length
+            )),
+        }
+    }
+    if req.body.is_chunked() && !is_chunked {
+        violations.push(
+            "Streamed body without Transfer-Encoding: chunked, RFC 9112 section 6.1".to_string(),
+        );
+    }
+    if req.method.eq_ignore_ascii_case("TRACE")
+        && (req.body.is_chunked() || req.body.content_length() > 0)
+    {
+        violations.push("TRACE request with a body, RFC 9110 section 9.3.8".to_string());
+    }
+
+    violations
+}
+
+/// Check response header against RFC 9110 and RFC 9112, returning a description of each
+/// violation found
+pub fn check_response(res: &HttpResponse) -> Vec<String> {
+    let mut violations = Vec::new();
+    let status = res.status_code();
+
+    // Status line
+    if !["1.0", "1.1"].contains(&res.version().as_str()) {
+        violations.push(format!(
+            "HTTP version '{}' is not 1.0 or 1.1, RFC 9112 section 2.3",
+            res.version()
+        ));
+    }
+    if !(100..=599).contains(&status) {
+        violations.push(format!(
+            "Status code {} is outside of 100 - 599, RFC 9110 section 15",
+            status
+        ));
+    }
+    if !is_field_value(&res.reason()) {
+        violations
+            .push("Reason phrase contains control characters, RFC 9112 section 4".to_string());
+    }
+
+    // Headers
+    let headers = res.headers();
+    check_headers(&headers, &mut violations);
+    if headers.has_lower("content-length") && headers.has_lower("transfer-encoding") {
+        violations.push(
+            "Both Content-Length and Transfer-Encoding headers, RFC 9112 section 6.2".to_string(),
+        );
+    }
+    if let Some(length) = headers.get_lower("content-length") {
+        if length.trim().parse::<u64>().is_err() {
+            violations.push(format!(
+                "Content-Length '{}' is not a number, RFC 9110 section 8.6",
+                length
+            ));
+        }
+    }
+
+    // Framing of responses without content
+    if (100..200).contains(&status) || status == 204 {
+        if headers.has_lower("content-length") {
+            violations.push(format!(
+                "Content-Length header within {} response, RFC 9110 section 8.6",
+                status
+            ));
+        }
+        if headers.has_lower("transfer-encoding") {
+            violations.push(format!(
+                "Transfer-Encoding header within {} response, RFC 9112 section 6.1",
+                status
+            ));
+        }
+    }
+    if res.version() == "1.0" && headers.has_lower("transfer-encoding") {
+        violations.push(
+            "Transfer-Encoding header within HTTP/1.0 response, RFC 9112 section 6.1".to_string(),
+        );
+    }
+
+    // Date, required from origin servers with a clock
+    if (200..500).contains(&status) && !headers.has_lower("date") {
+        violations.push("No Date header, RFC 9110 section 6.6.1".to_string());
+    }
+
+    violations
+}
+
+// Check names and values of all headers
+fn check_headers(headers: &HttpHeaders, violations: &mut Vec<String>) {
+    let mut names: Vec<(String, Vec<String>)> = headers.all().into_iter().collect();
+    names.sort();
+    for (name, values) in names.iter() {
+        if !is_token(name) {
+            violations.push(format!(
+                "Header name '{}' is not a token, RFC 9110 section 5.1",
+                name.escape_debug()
+            ));
+        }
+        for value in values.iter() {
+            if !is_field_value(value) {
+                violations.push(format!(
+                    "Value of header {} contains CR, LF, NUL or control characters, RFC 9110 \
+                     section 5.5",
+                    name.escape_debug()
+                ));
+            } else if value.trim_matches([' ', '\t']) != value {
+                violations.push(format!(
+                    "Value of header {} has leading or trailing whitespace, RFC 9110 section 5.5",
+                    name.escape_debug()
+                ));
+            }
+        }
+    }
+}
+
+// Whether or not string is a token, RFC 9110 section 5.6.2
+fn is_token(value: &str) -> bool {
+    !value.is_empty()
+        && value
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || TCHARS.contains(&b))
+}
+
+// Whether or not string only contains visible characters, spaces, tabs and obs-text
+fn is_field_value(value: &str) -> bool {
+    value
+        .bytes()
+        .all(|b| b == b'\t' || (b >= 0x20 && b != 0x7f))
+}
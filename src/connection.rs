@@ -0,0 +1,971 @@
+use super::{EarlyData, HttpBody, HttpClientConfig, HttpResponse, ProxyType, Timeouts};
+use crate::error::{Error, InvalidResponseError, ProxyFailure};
+use crate::resolver::IpFamily;
+use crate::socks5;
+use crate::timings::{Phase, Timings};
+use rustls::pki_types::ServerName;
+use rustls::{ClientConnection, StreamOwned};
+use socket2::{Domain, Protocol, Socket, Type};
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{BufRead, BufReader, ErrorKind, Read, Write};
+use std::net::{IpAddr, Shutdown, SocketAddr, TcpStream, ToSocketAddrs};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use url::Url;
+
+/// Details of an established TLS session
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TlsInfo {
+    /// Negotiated protocol version, eg. TLSv1.3
+    pub version: String,
+    /// Negotiated cipher suite, eg. TLS13_AES_256_GCM_SHA384
+    pub cipher_suite: String,
+    /// DER encoded certificate chain presented by the server, leaf first
+    pub peer_certificates: Vec<Vec<u8>>,
+}
+
+/// Bi-directional stream to a remote server, either plain TCP or TLS.
+pub trait HttpStream: Read + Write + Send {
+    /// Set timeout of read operations on the underlying socket, None blocks indefinitely.
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> std::io::Result<()>;
+
+    /// Set timeout of write operations on the underlying socket, None blocks indefinitely.
+    fn set_write_timeout(&self, _timeout: Option<Duration>) -> std::io::Result<()> {
+        Ok(())
+    }
+
+    /// Protocol agreed upon via ALPN during the TLS handshake, None for plain connections.
+    fn alpn_protocol(&self) -> Option<String> {
+        None
+    }
+
+    /// Version, cipher suite and server certificates of the TLS session, None for plain
+    /// connections.
+    fn tls_info(&self) -> Option<TlsInfo> {
+        None
+    }
+
+    /// Cheap check whether an idle connection is still open, without blocking.  Defaults to
+    /// assuming it is.
+    fn is_alive(&self) -> bool {
+        true
+    }
+
+    /// Gracefully close the connection, such as sending a TLS close_notify before shutting down
+    /// the socket.  Defaults to doing nothing, leaving the stream to close when dropped.
+    fn shutdown(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+
+    /// Send data as TLS 1.3 early data while resuming a session, then complete the handshake.
+    /// Returns false if nothing was sent that way or the server rejected it, in which case the
+    /// data has to be written again.  Defaults to false.
+    fn send_early_data(&mut self, _data: &[u8]) -> std::io::Result<bool> {
+        Ok(false)
+    }
+
+    /// Whether or not the TLS handshake is yet to complete, as with connections resuming a
+    /// session which allows early data.  Defaults to false.
+    fn is_handshaking(&self) -> bool {
+        false
+    }
+
+    /// Complete a pending TLS handshake without sending early data.  Defaults to doing nothing.
+    fn complete_handshake(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl HttpStream for TcpStream {
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> std::io::Result<()> {
+        TcpStream::set_read_timeout(self, timeout)
+    }
+
+    fn set_write_timeout(&self, timeout: Option<Duration>) -> std::io::Result<()> {
+        TcpStream::set_write_timeout(self, timeout)
+    }
+
+    fn is_alive(&self) -> bool {
+        probe(self)
+    }
+
+    fn shutdown(&mut self) -> std::io::Result<()> {
+        TcpStream::shutdown(self, Shutdown::Both)
+    }
+}
+
+impl HttpStream for StreamOwned<ClientConnection, TcpStream> {
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> std::io::Result<()> {
+        self.sock.set_read_timeout(timeout)
+    }
+
+    fn set_write_timeout(&self, timeout: Option<Duration>) -> std::io::Result<()> {
+        self.sock.set_write_timeout(timeout)
+    }
+
+    fn alpn_protocol(&self) -> Option<String> {
+        self.conn
+            .alpn_protocol()
+            .map(|proto| String::from_utf8_lossy(proto).to_string())
+    }
+
+    fn tls_info(&self) -> Option<TlsInfo> {
+        let version = self.conn.protocol_version()?;
+        let cipher_suite = self.conn.negotiated_cipher_suite()?.suite();
+        Some(TlsInfo {
+            version: version
+                .as_str()
+                .map(|v| v.replace('_', "."))
+                .unwrap_or_else(|| format!("{:#06x}", version.get_u16())),
+            cipher_suite: cipher_suite
+                .as_str()
+                .map(|c| c.to_string())
+                .unwrap_or_else(|| format!("{:#06x}", cipher_suite.get_u16())),
+            peer_certificates: self
+                .conn
+                .peer_certificates()
+                .map(|certs| certs.iter().map(|cert| cert.to_vec()).collect())
+                .unwrap_or_default(),
+        })
+    }
+
+    fn is_alive(&self) -> bool {
+        probe(&self.sock)
+    }
+
+    fn shutdown(&mut self) -> std::io::Result<()> {
+        self.conn.send_close_notify();
+        let _ = self.conn.complete_io(&mut self.sock);
+        self.sock.shutdown(Shutdown::Both)
+    }
+
+    fn send_early_data(&mut self, data: &[u8]) -> std::io::Result<bool> {
+        let sent = match self.conn.early_data() {
+            Some(mut early) if early.bytes_left() >= data.len() => early.write_all(data).is_ok(),
+            _ => false,
+        };
+        while self.conn.is_handshaking() {
+            self.conn.complete_io(&mut self.sock)?;
+        }
+        Ok(sent && self.conn.is_early_data_accepted())
+    }
+
+    fn is_handshaking(&self) -> bool {
+        self.conn.is_handshaking()
+    }
+
+    fn complete_handshake(&mut self) -> std::io::Result<()> {
+        while self.conn.is_handshaking() {
+            self.conn.complete_io(&mut self.sock)?;
+        }
+        Ok(())
+    }
+}
+
+// Peek at an idle socket without blocking.  Nothing to read means the connection is still open,
+// while EOF, an error, or unsolicited data such as a TLS close_notify means it is not reusable.
+fn probe(sock: &TcpStream) -> bool {
+    if sock.set_nonblocking(true).is_err() {
+        return false;
+    }
+    let mut buf = [0u8; 1];
+    let is_idle = matches!(sock.peek(&mut buf), Err(e) if e.kind() == ErrorKind::WouldBlock);
+    sock.set_nonblocking(false).is_ok() && is_idle
+}
+
+/// Maximum number of idle connections kept per host
+const MAX_IDLE_PER_HOST: usize = 8;
+
+/// Open connection to a server, along with the key it is pooled under once released, and the
+/// timings of the request being sent over it.
+pub struct Connection {
+    pub key: String,
+    pub reused: bool,
+    pub reader: BufReader<Box<dyn HttpStream>>,
+    pub started: Instant,
+    pub timings: Timings,
+    pub sent: u64,
+    pub received: u64,
+    pub must_close: bool,
+    pending: Vec<u8>,
+    read_timeout: Option<Duration>,
+    deadline: Option<Instant>,
+}
+
+impl Connection {
+    fn new(key: String, reused: bool, reader: BufReader<Box<dyn HttpStream>>) -> Self {
+        Self {
+            key,
+            reused,
+            reader,
+            started: Instant::now(),
+            timings: Timings::default(),
+            sent: 0,
+            received: 0,
+            must_close: false,
+            pending: Vec::new(),
+            read_timeout: None,
+            deadline: None,
+        }
+    }
+
+    /// Wrap stream established elsewhere, to be keyed under the given origin
+    pub fn from_stream(key: String, stream: Box<dyn HttpStream>) -> Self {
+        Self::new(key, false, BufReader::with_capacity(2048, stream))
+    }
+
+    /// Apply timeout of individual reads, and the deadline of the whole request
+    pub fn set_timeouts(
+        &mut self,
+        read_timeout: Option<Duration>,
+        deadline: Option<Instant>,
+    ) -> std::io::Result<()> {
+        self.read_timeout = read_timeout;
+        self.deadline = deadline;
+        self.arm(None)
+    }
+
+    /// Whether or not the deadline of the request has passed
+    pub fn is_expired(&self) -> bool {
+        self.deadline.map(|d| d <= Instant::now()).unwrap_or(false)
+    }
+
+    /// Hand back bytes already read, to be read again before anything else
+    pub fn unread(&mut self, bytes: &[u8]) {
+        self.received = self.received.saturating_sub(bytes.len() as u64);
+        self.pending.splice(0..0, bytes.iter().copied());
+    }
+
+    /// Wait at most timeout for data to arrive, and return a copy of what has been buffered
+    pub fn wait_for_data(&mut self, timeout: Duration) -> std::io::Result<Vec<u8>> {
+        self.arm(Some(timeout))?;
+        let result = self.fill_buf().map(|buf| buf.to_vec());
+        self.arm(None)?;
+        result
+    }
+
+    // Set socket read timeout to the lesser of the read timeout, the time remaining until the
+    // deadline, and limit
+    fn arm(&mut self, limit: Option<Duration>) -> std::io::Result<()> {
+        let remaining = match self.deadline {
+            Some(deadline) => match deadline.checked_duration_since(Instant::now()) {
+                Some(r) if !r.is_zero() => Some(r),
+                _ => return Err(ErrorKind::TimedOut.into()),
+            },
+            None => None,
+        };
+        let timeout = [self.read_timeout, remaining, limit]
+            .into_iter()
+            .flatten()
+            .min();
+        self.reader.get_ref().set_read_timeout(timeout)
+    }
+}
+
+impl Read for Connection {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if !self.pending.is_empty() {
+            let len = buf.len().min(self.pending.len());
+            buf[..len].copy_from_slice(&self.pending[..len]);
+            self.pending.drain(..len);
+            self.received += len as u64;
+            return Ok(len);
+        }
+        if self.deadline.is_some() && self.reader.buffer().is_empty() {
+            self.arm(None)?;
+        }
+        let len = self.reader.read(buf)?;
+        if len > 0 && self.timings.ttfb.is_none() {
+            self.timings.ttfb = Some(self.started.elapsed());
+        }
+        self.received += len as u64;
+        Ok(len)
+    }
+}
+
+impl Write for Connection {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let len = self.reader.get_mut().write(buf)?;
+        self.sent += len as u64;
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.reader.get_mut().flush()
+    }
+}
+
+impl BufRead for Connection {
+    fn fill_buf(&mut self) -> std::io::Result<&[u8]> {
+        if !self.pending.is_empty() {
+            return Ok(&self.pending);
+        }
+        if self.deadline.is_some() && self.reader.buffer().is_empty() {
+            self.arm(None)?;
+        }
+        let buf = self.reader.fill_buf()?;
+        if !buf.is_empty() && self.timings.ttfb.is_none() {
+            self.timings.ttfb = Some(self.started.elapsed());
+        }
+        Ok(buf)
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.received += amt as u64;
+        match self.pending.is_empty() {
+            true => self.reader.consume(amt),
+            false => {
+                self.pending.drain(..amt.min(self.pending.len()));
+            }
+        }
+    }
+}
+
+/// Idle keep-alive connections, keyed by scheme, host and port.  Clones share the same pool.
+#[derive(Clone, Default)]
+pub struct ConnectionPool {
+    idle: Arc<Mutex<HashMap<String, Vec<BufReader<Box<dyn HttpStream>>>>>>,
+}
+
+impl fmt::Debug for ConnectionPool {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let idle = self
+            .idle
+            .lock()
+            .map(|idle| idle.values().map(|v| v.len()).sum())
+            .unwrap_or(0);
+        f.debug_struct("ConnectionPool")
+            .field("idle", &idle)
+            .finish()
+    }
+}
+
+impl ConnectionPool {
+    /// Get idle connection to the server, or open a new one, applying the timeouts of the
+    /// request which started at the given time.  The connect timeout defaults to that of the
+    /// client, and connections with a different TLS server name than the host are pooled apart.
+    #[allow(clippy::too_many_arguments)]
+    pub fn checkout(
+        &self,
+        config: &HttpClientConfig,
+        uri: &Url,
+        port: &u16,
+        sni: Option<&str>,
+        timeouts: &Timeouts,
+        started: Instant,
+        deadline: Option<Instant>,
+    ) -> Result<Connection, Error> {
+        let mut timings = Timings::default();
+        // Deadline passed while still getting a connection, before anything was written
+        let timeout_err = |e: std::io::Error| match e.kind() {
+            ErrorKind::TimedOut => Error::timeout(uri.as_str(), Phase::Connect),
+            _ => Error::Io(e),
+        };
+        let mut key = format!(
+            "{}://{}:{}",
+            uri.scheme(),
+            uri.host_str().unwrap_or(""),
+            port
+        );
+        if let Some(server_name) = sni {
+            key = format!("{} ({})", key, server_name);
+        }
+
+        let mut conn = loop {
+            let idle = self
+                .idle
+                .lock()
+                .ok()
+                .and_then(|mut idle| idle.get_mut(&key).and_then(|conns| conns.pop()));
+            let reader = match idle {
+                Some(r) => r,
+                None => break None,
+            };
+
+            // Discard connections the server has since closed
+            if config.probe_idle && (!reader.buffer().is_empty() || !reader.get_ref().is_alive()) {
+                continue;
+            }
+            break Some(Connection::new(key.clone(), true, reader));
+        };
+
+        // Connect, within the time remaining until the deadline
+        if conn.is_none() {
+            let mut connect_timeout = timeouts
+                .connect
+                .unwrap_or(Duration::from_secs(config.timeout));
+            if let Some(deadline) = deadline {
+                connect_timeout = match deadline.checked_duration_since(Instant::now()) {
+                    Some(r) if !r.is_zero() => connect_timeout.min(r),
+                    _ => {
+                        return Err(Error::timeout(uri.as_str(), Phase::Connect)
+                            .with_timings(started, &timings))
+                    }
+                };
+            }
+            let stream = config
+                .connector
+                .connect(config, uri, *port, sni, connect_timeout, &mut timings)
+                .map_err(|e| e.with_timings(started, &timings))?;
+            conn = Some(Connection::new(
+                key,
+                false,
+                BufReader::with_capacity(2048, stream),
+            ));
+        }
+
+        // Read timeout of the request takes precedence over that of the client
+        let read_timeout = timeouts
+            .read
+            .or(config.read_timeout.map(Duration::from_secs));
+        let write_timeout = config.write_timeout.map(Duration::from_secs);
+        let mut conn = conn.unwrap();
+        conn.started = started;
+        conn.timings = timings;
+        conn.reader
+            .get_ref()
+            .set_write_timeout(write_timeout)
+            .map_err(|e| timeout_err(e).with_timings(started, &conn.timings))?;
+        conn.set_timeouts(read_timeout, deadline)
+            .map_err(|e| timeout_err(e).with_timings(started, &conn.timings))?;
+        Ok(conn)
+    }
+
+    /// Gracefully close all idle connections, returning the number closed
+    pub fn drain(&self) -> usize {
+        let idle: Vec<BufReader<Box<dyn HttpStream>>> = match self.idle.lock() {
+            Ok(mut idle) => idle.drain().flat_map(|(_, conns)| conns).collect(),
+            Err(_) => return 0,
+        };
+
+        let count = idle.len();
+        for reader in idle {
+            let _ = reader.into_inner().shutdown();
+        }
+        count
+    }
+
+    /// Return connection to the pool once its response has been fully read, if both sides
+    /// agreed to keep it open.  Otherwise it is closed.
+    pub fn release(&self, conn: Connection, res: &HttpResponse) {
+        if conn.must_close || !is_reusable(res) {
+            return;
+        }
+
+        if let Ok(mut idle) = self.idle.lock() {
+            let conns = idle.entry(conn.key).or_default();
+            if conns.len() < MAX_IDLE_PER_HOST {
+                conns.push(conn.reader);
+            }
+        }
+    }
+}
+
+impl Drop for ConnectionPool {
+    // Close idle connections once the last client sharing the pool is dropped
+    fn drop(&mut self) {
+        if Arc::strong_count(&self.idle) == 1 {
+            self.drain();
+        }
+    }
+}
+
+/// Whether or not the connection a response was read from may be reused for another request
+pub fn is_reusable(res: &HttpResponse) -> bool {
+    let is_close = |value: Option<String>| {
+        value
+            .map(|v| v.to_lowercase().contains("close"))
+            .unwrap_or(false)
+    };
+    let headers = res.headers();
+    let req_close = res
+        .effective_request()
+        .map(|req| is_close(req.headers.get_lower("connection")))
+        .unwrap_or(true);
+
+    // Body must be framed, otherwise it ends when the connection closes
+    let is_framed = !res.has_body()
+        || headers.has_lower("content-length")
+        || headers
+            .get_lower_line("transfer-encoding")
+            .map(|te| te.to_lowercase().contains("chunked"))
+            .unwrap_or(false);
+
+    res.version() == "1.1"
+        && !req_close
+        && !res.must_close()
+        && !is_close(headers.get_lower("connection"))
+        && is_framed
+}
+
+// Open tcp stream to the address, bound to the local address if given
+fn open_tcp(
+    addr: &SocketAddr,
+    local: Option<IpAddr>,
+    timeout: Duration,
+) -> std::io::Result<TcpStream> {
+    let local = match local {
+        Some(r) => r,
+        None => return TcpStream::connect_timeout(addr, timeout),
+    };
+    let sock = Socket::new(
+        Domain::for_address(*addr),
+        Type::STREAM,
+        Some(Protocol::TCP),
+    )?;
+    sock.bind(&SocketAddr::new(local, 0).into())?;
+    sock.connect_timeout(&(*addr).into(), timeout)?;
+    Ok(sock.into())
+}
+
+/// Connect to remote server, going through the proxy and TLS handshake as needed, and recording
+/// the duration of each step within timings.  The server name sent in the TLS handshake, if
+/// given, overrides that of the client's sni_host() setting and the host of the URL.
+pub fn connect_sni(
+    config: &HttpClientConfig,
+    uri: &Url,
+    port: &u16,
+    sni: Option<&str>,
+    timeout: Duration,
+    timings: &mut Timings,
+) -> Result<Box<dyn HttpStream>, Error> {
+    // Prepare uri
+    let via_proxy = config.proxy_for(uri) != ProxyType::None;
+    let (host, host_port) = if via_proxy {
+        (config.proxy_host.as_str(), config.proxy_port)
+    } else {
+        (uri.host_str().unwrap(), *port)
+    };
+    let hostname = format!("{}:{}", host, host_port);
+
+    // Resolve host, failures to resolve the proxy are failures to connect to it
+    let start = Instant::now();
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    let none_found = match (config.local_address, config.ip_family) {
+        (Some(IpAddr::V6(_)), _) => "no IPv6 addresses found",
+        (Some(IpAddr::V4(_)), _) | (None, IpFamily::Ipv4Only) => "no IPv4 addresses found",
+        _ => "no addresses found",
+    };
+    let addrs = match config.resolver.resolve(host, host_port) {
+        Ok(addrs) => {
+            // Only addresses reachable from the local address, if bound to one
+            let mut addrs = config.ip_family.apply(addrs);
+            if let Some(local) = config.local_address {
+                addrs.retain(|addr| addr.is_ipv4() == local.is_ipv4());
+            }
+            addrs
+        }
+        Err(e) if via_proxy => {
+            let target = format!("{}:{}", uri.host_str().unwrap_or(""), port);
+            return Err(Error::proxy(
+                &hostname,
+                &target,
+                ProxyFailure::Connect,
+                &e.to_string(),
+            ));
+        }
+        Err(e) => return Err(Error::Dns(host.to_string(), e.to_string())),
+    };
+    if addrs.is_empty() && via_proxy {
+        let target = format!("{}:{}", uri.host_str().unwrap_or(""), port);
+        return Err(Error::proxy(
+            &hostname,
+            &target,
+            ProxyFailure::Connect,
+            none_found,
+        ));
+    } else if addrs.is_empty() {
+        return Err(Error::Dns(host.to_string(), none_found.to_string()));
+    }
+    timings.dns = Some(start.elapsed());
+    let timeout = match timeout.checked_sub(start.elapsed()) {
+        Some(r) if !r.is_zero() => r,
+        _ => return Err(Error::timeout(uri.as_str(), Phase::Dns)),
+    };
+
+    // Open tcp stream, trying each address in turn with an equal share of the remaining time
+    let start = Instant::now();
+    let (mut sock, mut last_err) = (None, None);
+    for (i, addr) in addrs.iter().enumerate() {
+        let remaining = match timeout.checked_sub(start.elapsed()) {
+            Some(r) if !r.is_zero() => r,
+            _ => break,
+        };
+        match open_tcp(
+            addr,
+            config.local_address,
+            remaining / (addrs.len() - i) as u32,
+        ) {
+            Ok(r) => {
+                sock = Some(r);
+                break;
+            }
+            Err(e) => last_err = Some(e),
+        }
+    }
+    let mut sock = match (sock, last_err) {
+        (Some(r), _) => r,
+        (None, Some(e)) if e.kind() == ErrorKind::TimedOut || e.kind() == ErrorKind::WouldBlock => {
+            return Err(Error::timeout(uri.as_str(), Phase::Connect));
+        }
+        (None, None) => return Err(Error::timeout(uri.as_str(), Phase::Connect)),
+        (None, Some(e)) if via_proxy => {
+            let target = format!("{}:{}", uri.host_str().unwrap_or(""), port);
+            return Err(Error::proxy(
+                &hostname,
+                &target,
+                ProxyFailure::Connect,
+                &e.to_string(),
+            ));
+        }
+        (None, Some(_e)) => {
+            return Err(Error::NoConnect(hostname.clone()));
+        }
+    };
+    sock.set_nodelay(true).unwrap();
+
+    // SOCKs5 connection, or tunnel through HTTP proxy for https, if needed
+    if via_proxy && config.proxy_type == ProxyType::SOCKS5 {
+        socks5::connect(&mut sock, config, uri, port)?;
+    } else if via_proxy && uri.scheme() == "https" {
+        tunnel(&mut sock, config, uri, port, timeout)?;
+    }
+    timings.connect = Some(start.elapsed());
+
+    // Connect over SSL, if needed
+    if uri.scheme() == "https" {
+        let host = uri.host_str().unwrap();
+        let server_name = sni
+            .or(config.sni_hosts.get(host).map(|name| name.as_str()))
+            .unwrap_or(host);
+        let dns_name = ServerName::try_from(server_name)
+            .map_err(|_| Error::Tls(format!("Invalid TLS server name '{}'", server_name)))?
+            .to_owned();
+
+        // ALPN protocols, per-host overrides take precedence.  Copies share the session cache.
+        let alpn = config.alpn_hosts.get(host);
+        let early_data = config.early_data != EarlyData::Never;
+        let tls_config = if alpn.is_some() || early_data != config.tls_config.enable_early_data {
+            let mut host_config = (*config.tls_config).clone();
+            if let Some(protocols) = alpn {
+                host_config.alpn_protocols =
+                    protocols.iter().map(|p| p.as_bytes().to_vec()).collect();
+            }
+            host_config.enable_early_data = early_data;
+            Arc::new(host_config)
+        } else {
+            Arc::clone(&config.tls_config)
+        };
+        let mut conn = ClientConnection::new(tls_config, dns_name).unwrap();
+
+        // Resumed sessions which allow early data complete the handshake with the first request,
+        // so it may be sent along.  The handshake timeout, its timing and the protocol check are
+        // then applied by write_early().
+        if early_data && conn.early_data().is_some() {
+            return Ok(Box::new(StreamOwned::new(conn, sock)));
+        }
+
+        // Complete handshake, within the connect timeout
+        let start = Instant::now();
+        sock.set_read_timeout(Some(timeout)).map_err(Error::Io)?;
+        sock.set_write_timeout(Some(timeout)).map_err(Error::Io)?;
+        let mut tls_stream = StreamOwned::new(conn, sock);
+        while tls_stream.conn.is_handshaking() {
+            match tls_stream.conn.complete_io(&mut tls_stream.sock) {
+                Ok(_) => {}
+                Err(e) if e.kind() == ErrorKind::TimedOut || e.kind() == ErrorKind::WouldBlock => {
+                    return Err(Error::timeout(uri.as_str(), Phase::Tls));
+                }
+                Err(e) => {
+                    return Err(Error::Tls(format!(
+                        "Handshake with {} failed, {}",
+                        hostname, e
+                    )));
+                }
+            }
+        }
+        tls_stream.sock.set_read_timeout(None).map_err(Error::Io)?;
+        tls_stream.sock.set_write_timeout(None).map_err(Error::Io)?;
+        timings.tls = Some(start.elapsed());
+
+        check_alpn(&tls_stream, &hostname)?;
+        return Ok(Box::new(tls_stream));
+    }
+
+    Ok(Box::new(sock))
+}
+
+// Fail unless the protocol negotiated via ALPN, if any, is HTTP/1.1 as that is the only one spoken
+fn check_alpn(stream: &dyn HttpStream, hostname: &str) -> Result<(), Error> {
+    match stream.alpn_protocol() {
+        Some(protocol) if protocol != "http/1.1" => Err(Error::Tls(format!(
+            "{} negotiated unsupported protocol '{}' via ALPN, only http/1.1 is supported",
+            hostname, protocol
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// Open tunnel to the server through a HTTP proxy via the CONNECT method.  The proxy response is
+/// read byte by byte so nothing sent through the tunnel afterwards is consumed.
+fn tunnel(
+    sock: &mut TcpStream,
+    config: &HttpClientConfig,
+    uri: &Url,
+    port: &u16,
+    timeout: Duration,
+) -> Result<(), Error> {
+    let target = format!("{}:{}", uri.host_str().unwrap_or(""), port);
+    let mut message = format!("CONNECT {} HTTP/1.1\r\nHost: {}\r\n", target, target);
+    if let Some(auth) = config.headers.get_lower_line("proxy-authorization") {
+        message.push_str(&format!("Proxy-Authorization: {}\r\n", auth));
+    }
+    message.push_str("\r\n");
+    sock.write_all(message.as_bytes())
+        .map_err(|e| Error::NoWrite(e.to_string()))?;
+
+    // Read response header
+    let proxy = format!("{}:{}", config.proxy_host, config.proxy_port);
+    let refused = |failure: ProxyFailure, response: &str| {
+        Error::proxy(
+            &proxy,
+            &target,
+            failure,
+            &format!("HTTP proxy refused to open tunnel, {}", response),
+        )
+    };
+    sock.set_read_timeout(Some(timeout)).map_err(Error::Io)?;
+    let mut head = Vec::new();
+    let mut byte = [0u8; 1];
+    while !head.ends_with(b"\r\n\r\n") && !head.ends_with(b"\n\n") {
+        match sock.read(&mut byte) {
+            Ok(0) => return Err(refused(ProxyFailure::Protocol, "connection closed")),
+            Ok(_) => head.push(byte[0]),
+            Err(e) => return Err(refused(ProxyFailure::Protocol, &e.to_string())),
+        }
+        if head.len() > 65536 {
+            return Err(refused(ProxyFailure::Protocol, "response header too large"));
+        }
+    }
+    sock.set_read_timeout(None).map_err(Error::Io)?;
+
+    // Check status
+    let head = String::from_utf8_lossy(&head).to_string();
+    let status_line = head.lines().next().unwrap_or("").trim();
+    match status_line
+        .split_whitespace()
+        .nth(1)
+        .map(|s| s.parse::<u16>())
+    {
+        Some(Ok(status)) if (200..300).contains(&status) => Ok(()),
+        Some(Ok(status)) => Err(refused(
+            ProxyFailure::from_status(status),
+            &format!("responded with {}", status_line),
+        )),
+        _ => Err(refused(
+            ProxyFailure::Protocol,
+            &format!("responded with {}", status_line),
+        )),
+    }
+}
+
+/// Write request message and body to the server.  If the message contains an Expect: 100-continue
+/// header, the body is held back until the server responds with 100 Continue, or until the
+/// expect_continue timeout elapses, whichever comes first.
+pub fn write_request(
+    conn: &mut Connection,
+    message: &[u8],
+    body: &HttpBody,
+    expect_continue: Option<Duration>,
+    url: &str,
+) -> Result<(), Error> {
+    let inline_body = match write_head(conn, message, expect_continue, url)? {
+        Some(r) => r,
+        None => return Ok(()),
+    };
+
+    // Send body
+    conn.write_all(inline_body)
+        .map_err(|e| write_error(url, e))?;
+    body.write_stream(conn).map_err(|e| write_error(url, e))?;
+    conn.flush().map_err(|e| write_error(url, e))?;
+
+    Ok(())
+}
+
+/// Write request message and body to the server as per write_request(), reading bodies from
+/// async readers without blocking
+pub async fn write_request_async(
+    conn: &mut Connection,
+    message: &[u8],
+    body: &HttpBody,
+    expect_continue: Option<Duration>,
+    url: &str,
+) -> Result<(), Error> {
+    let inline_body = match write_head(conn, message, expect_continue, url)? {
+        Some(r) => r,
+        None => return Ok(()),
+    };
+
+    // Send body
+    conn.write_all(inline_body)
+        .map_err(|e| write_error(url, e))?;
+    body.write_stream_async(conn)
+        .await
+        .map_err(|e| write_error(url, e))?;
+    conn.flush().map_err(|e| write_error(url, e))?;
+
+    Ok(())
+}
+
+// Write header of the request, waiting for 100 Continue if expected.  Returns the rest of the
+// message to send along with the body, None if the server responded already so the body is
+// not to be sent.
+fn write_head<'a>(
+    conn: &mut Connection,
+    message: &'a [u8],
+    expect_continue: Option<Duration>,
+    url: &str,
+) -> Result<Option<&'a [u8]>, Error> {
+    // Split header from body
+    let (head, inline_body) = match (
+        expect_continue,
+        message.windows(4).position(|w| w == b"\r\n\r\n"),
+    ) {
+        (Some(_), Some(pos)) => message.split_at(pos + 4),
+        _ => (message, &[][..]),
+    };
+    conn.write_all(head).map_err(|e| write_error(url, e))?;
+
+    // Wait for 100 Continue, skipping other interim responses such as 103 Early Hints
+    if let Some(timeout) = expect_continue {
+        let waiting = Instant::now();
+        loop {
+            let remaining = match timeout.checked_sub(waiting.elapsed()) {
+                Some(r) if !r.is_zero() => r,
+                _ => break,
+            };
+            match conn.wait_for_data(remaining) {
+                Ok(buf) if buf.is_empty() => {
+                    conn.must_close = true;
+                    return Ok(None);
+                }
+                Ok(_) => {}
+                Err(e) if e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::TimedOut => {
+                    break
+                }
+                Err(e) => return Err(write_error(url, e)),
+            }
+
+            // Decide upon the complete status line, not just whatever arrived first
+            let mut status_line = Vec::new();
+            conn.read_until(b'\n', &mut status_line)
+                .map_err(|e| write_error(url, e))?;
+            let status = String::from_utf8_lossy(&status_line)
+                .split_whitespace()
+                .nth(1)
+                .and_then(|code| code.parse::<u16>().ok())
+                .unwrap_or(0);
+            if !(100..200).contains(&status) || status == 101 {
+                // Final response, the body is held back so the connection can not be reused
+                conn.unread(&status_line);
+                conn.must_close = true;
+                return Ok(None);
+            }
+
+            // Discard interim response
+            loop {
+                let mut line = Vec::new();
+                if conn
+                    .read_until(b'\n', &mut line)
+                    .map_err(|e| write_error(url, e))?
+                    == 0
+                    || line.iter().all(|b| b.is_ascii_whitespace())
+                {
+                    break;
+                }
+            }
+            if status == 100 {
+                break;
+            }
+        }
+    }
+
+    Ok(Some(inline_body))
+}
+
+// Error of a failed write, a timeout if the socket timed out
+fn write_error(url: &str, e: std::io::Error) -> Error {
+    match e.kind() {
+        ErrorKind::WouldBlock | ErrorKind::TimedOut => Error::timeout(url, Phase::Write),
+        _ => Error::NoWrite(e.to_string()),
+    }
+}
+
+/// Complete the TLS handshake of a connection resuming a session which allows early data,
+/// sending the request message along as early data if allowed.  The handshake is bounded by
+/// timeout, and its timing and negotiated protocol are checked as for other connections.
+/// Returns false if the message was not sent that way or the server rejected it, so it has to
+/// be written as usual via write_request().
+pub fn write_early(
+    conn: &mut Connection,
+    message: &[u8],
+    allowed: bool,
+    timeout: Duration,
+    url: &str,
+) -> Result<bool, Error> {
+    if !conn.reader.get_ref().is_handshaking() {
+        return Ok(false);
+    }
+
+    // Complete handshake
+    let start = Instant::now();
+    conn.arm(Some(timeout))
+        .map_err(|_| Error::timeout(url, Phase::Tls))?;
+    let stream = conn.reader.get_mut();
+    let result = match allowed {
+        true => stream.send_early_data(message),
+        false => stream.complete_handshake().map(|_| false),
+    };
+    conn.arm(None)
+        .map_err(|_| Error::timeout(url, Phase::Tls))?;
+    let sent = match result {
+        Ok(r) => r,
+        Err(e) if e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::TimedOut => {
+            return Err(Error::timeout(url, Phase::Tls));
+        }
+        Err(e) => {
+            return Err(Error::Tls(format!(
+                "Handshake with {} failed, {}",
+                conn.key, e
+            )))
+        }
+    };
+    conn.timings.tls = Some(start.elapsed());
+    check_alpn(conn.reader.get_ref().as_ref(), &conn.key)?;
+
+    if sent {
+        conn.sent += message.len() as u64;
+    }
+    Ok(sent)
+}
+
+/// Wait up to timeout for the first byte of the response, failing with Error::TtfbTimeout if the
+/// server accepted the request but never started responding.
+pub fn wait_first_byte(conn: &mut Connection, timeout: Duration, url: &str) -> Result<(), Error> {
+    match conn.wait_for_data(timeout) {
+        Ok(_) => Ok(()),
+        Err(e) if e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::TimedOut => {
+            if conn.is_expired() {
+                Err(Error::timeout(url, Phase::Ttfb))
+            } else {
+                Err(Error::TtfbTimeout(url.to_string()))
+            }
+        }
+        Err(e) => Err(Error::NoRead(InvalidResponseError {
+            url: url.to_string(),
+            response: e.to_string(),
+        })),
+    }
+}
@@ -0,0 +1,60 @@
+use super::{HttpClientConfig, HttpStream};
+use crate::connection;
+use crate::error::Error;
+use crate::timings::Timings;
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+use url::Url;
+
+/// Opens the streams requests are sent over, eg. to plug in in-memory pipes within tests, another
+/// TLS implementation or a custom tunnel.  Set via HttpClientBuilder::connector(), and used by
+/// both clients for every new connection.  Connections are pooled and read from the same as
+/// those of the default connector.
+pub trait Connector: fmt::Debug + Send + Sync {
+    /// Connect to the server of the URL at port within timeout, going through any proxy and TLS
+    /// handshake as needed.  The server name sent in the TLS handshake, if overridden for the
+    /// request, is given as sni.  Durations of each step may be recorded within timings.
+    fn connect(
+        &self,
+        config: &HttpClientConfig,
+        uri: &Url,
+        port: u16,
+        sni: Option<&str>,
+        timeout: Duration,
+        timings: &mut Timings,
+    ) -> Result<Box<dyn HttpStream>, Error>;
+}
+
+impl<C: Connector + ?Sized> Connector for Arc<C> {
+    fn connect(
+        &self,
+        config: &HttpClientConfig,
+        uri: &Url,
+        port: u16,
+        sni: Option<&str>,
+        timeout: Duration,
+        timings: &mut Timings,
+    ) -> Result<Box<dyn HttpStream>, Error> {
+        (**self).connect(config, uri, port, sni, timeout, timings)
+    }
+}
+
+/// Connects over TCP via the resolver of the client, through its HTTP or SOCKS5 proxy if any,
+/// and with rustls for https.  Custom connectors may wrap it to only handle some hosts.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DefaultConnector;
+
+impl Connector for DefaultConnector {
+    fn connect(
+        &self,
+        config: &HttpClientConfig,
+        uri: &Url,
+        port: u16,
+        sni: Option<&str>,
+        timeout: Duration,
+        timings: &mut Timings,
+    ) -> Result<Box<dyn HttpStream>, Error> {
+        connection::connect_sni(config, uri, &port, sni, timeout, timings)
+    }
+}
@@ -1,13 +1,16 @@
 
 use super::HttpHeaders;
+use crate::clock::{Clock, SystemClock};
 use crate::cookie::Cookie;
 use crate::error::{Error, FileNotCreatedError};
-use std::collections::HashMap;
+use crate::http_date;
+use std::collections::{BTreeMap, HashMap};
 use std::fs;
 use std::fs::File;
 use std::io::prelude::*;
 use std::path::Path;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::sync::Arc;
+use std::time::{Duration, Instant, UNIX_EPOCH};
 use url::Url;
 
 #[derive(Clone, Debug)]
@@ -15,6 +18,44 @@ pub struct CookieJar {
     jar_file: String,
     auto_update: bool,
     cookies: HashMap<String, Cookie>,
+    limits: CookieLimits,
+    tick: u64,
+    used: HashMap<String, u64>,
+    lru: BTreeMap<u64, String>,
+    domains: HashMap<String, BTreeMap<u64, String>>,
+    save_every: Option<Duration>,
+    last_saved: Option<Instant>,
+    unsaved: bool,
+    clock: Arc<dyn Clock>,
+}
+
+/// Size caps of a cookie jar.  Once a cap is reached the least recently used cookie, either
+/// globally or within the domain, is evicted to make room.  All caps are unlimited by default.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct CookieLimits {
+    pub max_cookies: Option<usize>,
+    pub max_per_domain: Option<usize>,
+    pub max_cookie_size: Option<usize>,
+}
+
+impl CookieLimits {
+    /// Set maximum number of cookies within the jar
+    pub fn max_cookies(mut self, max: usize) -> Self {
+        self.max_cookies = Some(max);
+        self
+    }
+
+    /// Set maximum number of cookies per domain
+    pub fn max_per_domain(mut self, max: usize) -> Self {
+        self.max_per_domain = Some(max);
+        self
+    }
+
+    /// Set maximum size in bytes of a cookie's name and value, larger cookies are ignored
+    pub fn max_cookie_size(mut self, max: usize) -> Self {
+        self.max_cookie_size = Some(max);
+        self
+    }
 }
 
 impl CookieJar {
@@ -24,6 +65,15 @@ impl CookieJar {
             jar_file: String::new(),
             auto_update: false,
             cookies: HashMap::new(),
+            limits: CookieLimits::default(),
+            tick: 0,
+            used: HashMap::new(),
+            lru: BTreeMap::new(),
+            domains: HashMap::new(),
+            save_every: None,
+            last_saved: None,
+            unsaved: false,
+            clock: Arc::new(SystemClock),
         }
     }
 
@@ -46,22 +96,18 @@ impl CookieJar {
     /// Instantiate cookie jar from a string of a Netscape formatted cookies.txt file
     pub fn from_string(contents: &String) -> Self {
         // Go through lines
-        let mut cookies: HashMap<String, Cookie> = HashMap::new();
+        let mut jar = Self::new();
         for line in contents.split('\n') {
             if line.starts_with('#') {
                 continue;
             }
 
             if let Some(cookie) = Cookie::from_line(line) {
-                cookies.insert(cookie.name.clone(), cookie.clone());
+                jar.set(&cookie);
             }
         }
 
-        Self {
-            jar_file: String::new(),
-            auto_update: false,
-            cookies,
-        }
+        jar
     }
 
     /// Update jar filename
@@ -74,6 +120,71 @@ impl CookieJar {
         self.auto_update = auto_update;
     }
 
+    /// Get interval between saves of the jar file, None if saved after every change
+    pub fn save_every(&self) -> Option<Duration> {
+        self.save_every
+    }
+
+    /// Save the jar file at most once per interval when auto-updating, instead of after every
+    /// response that sets cookies.  The in-memory jar is always current, and unsaved changes are
+    /// written by the next save or HttpClient::close().
+    pub fn set_save_every(&mut self, interval: Option<Duration>) {
+        self.save_every = interval;
+    }
+
+    /// Get clock cookies expire by
+    pub fn clock(&self) -> Arc<dyn Clock> {
+        Arc::clone(&self.clock)
+    }
+
+    /// Expire cookies and time saves of the jar file as per the given clock instead of the
+    /// system clock
+    pub fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+        self.clock = clock;
+    }
+
+    /// Whether or not the jar has changes not yet saved to the jar file
+    pub fn has_unsaved(&self) -> bool {
+        self.unsaved
+    }
+
+    /// Get size caps
+    pub fn limits(&self) -> CookieLimits {
+        self.limits
+    }
+
+    /// Change size caps, evicting least recently used cookies if the jar already exceeds them
+    pub fn set_limits(&mut self, limits: CookieLimits) {
+        self.limits = limits;
+
+        if let Some(max_size) = limits.max_cookie_size {
+            let oversized: Vec<String> = self
+                .cookies
+                .values()
+                .filter(|cookie| cookie.name.len() + cookie.value.len() > max_size)
+                .map(|cookie| cookie.name.clone())
+                .collect();
+            for name in oversized {
+                self.delete(&name);
+            }
+        }
+
+        let hosts: Vec<String> = self.domains.keys().cloned().collect();
+        for host in hosts {
+            self.evict(&host);
+        }
+    }
+
+    /// Number of cookies within jar
+    pub fn len(&self) -> usize {
+        self.cookies.len()
+    }
+
+    /// Whether or not the jar is empty
+    pub fn is_empty(&self) -> bool {
+        self.cookies.is_empty()
+    }
+
     /// Get individual cookie
     pub fn get(&self, name: &str) -> Option<Cookie> {
         if let Some(cookie) = self.cookies.get(name) {
@@ -84,33 +195,68 @@ impl CookieJar {
 
     // Set a cookie.  Will insert new cookie, or update if cookie already exists within jar.
     pub fn set(&mut self, cookie: &Cookie) {
+        if let Some(max_size) = self.limits.max_cookie_size {
+            if cookie.name.len() + cookie.value.len() > max_size {
+                return;
+            }
+        }
+
         let name = cookie.name.clone();
+        self.untrack(&name);
         *self.cookies.entry(name.clone()).or_insert(cookie.clone()) = cookie.clone();
+        self.track(&name);
+        self.evict(&cookie.host);
     }
 
     /// Delete a cookie within jar
     pub fn delete(&mut self, name: &str) {
+        self.untrack(name);
         self.cookies.remove(name);
     }
 
     /// Clear all cookies within jar
     pub fn clear(&mut self) {
         self.cookies.clear();
+        self.used.clear();
+        self.lru.clear();
+        self.domains.clear();
+    }
+
+    /// Mark cookies sent to URL as recently used, so they are the last to be evicted
+    pub fn touch(&mut self, uri: &Url) {
+        let host = match uri.host_str() {
+            Some(r) => r,
+            None => return,
+        };
+
+        let mut names = Vec::new();
+        for domain in [host.to_string(), format!(".{}", host)] {
+            if let Some(entries) = self.domains.get(&domain) {
+                names.extend(entries.values().cloned());
+            }
+        }
+
+        for name in names {
+            if self
+                .cookies
+                .get(&name)
+                .is_some_and(|cookie| Self::matches(cookie, uri))
+            {
+                self.untrack(&name);
+                self.track(&name);
+            }
+        }
     }
 
     /// Get http header for host
     pub fn get_http_header(&self, uri: &Url) -> Option<String> {
         // Initialize
         let mut pairs = Vec::new();
-        let host = uri.host_str().unwrap();
-        let host_chk = format!(".{}", host);
 
-        // Iterate through cookies
+        // Iterate through cookies, skipping expired ones
+        let now = self.clock.unix_secs();
         for (_name, cookie) in self.iter() {
-            if (cookie.host != host && cookie.host != host_chk)
-                || (!uri.path().starts_with(&cookie.path))
-                || (cookie.secure && uri.scheme() != "https")
-            {
+            if !Self::matches(&cookie, uri) || (cookie.expires != 0 && cookie.expires <= now) {
                 continue;
             }
 
@@ -132,8 +278,25 @@ impl CookieJar {
 
     /// Update cookie jar from response http headers
     pub fn update_jar(&mut self, headers: &HttpHeaders) {
+        self.update_jar_unsaved(headers);
+
+        // Save jar file, once the interval elapsed if batching
+        let due = match (self.save_every, self.last_saved) {
+            (Some(interval), Some(last)) => {
+                self.clock.instant().saturating_duration_since(last) >= interval
+            }
+            _ => true,
+        };
+        if self.auto_update && self.unsaved && due {
+            self.save_jar();
+        }
+    }
+
+    /// Update cookie jar from response http headers, without saving the jar file
+    pub fn update_jar_unsaved(&mut self, headers: &HttpHeaders) {
         // GO through headers
         for line in headers.get_lower_vec("set-cookie") {
+            self.unsaved = true;
             // Get name and value
             let eq_index = line.find('=').unwrap_or(0);
             let sc_index = line.find(';').unwrap_or(0);
@@ -160,13 +323,23 @@ impl CookieJar {
                 })
                 .collect();
 
-            let expires: u64 = 0;
-            if let Some(_max_age) = elem.get(&"max-age".to_string()) {
-                let _secs = SystemTime::now()
-                    .duration_since(UNIX_EPOCH)
-                    .unwrap()
-                    .as_secs();
-                //expires = secs as u64 + max_age.parse::<u64>().unwrap();
+            // Expiry, Max-Age takes precedence over Expires, RFC 6265 section 5.3
+            let now = self.clock.unix_secs();
+            let expires = match (elem.get("max-age"), elem.get("expires")) {
+                (Some(max_age), _) => match max_age.parse::<i64>() {
+                    Ok(secs) if secs <= 0 => now,
+                    Ok(secs) => now.saturating_add(secs as u64),
+                    Err(_) => 0,
+                },
+                (None, Some(date)) => http_date::parse(date)
+                    .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+                    .map(|d| d.as_secs().max(1))
+                    .unwrap_or(0),
+                (None, None) => 0,
+            };
+            if expires != 0 && expires <= now {
+                self.delete(name.as_str());
+                continue;
             }
 
             let cookie = Cookie {
@@ -186,10 +359,73 @@ impl CookieJar {
             };
             self.set(&cookie);
         }
+    }
 
-        // Save jar file
-        if self.auto_update {
-            self.save_jar();
+    // Whether or not cookie is sent to URL
+    fn matches(cookie: &Cookie, uri: &Url) -> bool {
+        let host = uri.host_str().unwrap_or("");
+        (cookie.host == host || cookie.host == format!(".{}", host))
+            && uri.path().starts_with(&cookie.path)
+            && (!cookie.secure || uri.scheme() == "https")
+    }
+
+    // Record cookie as the most recently used
+    fn track(&mut self, name: &str) {
+        let host = match self.cookies.get(name) {
+            Some(r) => r.host.clone(),
+            None => return,
+        };
+
+        self.tick += 1;
+        self.used.insert(name.to_string(), self.tick);
+        self.lru.insert(self.tick, name.to_string());
+        self.domains
+            .entry(host)
+            .or_default()
+            .insert(self.tick, name.to_string());
+    }
+
+    // Remove cookie from usage tracking
+    fn untrack(&mut self, name: &str) {
+        let tick = match self.used.remove(name) {
+            Some(r) => r,
+            None => return,
+        };
+        self.lru.remove(&tick);
+
+        let host = self
+            .cookies
+            .get(name)
+            .map(|cookie| cookie.host.clone())
+            .unwrap_or_default();
+        if let Some(entries) = self.domains.get_mut(&host) {
+            entries.remove(&tick);
+            if entries.is_empty() {
+                self.domains.remove(&host);
+            }
+        }
+    }
+
+    // Evict least recently used cookies until within the per domain and total caps
+    fn evict(&mut self, host: &str) {
+        if let Some(max) = self.limits.max_per_domain {
+            while let Some(entries) = self.domains.get(host) {
+                if entries.len() <= max {
+                    break;
+                }
+                let name = entries.values().next().cloned().unwrap_or_default();
+                self.delete(&name);
+            }
+        }
+
+        if let Some(max) = self.limits.max_cookies {
+            while self.cookies.len() > max {
+                let name = match self.lru.values().next() {
+                    Some(r) => r.clone(),
+                    None => break,
+                };
+                self.delete(&name);
+            }
         }
     }
 
@@ -218,6 +454,8 @@ impl CookieJar {
             writeln!(file, "{}", Cookie::to_line(&cookie));
         }
 
+        self.last_saved = Some(self.clock.instant());
+        self.unsaved = false;
         Ok(())
     }
 }
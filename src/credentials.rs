@@ -0,0 +1,123 @@
+use super::HttpResponse;
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use std::fmt;
+use std::sync::Arc;
+use url::Url;
+
+/// Supplies credentials when a request is answered with 401 Unauthorized, eg. to refresh an
+/// expired OAuth token.  The request is then retried once with the credentials.  Set via
+/// HttpClientBuilder::credentials_provider().
+pub trait CredentialsProvider: fmt::Debug + Send + Sync {
+    /// Credentials for the challenge of the server, None to return the 401 response as is
+    fn credentials(&self, challenge: &AuthChallenge) -> Option<Credentials>;
+}
+
+impl<P: CredentialsProvider + ?Sized> CredentialsProvider for Arc<P> {
+    fn credentials(&self, challenge: &AuthChallenge) -> Option<Credentials> {
+        (**self).credentials(challenge)
+    }
+}
+
+/// Credentials sent within the Authorization header of the retried request
+#[derive(Clone, Debug, PartialEq)]
+pub enum Credentials {
+    Basic(String, String),
+    Bearer(String),
+    /// Complete header value, for other authentication schemes
+    Raw(String),
+}
+
+impl Credentials {
+    /// Value of the Authorization header
+    pub fn header_value(&self) -> String {
+        match self {
+            Credentials::Basic(user, password) => {
+                format!(
+                    "Basic {}",
+                    STANDARD.encode(format!("{}:{}", user, password))
+                )
+            }
+            Credentials::Bearer(token) => format!("Bearer {}", token),
+            Credentials::Raw(value) => value.to_string(),
+        }
+    }
+}
+
+/// Challenge of a 401 response, as per its WWW-Authenticate header
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct AuthChallenge {
+    /// URL of the request
+    pub url: String,
+    /// Host of the request, lowercased
+    pub host: String,
+    /// Authentication scheme asked for, eg. Bearer, empty if no WWW-Authenticate header was sent
+    pub scheme: String,
+    /// Protection space of the challenge, RFC 9110 section 11.5
+    pub realm: Option<String>,
+}
+
+impl AuthChallenge {
+    /// Get challenge of the 401 response to a request for url.  Only the first challenge is
+    /// used if the server offers several schemes.
+    pub fn from_response(url: &str, res: &HttpResponse) -> Self {
+        let header = res
+            .headers()
+            .get_lower_vec("www-authenticate")
+            .into_iter()
+            .next()
+            .unwrap_or_default();
+        let header = header.trim();
+        let (scheme, params) = header.split_once(' ').unwrap_or((header, ""));
+
+        Self {
+            url: url.to_string(),
+            host: Url::parse(url)
+                .ok()
+                .and_then(|uri| uri.host_str().map(|host| host.to_lowercase()))
+                .unwrap_or_default(),
+            scheme: scheme.to_string(),
+            realm: Self::param(params, "realm"),
+        }
+    }
+
+    // Value of auth parameter, either a token or a quoted string
+    fn param(params: &str, name: &str) -> Option<String> {
+        let mut rest = params;
+        while let Some((key, value)) = rest.split_once('=') {
+            let value = value.trim_start();
+            let (parsed, remaining) = match value.strip_prefix('"') {
+                Some(quoted) => {
+                    let mut parsed = String::new();
+                    let mut chars = quoted.char_indices();
+                    let mut end = quoted.len();
+                    while let Some((index, c)) = chars.next() {
+                        match c {
+                            '\\' => parsed.extend(chars.next().map(|(_, c)| c)),
+                            '"' => {
+                                end = index + 1;
+                                break;
+                            }
+                            _ => parsed.push(c),
+                        }
+                    }
+                    (parsed, &quoted[end..])
+                }
+                None => {
+                    let end = value.find(',').unwrap_or(value.len());
+                    (value[..end].trim().to_string(), &value[end..])
+                }
+            };
+
+            if key
+                .trim()
+                .trim_start_matches(',')
+                .trim()
+                .eq_ignore_ascii_case(name)
+            {
+                return Some(parsed);
+            }
+            rest = remaining.trim_start().trim_start_matches(',');
+        }
+        None
+    }
+}
@@ -0,0 +1,67 @@
+use crate::error::Error;
+use rustls::crypto::CryptoProvider;
+use rustls::pki_types::CertificateDer;
+use rustls::{ClientConfig, RootCertStore};
+use std::sync::Arc;
+
+#[cfg(not(any(feature = "ring", feature = "aws-lc-rs")))]
+compile_error!("Either the 'ring' or 'aws-lc-rs' feature must be enabled");
+
+/// Crypto provider used unless another is given via HttpClientBuilder::crypto_provider(),
+/// aws-lc-rs if the 'aws-lc-rs' feature is enabled, otherwise ring
+#[cfg(feature = "aws-lc-rs")]
+pub fn default_provider() -> Arc<CryptoProvider> {
+    Arc::new(rustls::crypto::aws_lc_rs::default_provider())
+}
+
+/// Crypto provider used unless another is given via HttpClientBuilder::crypto_provider(),
+/// aws-lc-rs if the 'aws-lc-rs' feature is enabled, otherwise ring
+#[cfg(all(feature = "ring", not(feature = "aws-lc-rs")))]
+pub fn default_provider() -> Arc<CryptoProvider> {
+    Arc::new(rustls::crypto::ring::default_provider())
+}
+
+/// Root store of the bundled Mozilla root certificates
+#[cfg(feature = "webpki-roots")]
+pub fn webpki_roots() -> RootCertStore {
+    let mut root_store = RootCertStore::empty();
+    root_store.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+    root_store
+}
+
+/// Empty root store, as the 'webpki-roots' feature is disabled.  Verifying https servers
+/// requires HttpClientBuilder::root_certificates() or native_roots().
+#[cfg(not(feature = "webpki-roots"))]
+pub fn webpki_roots() -> RootCertStore {
+    RootCertStore::empty()
+}
+
+/// Load root store from PEM encoded CA certificates, eg. of a private CA
+pub fn roots_from_pem(pem: &[u8]) -> Result<RootCertStore, Error> {
+    let certs = rustls_pemfile::certs(&mut &pem[..])
+        .collect::<Result<Vec<CertificateDer<'static>>, _>>()
+        .map_err(|e| Error::Tls(format!("Invalid CA certificate, {}", e)))?;
+    if certs.is_empty() {
+        return Err(Error::Tls(
+            "No CA certificates found within PEM".to_string(),
+        ));
+    }
+
+    let mut root_store = RootCertStore::empty();
+    for cert in certs {
+        root_store
+            .add(cert)
+            .map_err(|e| Error::Tls(format!("Invalid CA certificate, {}", e)))?;
+    }
+    Ok(root_store)
+}
+
+/// TLS client configuration with the given provider and root certificates.  Panics if the
+/// provider supports neither TLS 1.2 nor TLS 1.3, same as rustls::ClientConfig::builder().
+pub fn client_config(provider: Arc<CryptoProvider>, root_store: RootCertStore) -> ClientConfig {
+    ClientConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()
+        .expect("Crypto provider supports neither TLS 1.2 nor TLS 1.3")
+        .with_root_certificates(root_store)
+        .with_no_client_auth()
+}
@@ -0,0 +1,169 @@
+use super::HttpResponse;
+use crate::error::{Error, FileNotCreatedError};
+use rand::{distributions::Alphanumeric, thread_rng, Rng};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const INDEX_FILE: &str = "index.txt";
+
+#[derive(Clone, Debug)]
+pub struct CacheEntry {
+    pub url: String,
+    pub hash: String,
+    pub etag: String,
+    pub last_modified: String,
+}
+
+/// Content-addressed store of downloaded files.  Files are named by the SHA-256 hash of their
+/// contents, and an index maps each URL to its file along with the validators needed to
+/// revalidate it.
+#[derive(Clone, Debug)]
+pub struct DownloadCache {
+    dir: PathBuf,
+    entries: HashMap<String, CacheEntry>,
+}
+
+impl DownloadCache {
+    /// Open cache within directory, creating it if needed
+    pub fn new(dir: &str) -> Result<Self, Error> {
+        if let Err(e) = fs::create_dir_all(dir) {
+            return Err(Error::FileNotCreated(FileNotCreatedError {
+                filename: dir.to_string(),
+                error: e.to_string(),
+            }));
+        }
+
+        // Load index
+        let mut entries = HashMap::new();
+        let contents = fs::read_to_string(Path::new(dir).join(INDEX_FILE)).unwrap_or_default();
+        for line in contents.lines() {
+            let parts: Vec<&str> = line.split('\t').collect();
+            if parts.len() < 4 || line.starts_with('#') {
+                continue;
+            }
+            let entry = CacheEntry {
+                url: parts[0].to_string(),
+                hash: parts[1].to_string(),
+                etag: parts[2].to_string(),
+                last_modified: parts[3].to_string(),
+            };
+            entries.insert(entry.url.clone(), entry);
+        }
+
+        Ok(Self {
+            dir: PathBuf::from(dir),
+            entries,
+        })
+    }
+
+    /// Get entry for URL, only if its file still exists within the cache
+    pub fn get(&self, url: &str) -> Option<CacheEntry> {
+        self.entries
+            .get(url)
+            .filter(|entry| self.path(&entry.hash).exists())
+            .cloned()
+    }
+
+    /// Get path of a cached file by its hash
+    pub fn path(&self, hash: &str) -> PathBuf {
+        self.dir.join(hash)
+    }
+
+    /// Conditional request headers to revalidate the cached copy of URL, if any
+    pub fn conditional_headers(&self, url: &str) -> Vec<String> {
+        let mut headers = Vec::new();
+        if let Some(entry) = self.get(url) {
+            if !entry.etag.is_empty() {
+                headers.push(format!("If-None-Match: {}", entry.etag));
+            }
+            if !entry.last_modified.is_empty() {
+                headers.push(format!("If-Modified-Since: {}", entry.last_modified));
+            }
+        }
+        headers
+    }
+
+    /// Get temporary file to download into
+    pub fn tmp_path(&self) -> String {
+        let name: String = thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(16)
+            .map(|c| c as char)
+            .collect();
+        self.dir
+            .join(format!(".tmp-{}", name))
+            .to_string_lossy()
+            .to_string()
+    }
+
+    /// Complete a download into tmp_file, moving it to its hash-named location and updating the
+    /// index, or reusing the cached file on 304 Not Modified.
+    pub fn complete(
+        &mut self,
+        url: &str,
+        res: &HttpResponse,
+        tmp_file: &str,
+    ) -> Result<PathBuf, Error> {
+        if res.status_code() == 304 {
+            let _ = fs::remove_file(tmp_file);
+            if let Some(entry) = self.get(url) {
+                return Ok(self.path(&entry.hash));
+            }
+        }
+
+        if !(200..300).contains(&res.status_code()) {
+            let _ = fs::remove_file(tmp_file);
+            return Err(Error::UnexpectedStatus(res.status_code(), url.to_string()));
+        }
+
+        // Move into place, identical content may already exist under another URL
+        let hash = res.body_hash().unwrap_or_default();
+        let dest = self.path(&hash);
+        if dest.exists() {
+            let _ = fs::remove_file(tmp_file);
+        } else if let Err(e) = fs::rename(tmp_file, &dest) {
+            return Err(Error::FileNotCreated(FileNotCreatedError {
+                filename: dest.to_string_lossy().to_string(),
+                error: e.to_string(),
+            }));
+        }
+
+        // Update index
+        let headers = res.headers();
+        self.entries.insert(
+            url.to_string(),
+            CacheEntry {
+                url: url.to_string(),
+                hash,
+                etag: headers.get_lower("etag").unwrap_or_default(),
+                last_modified: headers.get_lower("last-modified").unwrap_or_default(),
+            },
+        );
+        self.save()?;
+
+        Ok(dest)
+    }
+
+    /// Save index file
+    pub fn save(&self) -> Result<(), Error> {
+        let mut lines = vec![
+            "# Auto-generated by atlas-http (https://crates.io/crates/atlas-http)".to_string(),
+        ];
+        for entry in self.entries.values() {
+            lines.push(format!(
+                "{}\t{}\t{}\t{}",
+                entry.url, entry.hash, entry.etag, entry.last_modified
+            ));
+        }
+
+        let index_file = self.dir.join(INDEX_FILE);
+        if let Err(e) = fs::write(&index_file, lines.join("\n") + "\n") {
+            return Err(Error::FileNotCreated(FileNotCreatedError {
+                filename: index_file.to_string_lossy().to_string(),
+                error: e.to_string(),
+            }));
+        }
+        Ok(())
+    }
+}
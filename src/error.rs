@@ -1,6 +1,8 @@
 //use std::error::Error;
-use super::HttpRequest;
+use super::{HttpRequest, HttpResponse};
+use crate::timings::{Phase, Timings};
 use std::fmt;
+use std::time::{Duration, Instant};
 
 #[derive(Debug)]
 pub enum Error {
@@ -8,12 +10,28 @@ pub enum Error {
     InvalidUri(String),
     ProtoNotSupported(String),
     NoConnect(String),
+    Dns(String, String),
     NoRead(InvalidResponseError),
     NoWrite(String),
     InvalidFirstLine(InvalidFirstLineError),
     Io(std::io::Error),
     FileNotExists(String),
     FileNotCreated(FileNotCreatedError),
+    Aborted(String),
+    Tls(String),
+    UnexpectedStatus(u16, String),
+    Extract(String),
+    TooManyRedirects(String),
+    RedirectLoop(Vec<String>),
+    MalformedHeader(String, String),
+    AmbiguousFraming(String, String),
+    TtfbTimeout(String),
+    Json(JsonError),
+    Timeout(TimeoutError),
+    Proxy(ProxyError),
+    Nonconformant(String, Vec<String>),
+    UnknownJar(String),
+    Batch(BatchError),
     Custom(String),
 }
 
@@ -34,20 +52,241 @@ pub struct FileNotCreatedError {
     pub error: String,
 }
 
+#[derive(Debug)]
+pub struct TimeoutError {
+    pub url: String,
+    pub phase: Phase,
+    pub elapsed: Duration,
+    pub timings: Timings,
+}
+
+#[derive(Debug)]
+pub struct ProxyError {
+    pub proxy: String,
+    pub target: String,
+    pub failure: ProxyFailure,
+    pub message: String,
+}
+
+/// Cause of a failed request through a proxy.  Connect, Auth and Protocol are failures of the
+/// proxy itself, the others are the proxy failing to reach the origin server.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ProxyFailure {
+    /// Unable to connect to the proxy
+    Connect,
+    /// Proxy rejected the credentials, or requires credentials that were not given
+    Auth,
+    /// Proxy sent a malformed response or closed the connection
+    Protocol,
+    /// SOCKS5 general failure
+    GeneralFailure,
+    /// Connection to the origin not allowed by the rules of the proxy, SOCKS5 or HTTP 403
+    NotAllowed,
+    /// SOCKS5 network unreachable
+    NetworkUnreachable,
+    /// SOCKS5 host unreachable
+    HostUnreachable,
+    /// SOCKS5 connection refused by the origin
+    ConnectionRefused,
+    /// SOCKS5 TTL expired
+    TtlExpired,
+    /// SOCKS5 command not supported
+    CommandNotSupported,
+    /// SOCKS5 address type not supported
+    AddressTypeNotSupported,
+    /// HTTP proxy responded to CONNECT with another status, eg. 502 or 504
+    Status(u16),
+}
+
+impl ProxyFailure {
+    /// Failure from a SOCKS5 reply code, RFC 1928 section 6
+    pub fn from_socks5_reply(code: u8) -> Self {
+        match code {
+            0x02 => ProxyFailure::NotAllowed,
+            0x03 => ProxyFailure::NetworkUnreachable,
+            0x04 => ProxyFailure::HostUnreachable,
+            0x05 => ProxyFailure::ConnectionRefused,
+            0x06 => ProxyFailure::TtlExpired,
+            0x07 => ProxyFailure::CommandNotSupported,
+            0x08 => ProxyFailure::AddressTypeNotSupported,
+            _ => ProxyFailure::GeneralFailure,
+        }
+    }
+
+    /// Failure from the status of a HTTP proxy's response to CONNECT
+    pub fn from_status(status: u16) -> Self {
+        match status {
+            407 => ProxyFailure::Auth,
+            403 => ProxyFailure::NotAllowed,
+            _ => ProxyFailure::Status(status),
+        }
+    }
+
+    /// Whether or not the proxy itself failed, as opposed to failing to reach the origin, eg. to
+    /// decide whether to rotate to another proxy
+    pub fn is_proxy_failure(&self) -> bool {
+        matches!(
+            self,
+            ProxyFailure::Connect | ProxyFailure::Auth | ProxyFailure::Protocol
+        )
+    }
+}
+
+#[derive(Debug)]
+pub struct JsonError {
+    pub url: String,
+    pub error: String,
+    pub body: Vec<u8>,
+}
+
+/// Failures of a batch of requests, eg. via send_all(), each with its position within the batch.
+/// Responses of the requests that succeeded are kept at the same positions.
+#[derive(Debug)]
+pub struct BatchError {
+    pub failures: Vec<BatchFailure>,
+    pub responses: Vec<Option<HttpResponse>>,
+}
+
+#[derive(Debug)]
+pub struct BatchFailure {
+    pub index: usize,
+    pub request: String,
+    pub error: Error,
+}
+
+impl BatchError {
+    /// Gather results of a batch, given along with a summary of each request such as
+    /// "GET https://example.com/".  Returns all responses in order if none failed.
+    pub fn collect(
+        results: Vec<(String, Result<HttpResponse, Error>)>,
+    ) -> Result<Vec<HttpResponse>, Error> {
+        let mut failures = Vec::new();
+        let mut responses = Vec::new();
+        for (index, (request, result)) in results.into_iter().enumerate() {
+            match result {
+                Ok(res) => responses.push(Some(res)),
+                Err(error) => {
+                    failures.push(BatchFailure {
+                        index,
+                        request,
+                        error,
+                    });
+                    responses.push(None);
+                }
+            }
+        }
+
+        if failures.is_empty() {
+            return Ok(responses.into_iter().flatten().collect());
+        }
+        Err(Error::Batch(BatchError {
+            failures,
+            responses,
+        }))
+    }
+
+    /// Number of requests within the batch
+    pub fn total(&self) -> usize {
+        self.responses.len()
+    }
+}
+
+impl fmt::Display for BatchFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{} {}: {}", self.index, self.request, self.error)
+    }
+}
+
+impl Error {
+    /// Timeout during the given phase, elapsed time and timings are added via with_timings()
+    pub fn timeout(url: &str, phase: Phase) -> Self {
+        Error::Timeout(TimeoutError {
+            url: url.to_string(),
+            phase,
+            elapsed: Duration::ZERO,
+            timings: Timings::default(),
+        })
+    }
+
+    /// Failure of a request through the given proxy to the target host:port
+    pub fn proxy(proxy: &str, target: &str, failure: ProxyFailure, message: &str) -> Self {
+        Error::Proxy(ProxyError {
+            proxy: proxy.to_string(),
+            target: target.to_string(),
+            failure,
+            message: message.to_string(),
+        })
+    }
+
+    /// Add elapsed time and timings of the request to a timeout error, other errors are returned
+    /// as is.  Header timeouts before the first byte arrived are reported as the TTFB phase.
+    pub fn with_timings(self, started: Instant, timings: &Timings) -> Self {
+        match self {
+            Error::Timeout(mut err) => {
+                if err.phase == Phase::Header && timings.ttfb.is_none() {
+                    err.phase = Phase::Ttfb;
+                }
+                err.elapsed = started.elapsed();
+                err.timings = Timings {
+                    total: err.elapsed,
+                    ..*timings
+                };
+                Error::Timeout(err)
+            }
+            other => other,
+        }
+    }
+}
+
+impl fmt::Display for ProxyFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProxyFailure::Connect => write!(f, "unable to connect to proxy"),
+            ProxyFailure::Auth => write!(f, "proxy authentication failed"),
+            ProxyFailure::Protocol => write!(f, "invalid response from proxy"),
+            ProxyFailure::GeneralFailure => write!(f, "general failure"),
+            ProxyFailure::NotAllowed => write!(f, "connection not allowed by proxy"),
+            ProxyFailure::NetworkUnreachable => write!(f, "network unreachable"),
+            ProxyFailure::HostUnreachable => write!(f, "host unreachable"),
+            ProxyFailure::ConnectionRefused => write!(f, "connection refused"),
+            ProxyFailure::TtlExpired => write!(f, "TTL expired"),
+            ProxyFailure::CommandNotSupported => write!(f, "command not supported"),
+            ProxyFailure::AddressTypeNotSupported => write!(f, "address type not supported"),
+            ProxyFailure::Status(status) => write!(f, "proxy responded with status {}", status),
+        }
+    }
+}
+
 impl std::error::Error for Error {}
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Error::InvalidResponse(err) => write!(f, "InvalidResponse: Did not receive valid HTTP response from {}", err.url),
             Error::InvalidUri(url) => write!(f, "InvalidUri: The supplied URL is invalid, {}", url),
-            Error::ProtoNotSupported(proto) => write!(f, "The '{}://' protocol is not supported.  Only the https:// and http:// protocols are supported.", proto),
+            Error::ProtoNotSupported(proto) => write!(f, "The '{}://' protocol is not supported.  Only the https:// and http:// protocols, plus those registered via HttpClientBuilder::allow_scheme(), are supported.", proto),
             Error::NoConnect(host) => write!(f, "Unable to connect to server at {}", host),
+            Error::Dns(host, err) => write!(f, "Unable to resolve host {}, {}", host, err),
             Error::NoRead(err) => write!(f, "Unable to read from server at URL {}, server error: {}", err.url, err.response),
             Error::NoWrite(err) => write!(f, "Unable to write to server, server error: {}", err),
             Error::InvalidFirstLine(err) => write!(f, "Received malformed first line within response: {}", err.first_line),
             Error::Io(err) => write!(f, "HTTP IO: {}", err),
             Error::FileNotExists(file_path) => write!(f, "Unable to upload file, as file does not exist at {}", file_path),
         Error::FileNotCreated(err) => write!(f, "Unable to create file at {}, error: {}", err.filename, err.error),
+            Error::Aborted(url) => write!(f, "Request to {} aborted by on_headers callback", url),
+            Error::Tls(err) => write!(f, "TLS error: {}", err),
+            Error::UnexpectedStatus(status, url) => write!(f, "Received unexpected status {} from {}", status, url),
+            Error::Extract(err) => write!(f, "Unable to extract archive, {}", err),
+            Error::TooManyRedirects(url) => write!(f, "Too many redirects, last redirected to {}", url),
+            Error::RedirectLoop(cycle) => write!(f, "Redirect loop detected: {}", cycle.join(" -> ")),
+            Error::MalformedHeader(url, line) => write!(f, "Received malformed header from {}: {}", url, line),
+            Error::AmbiguousFraming(url, reason) => write!(f, "Rejected response from {} with ambiguous framing, {}", url, reason),
+            Error::TtfbTimeout(url) => write!(f, "Timed out waiting for the first byte of the response from {}", url),
+            Error::Timeout(err) => write!(f, "Request to {} timed out during {} after {:.2?}", err.url, err.phase, err.elapsed),
+            Error::Proxy(err) => write!(f, "Request to {} through proxy {} failed, {}", err.target, err.proxy, err.message),
+            Error::Nonconformant(url, violations) => write!(f, "Request to {} violates the HTTP RFCs: {}", url, violations.join("; ")),
+            Error::UnknownJar(name) => write!(f, "No cookie jar named '{}' is registered with the client", name),
+            Error::Json(err) => write!(f, "Unable to parse JSON response from {}, error: {}", err.url, err.error),
+            Error::Batch(err) => write!(f, "{} of {} requests within batch failed: {}", err.failures.len(), err.total(), err.failures.iter().map(|fail| fail.to_string()).collect::<Vec<String>>().join("; ")),
             Error::Custom(err) => write!(f, "HTTP Error: {}", err)
         }
     }
@@ -0,0 +1,105 @@
+use super::HttpHeaders;
+use crate::error::Error;
+use std::io::Read;
+use std::path::Path;
+
+/// Archive formats supported by download_and_extract()
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ArchiveKind {
+    Zip,
+    Tar,
+    TarGz,
+}
+
+impl ArchiveKind {
+    /// Detect archive format from the URL's file extension, falling back to the Content-Type header
+    pub fn detect(url: &str, headers: &HttpHeaders) -> Option<Self> {
+        let path = url.split(['?', '#']).next().unwrap_or("").to_lowercase();
+        if path.ends_with(".zip") {
+            return Some(Self::Zip);
+        } else if path.ends_with(".tar.gz") || path.ends_with(".tgz") {
+            return Some(Self::TarGz);
+        } else if path.ends_with(".tar") {
+            return Some(Self::Tar);
+        }
+
+        let content_type = headers
+            .get_lower("content-type")
+            .unwrap_or_default()
+            .to_lowercase();
+        match content_type.split(';').next().unwrap_or("").trim() {
+            "application/zip" | "application/x-zip-compressed" => Some(Self::Zip),
+            "application/gzip" | "application/x-gzip" | "application/x-tgz" => Some(Self::TarGz),
+            "application/x-tar" => Some(Self::Tar),
+            _ => None,
+        }
+    }
+}
+
+/// Extract archive from reader into directory.  Entries with absolute paths or paths escaping
+/// the directory are skipped.
+pub fn extract(reader: &mut dyn Read, kind: ArchiveKind, dir: &Path) -> Result<(), Error> {
+    if let Err(e) = std::fs::create_dir_all(dir) {
+        return Err(Error::Extract(format!(
+            "Unable to create directory {}, {}",
+            dir.display(),
+            e
+        )));
+    }
+
+    match kind {
+        ArchiveKind::Zip => extract_zip(reader, dir),
+        ArchiveKind::Tar => extract_tar(reader, dir),
+        ArchiveKind::TarGz => extract_tar(&mut flate2::read::GzDecoder::new(reader), dir),
+    }
+}
+
+#[cfg(feature = "tar")]
+fn extract_tar(reader: &mut dyn Read, dir: &Path) -> Result<(), Error> {
+    // unpack() refuses entries outside of dir
+    tar::Archive::new(reader)
+        .unpack(dir)
+        .map_err(|e| Error::Extract(e.to_string()))
+}
+
+#[cfg(not(feature = "tar"))]
+fn extract_tar(_reader: &mut dyn Read, _dir: &Path) -> Result<(), Error> {
+    Err(Error::Extract(
+        "Tar archives require the 'tar' feature of atlas-http".to_string(),
+    ))
+}
+
+#[cfg(feature = "zip")]
+fn extract_zip(mut reader: &mut dyn Read, dir: &Path) -> Result<(), Error> {
+    let extract_err = |e: &dyn std::fmt::Display| Error::Extract(e.to_string());
+
+    while let Some(mut file) =
+        zip::read::read_zipfile_from_stream(&mut reader).map_err(|e| extract_err(&e))?
+    {
+        // Skip entries escaping the directory
+        let dest = match file.enclosed_name() {
+            Some(name) => dir.join(name),
+            None => continue,
+        };
+
+        if file.is_dir() {
+            std::fs::create_dir_all(&dest).map_err(|e| extract_err(&e))?;
+            continue;
+        }
+
+        if let Some(parent) = dest.parent() {
+            std::fs::create_dir_all(parent).map_err(|e| extract_err(&e))?;
+        }
+        let mut fh = std::fs::File::create(&dest).map_err(|e| extract_err(&e))?;
+        std::io::copy(&mut file, &mut fh).map_err(|e| extract_err(&e))?;
+    }
+
+    Ok(())
+}
+
+#[cfg(not(feature = "zip"))]
+fn extract_zip(_reader: &mut dyn Read, _dir: &Path) -> Result<(), Error> {
+    Err(Error::Extract(
+        "Zip archives require the 'zip' feature of atlas-http".to_string(),
+    ))
+}
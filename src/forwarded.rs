@@ -0,0 +1,199 @@
+use super::HttpHeaders;
+use crate::no_proxy;
+use std::net::{IpAddr, SocketAddr};
+
+/// Proxies whose Forwarded and X-Forwarded-* headers are believed, as exact IP addresses or
+/// CIDR ranges such as "10.0.0.0/8".  Headers from any other peer may be forged by the client.
+#[derive(Clone, Debug, Default)]
+pub struct TrustedProxies {
+    entries: Vec<(IpAddr, u32)>,
+}
+
+impl TrustedProxies {
+    /// Create from list of entries, skipping any which are not valid IP addresses or CIDR ranges
+    pub fn new(entries: &[&str]) -> Self {
+        let mut trusted = Self::default();
+        for entry in entries {
+            trusted.add(entry);
+        }
+        trusted
+    }
+
+    /// Trust loopback and private network addresses, eg. behind a load balancer within the
+    /// same network
+    pub fn private() -> Self {
+        Self::new(&[
+            "127.0.0.0/8",
+            "10.0.0.0/8",
+            "172.16.0.0/12",
+            "192.168.0.0/16",
+            "::1",
+            "fc00::/7",
+        ])
+    }
+
+    /// Add entry, returning false if it is not a valid IP address or CIDR range
+    pub fn add(&mut self, entry: &str) -> bool {
+        let entry = entry.trim();
+        let (network, bits) = match entry.split_once('/') {
+            Some((network, bits)) => (network, bits.parse::<u32>().ok()),
+            None => (entry, None),
+        };
+        let network = match network.parse::<IpAddr>() {
+            Ok(r) => r,
+            Err(_) => return false,
+        };
+        let bits = bits.unwrap_or(if network.is_ipv4() { 32 } else { 128 });
+        self.entries.push((network, bits));
+        true
+    }
+
+    /// Whether or not the address is of a trusted proxy
+    pub fn contains(&self, ip: &IpAddr) -> bool {
+        self.entries
+            .iter()
+            .any(|(network, bits)| no_proxy::in_range(*ip, *network, *bits))
+    }
+}
+
+/// Originating client of a request received through proxies, as per the Forwarded or
+/// X-Forwarded-* headers of trusted proxies
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ClientInfo {
+    /// IP address of the client, None if unknown or obfuscated
+    pub ip: Option<IpAddr>,
+    /// Protocol the client used, eg. https
+    pub proto: Option<String>,
+    /// Host the client requested
+    pub host: Option<String>,
+}
+
+// Single hop within the Forwarded or X-Forwarded-* headers
+#[derive(Default)]
+struct Hop {
+    ip: Option<IpAddr>,
+    proto: Option<String>,
+    host: Option<String>,
+}
+
+/// Get the originating client from the headers of a request received from peer.  Hops are
+/// walked from the nearest one, the first not from a trusted proxy being the client.  The
+/// Forwarded header of RFC 7239 takes precedence over X-Forwarded-For, -Proto and -Host.
+pub fn client_info(
+    headers: &HttpHeaders,
+    peer: Option<SocketAddr>,
+    trusted: &TrustedProxies,
+) -> ClientInfo {
+    let peer_ip = peer.map(|addr| addr.ip());
+    let direct = ClientInfo {
+        ip: peer_ip,
+        proto: None,
+        host: headers.get_lower("host"),
+    };
+    if !peer_ip.is_some_and(|ip| trusted.contains(&ip)) {
+        return direct;
+    }
+
+    let hops = match headers.has_lower("forwarded") {
+        true => parse_forwarded(headers),
+        false => parse_x_forwarded(headers),
+    };
+    if hops.is_empty() {
+        return direct;
+    }
+
+    // Nearest hop not from a trusted proxy, or the furthest one if all are trusted
+    let index = hops
+        .iter()
+        .rposition(|hop| !hop.ip.is_some_and(|ip| trusted.contains(&ip)))
+        .unwrap_or(0);
+    let hop = &hops[index];
+    ClientInfo {
+        ip: hop.ip,
+        proto: hop
+            .proto
+            .clone()
+            .or_else(|| hops.iter().find_map(|h| h.proto.clone())),
+        host: hop
+            .host
+            .clone()
+            .or_else(|| hops.iter().find_map(|h| h.host.clone()))
+            .or(direct.host),
+    }
+}
+
+// Parse hops of the Forwarded header, eg. for=192.0.2.60;proto=http, for="[2001:db8::17]:4711"
+fn parse_forwarded(headers: &HttpHeaders) -> Vec<Hop> {
+    let mut hops = Vec::new();
+    for value in headers.get_lower_vec("forwarded") {
+        for element in value.split(',') {
+            let mut hop = Hop::default();
+            for pair in element.split(';') {
+                let (key, val) = match pair.split_once('=') {
+                    Some(r) => r,
+                    None => continue,
+                };
+                let val = val.trim().trim_matches('"');
+                match key.trim().to_lowercase().as_str() {
+                    "for" => hop.ip = parse_node(val),
+                    "proto" => hop.proto = Some(val.to_lowercase()),
+                    "host" => hop.host = Some(val.to_string()),
+                    _ => {}
+                }
+            }
+            hops.push(hop);
+        }
+    }
+    hops
+}
+
+// Parse hops of the X-Forwarded-For header, with the X-Forwarded-Proto and -Host values of the
+// same position if they list as many
+fn parse_x_forwarded(headers: &HttpHeaders) -> Vec<Hop> {
+    let list = |name: &str| -> Vec<String> {
+        headers
+            .get_lower_vec(name)
+            .iter()
+            .flat_map(|value| {
+                value
+                    .split(',')
+                    .map(|v| v.trim().to_string())
+                    .collect::<Vec<String>>()
+            })
+            .filter(|v| !v.is_empty())
+            .collect()
+    };
+    let (ips, protos, hosts) = (
+        list("x-forwarded-for"),
+        list("x-forwarded-proto"),
+        list("x-forwarded-host"),
+    );
+    let pick = |values: &Vec<String>, i: usize| match values.len() == ips.len() {
+        true => values.get(i).cloned(),
+        false => values.first().cloned(),
+    };
+
+    ips.iter()
+        .enumerate()
+        .map(|(i, ip)| Hop {
+            ip: parse_node(ip),
+            proto: pick(&protos, i).map(|proto| proto.to_lowercase()),
+            host: pick(&hosts, i),
+        })
+        .collect()
+}
+
+// Parse node of a hop, eg. 192.0.2.60, 192.0.2.60:4711 or [2001:db8::17]:4711.  Unknown and
+// obfuscated nodes give None.
+fn parse_node(node: &str) -> Option<IpAddr> {
+    if let Ok(ip) = node.parse::<IpAddr>() {
+        return Some(ip);
+    }
+    if let Ok(addr) = node.parse::<SocketAddr>() {
+        return Some(addr.ip());
+    }
+    node.trim_start_matches('[')
+        .split(']')
+        .next()
+        .and_then(|ip| ip.parse::<IpAddr>().ok())
+}
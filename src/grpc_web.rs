@@ -0,0 +1,120 @@
+use super::{HttpBody, HttpHeaders, HttpRequest, HttpResponse};
+use crate::error::{Error, InvalidResponseError};
+
+pub const CONTENT_TYPE: &str = "application/grpc-web+proto";
+
+#[derive(Clone, Debug)]
+pub struct GrpcWebResponse {
+    pub http_status: u16,
+    pub messages: Vec<Vec<u8>>,
+    pub status: u32,
+    pub message: String,
+    pub trailers: HttpHeaders,
+}
+
+impl GrpcWebResponse {
+    /// Parse data and trailer frames from the body of a gRPC-web response
+    pub fn from_response(res: &HttpResponse) -> Result<Self, Error> {
+        let url = res
+            .effective_request()
+            .map(|req| req.url)
+            .unwrap_or_default();
+        let body = res.body_bytes();
+
+        // Go through frames
+        let mut messages = Vec::new();
+        let mut trailer_lines = Vec::new();
+        let mut pos = 0;
+        while pos < body.len() {
+            if pos + 5 > body.len() {
+                return Err(Error::InvalidResponse(InvalidResponseError {
+                    url,
+                    response: "Truncated gRPC-web frame header".to_string(),
+                }));
+            }
+            let flag = body[pos];
+            let length =
+                u32::from_be_bytes([body[pos + 1], body[pos + 2], body[pos + 3], body[pos + 4]])
+                    as usize;
+            pos += 5;
+
+            if pos + length > body.len() {
+                return Err(Error::InvalidResponse(InvalidResponseError {
+                    url,
+                    response: "Truncated gRPC-web frame".to_string(),
+                }));
+            }
+            let frame = &body[pos..pos + length];
+            pos += length;
+
+            // Trailer frame
+            if flag & 0x80 == 0x80 {
+                for line in String::from_utf8_lossy(frame).split("\r\n") {
+                    if !line.trim().is_empty() {
+                        trailer_lines.push(line.trim().to_string());
+                    }
+                }
+            } else {
+                messages.push(frame.to_vec());
+            }
+        }
+
+        // Trailers-only responses send the status within the HTTP headers
+        let trailers = HttpHeaders::from_vec(&trailer_lines);
+        let status_hdr = trailers
+            .get_lower("grpc-status")
+            .or_else(|| res.headers().get_lower("grpc-status"));
+        let message = trailers
+            .get_lower("grpc-message")
+            .or_else(|| res.headers().get_lower("grpc-message"))
+            .unwrap_or_default();
+
+        // gRPC status 2 is UNKNOWN, used when the server never reported one
+        let status = status_hdr
+            .and_then(|s| s.trim().parse::<u32>().ok())
+            .unwrap_or(2);
+
+        Ok(Self {
+            http_status: res.status_code(),
+            messages,
+            status,
+            message: urlencoding::decode(&message)
+                .map(|m| m.to_string())
+                .unwrap_or(message),
+            trailers,
+        })
+    }
+
+    /// Whether or not the call completed with gRPC status OK
+    pub fn is_ok(&self) -> bool {
+        self.status == 0
+    }
+
+    /// Get first message returned, for unary calls
+    pub fn message_bytes(&self) -> Option<Vec<u8>> {
+        self.messages.first().cloned()
+    }
+}
+
+/// Prefix message with the gRPC length-prefixed framing, flag byte plus big-endian length
+pub fn encode_message(message: &[u8]) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(message.len() + 5);
+    frame.push(0x00);
+    frame.extend_from_slice(&(message.len() as u32).to_be_bytes());
+    frame.extend_from_slice(message);
+    frame
+}
+
+/// Build request for a unary gRPC-web call, url is the full method path, eg.
+/// https://example.com/package.Service/Method
+pub fn request(url: &str, message: &[u8]) -> HttpRequest {
+    let content_type = format!("Content-Type: {}", CONTENT_TYPE);
+    let accept = format!("Accept: {}", CONTENT_TYPE);
+    let headers = vec![content_type.as_str(), accept.as_str(), "X-Grpc-Web: 1"];
+    HttpRequest::new(
+        "POST",
+        url,
+        &headers,
+        &HttpBody::from_raw(&encode_message(message)),
+    )
+}
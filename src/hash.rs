@@ -0,0 +1,86 @@
+use sha2::{Digest, Sha256};
+use std::io::Read;
+
+/// Algorithm used to hash response bodies while they are being read
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HashAlgorithm {
+    Sha256,
+    #[cfg(feature = "blake3")]
+    Blake3,
+}
+
+/// Incremental hasher for one of the supported algorithms
+#[derive(Clone)]
+pub enum BodyHasher {
+    Sha256(Sha256),
+    #[cfg(feature = "blake3")]
+    Blake3(Box<blake3::Hasher>),
+}
+
+impl BodyHasher {
+    pub fn new(algorithm: HashAlgorithm) -> Self {
+        match algorithm {
+            HashAlgorithm::Sha256 => Self::Sha256(Sha256::new()),
+            #[cfg(feature = "blake3")]
+            HashAlgorithm::Blake3 => Self::Blake3(Box::new(blake3::Hasher::new())),
+        }
+    }
+
+    /// Add data to the hash
+    pub fn update(&mut self, data: &[u8]) {
+        match self {
+            Self::Sha256(hasher) => hasher.update(data),
+            #[cfg(feature = "blake3")]
+            Self::Blake3(hasher) => {
+                hasher.update(data);
+            }
+        }
+    }
+
+    /// Finish hashing, and return lowercase hex digest
+    pub fn finalize(self) -> String {
+        let digest: Vec<u8> = match self {
+            Self::Sha256(hasher) => hasher.finalize().to_vec(),
+            #[cfg(feature = "blake3")]
+            Self::Blake3(hasher) => hasher.finalize().as_bytes().to_vec(),
+        };
+        digest.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+}
+
+/// Reader that hashes all bytes passing through it, if an algorithm is given
+pub struct HashingReader<R: Read> {
+    inner: R,
+    hasher: Option<BodyHasher>,
+}
+
+impl<R: Read> HashingReader<R> {
+    pub fn new(inner: R, algorithm: Option<HashAlgorithm>) -> Self {
+        Self {
+            inner,
+            hasher: algorithm.map(BodyHasher::new),
+        }
+    }
+
+    /// Get hex digest of all bytes read so far, None if not hashing
+    pub fn digest(&self) -> Option<String> {
+        self.hasher.clone().map(|hasher| hasher.finalize())
+    }
+}
+
+impl<R: Read> Read for HashingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let bytes_read = self.inner.read(buf)?;
+        if let Some(hasher) = self.hasher.as_mut() {
+            hasher.update(&buf[..bytes_read]);
+        }
+        Ok(bytes_read)
+    }
+}
+
+/// Hash data in one pass, returning lowercase hex digest
+pub fn digest(algorithm: HashAlgorithm, data: &[u8]) -> String {
+    let mut hasher = BodyHasher::new(algorithm);
+    hasher.update(data);
+    hasher.finalize()
+}
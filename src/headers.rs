@@ -158,6 +158,13 @@ impl HttpHeaders {
         self.pairs.remove(&key.to_string());
     }
 
+    /// Delete header, case-insensitive
+    pub fn delete_lower(&mut self, key: &str) {
+        if let Some(hdr_key) = self.lower_map.remove(&key.to_lowercase()) {
+            self.pairs.remove(&hdr_key);
+        }
+    }
+
     /// Clear / purge all headers
     pub fn clear(&mut self) {
         self.pairs.clear();
@@ -0,0 +1,75 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const MONTHS: [&str; 12] = [
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+
+/// Parse HTTP date as found within the Date, Last-Modified and Expires headers.  Accepts the
+/// IMF-fixdate, RFC 850 and asctime formats, RFC 9110 section 5.6.7.
+pub fn parse(value: &str) -> Option<SystemTime> {
+    let value = value.trim();
+
+    // Drop day of week, IMF-fixdate and RFC 850 separate it by a comma
+    let (rest, is_asctime) = match value.split_once(',') {
+        Some((_, rest)) => (rest, false),
+        None => (value.split_once(' ')?.1, true),
+    };
+    let parts: Vec<&str> = rest
+        .split(|c| c == ' ' || c == '-')
+        .filter(|part| !part.is_empty())
+        .collect();
+    if parts.len() < 4 {
+        return None;
+    }
+
+    // Sun, 06 Nov 1994 08:49:37 GMT / Sunday, 06-Nov-94 08:49:37 GMT / Sun Nov  6 08:49:37 1994
+    let (day, month, year, time) = match is_asctime {
+        true => (parts[1], parts[0], parts[3], parts[2]),
+        false => (parts[0], parts[1], parts[2], parts[3]),
+    };
+
+    // Get date, two digit years as per RFC 6265 section 5.1.1
+    let day = day.parse::<i64>().ok().filter(|d| (1..=31).contains(d))?;
+    let month = month.get(..3)?.to_lowercase();
+    let month = MONTHS.iter().position(|m| *m == month)? as i64 + 1;
+    let year = match year.parse::<i64>().ok()? {
+        y if y < 70 => y + 2000,
+        y if y < 100 => y + 1900,
+        y => y,
+    };
+
+    // Get time
+    let time: Vec<i64> = time
+        .split(':')
+        .map(|part| part.parse::<i64>().ok())
+        .collect::<Option<Vec<i64>>>()?;
+    let (hour, minute, second) = match time[..] {
+        [h, m, s] if h < 24 && m < 60 && s <= 60 => (h, m, s),
+        _ => return None,
+    };
+
+    let secs = days_from_civil(year, month, day) * 86400 + hour * 3600 + minute * 60 + second;
+    u64::try_from(secs)
+        .ok()
+        .map(|secs| UNIX_EPOCH + Duration::from_secs(secs))
+}
+
+/// Shift time by the given number of seconds, eg. by HttpResponse::clock_skew() to get the
+/// current time of a server whose clock differs from the local one
+pub fn skewed(time: SystemTime, skew: i64) -> SystemTime {
+    let offset = Duration::from_secs(skew.unsigned_abs());
+    match skew < 0 {
+        true => time.checked_sub(offset).unwrap_or(UNIX_EPOCH),
+        false => time + offset,
+    }
+}
+
+// Days since the epoch of the given date
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year - era * 400;
+    let doy = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
@@ -0,0 +1,63 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Operation of a JSON Patch document, RFC 6902.  Paths are JSON Pointers such as /items/0/name.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum PatchOp {
+    Add { path: String, value: Value },
+    Remove { path: String },
+    Replace { path: String, value: Value },
+    Move { from: String, path: String },
+    Copy { from: String, path: String },
+    Test { path: String, value: Value },
+}
+
+impl PatchOp {
+    /// Add value at path, appending to arrays with a path ending in /-
+    pub fn add(path: &str, value: Value) -> Self {
+        PatchOp::Add {
+            path: path.to_string(),
+            value,
+        }
+    }
+
+    /// Remove value at path
+    pub fn remove(path: &str) -> Self {
+        PatchOp::Remove {
+            path: path.to_string(),
+        }
+    }
+
+    /// Replace value at path
+    pub fn replace(path: &str, value: Value) -> Self {
+        PatchOp::Replace {
+            path: path.to_string(),
+            value,
+        }
+    }
+
+    /// Move value from one path to another
+    pub fn move_to(from: &str, path: &str) -> Self {
+        PatchOp::Move {
+            from: from.to_string(),
+            path: path.to_string(),
+        }
+    }
+
+    /// Copy value from one path to another
+    pub fn copy(from: &str, path: &str) -> Self {
+        PatchOp::Copy {
+            from: from.to_string(),
+            path: path.to_string(),
+        }
+    }
+
+    /// Fail the whole patch unless the value at path equals value
+    pub fn test(path: &str, value: Value) -> Self {
+        PatchOp::Test {
+            path: path.to_string(),
+            value,
+        }
+    }
+}
@@ -2,28 +2,98 @@
 pub mod body;
 pub mod client;
 pub mod client_builder;
+pub mod client_cert;
 pub mod client_sync;
+pub mod clock;
+pub mod codec;
+pub mod conformance;
+mod connection;
+pub mod connector;
 pub mod cookie;
 pub mod cookie_jar;
+pub mod credentials;
+pub mod crypto;
+pub mod download_cache;
 pub mod error;
+pub mod extract;
+pub mod forwarded;
+pub mod grpc_web;
+mod hash;
 pub mod headers;
+pub mod http_date;
+pub mod json_patch;
+mod limiter;
+pub mod locale;
+pub mod metrics;
+pub mod no_proxy;
+pub mod pinned;
+pub mod problem;
+pub mod rate_limit;
+pub mod redact;
+mod redirect_cache;
 pub mod request;
+pub mod request_builder;
+pub mod resolver;
 pub mod response;
+pub mod retry;
+pub mod s3;
+pub mod sigv4;
+pub mod simulated;
 mod socks5;
+pub mod stream;
+pub mod system_proxy;
+pub mod timings;
 mod tls_noverify;
+pub mod upgrade;
+pub mod upload;
 mod user_agent;
+pub mod validators;
+pub mod vcr;
+mod wire_log;
 
 use std::collections::HashMap;
 use std::sync::Arc;
 pub use self::client::HttpClient;
+pub use self::client_cert::ClientCert;
+pub use self::clock::{Clock, MockClock, SystemClock};
+pub use self::codec::{AsyncChunkedDecoder, AsyncDecoder, ChunkedDecoder};
 pub use self::cookie::Cookie;
 pub use self::client_sync::HttpSyncClient;
-pub use self::client_builder::{HttpClientConfig, HttpClientBuilder};
-pub use self::request::HttpRequest;
-pub use self::response::HttpResponse;
+pub use self::connection::{HttpStream, TlsInfo};
+pub use self::connector::{Connector, DefaultConnector};
+pub use self::credentials::{AuthChallenge, Credentials, CredentialsProvider};
+pub use self::client_builder::{AutoFallback, EarlyData, HttpClientConfig, HttpClientBuilder};
+pub use self::redact::Redaction;
+pub use self::request::{HttpRequest, Timeouts};
+pub use self::request_builder::RequestBuilder;
+pub use self::resolver::{CachingResolver, IpFamily, Resolver, SystemResolver};
+pub use self::rate_limit::RateLimit;
+pub use self::retry::RetryPolicy;
+pub use self::s3::S3;
+pub use self::sigv4::SigV4;
+pub use self::simulated::SimulatedNetwork;
+pub use self::stream::ResponseStream;
+pub use self::system_proxy::SystemProxy;
+pub use self::timings::{Phase, Timings};
+pub use self::upgrade::Upgraded;
+pub use self::response::{
+    BodyTransformer, HeaderDecision, HttpResponse, HttpResponseBuilder, ParseMode, RedirectHop,
+    RemoteWriteOutcome,
+};
 pub use self::body::HttpBody;
 pub use self::headers::HttpHeaders;
-pub use self::cookie_jar::CookieJar;
+pub use self::json_patch::PatchOp;
+pub use self::locale::LocalePack;
+pub use self::forwarded::{ClientInfo, TrustedProxies};
+pub use self::metrics::RequestStats;
+pub use self::no_proxy::NoProxy;
+pub use self::pinned::PinnedConnection;
+pub use self::problem::Problem;
+pub use self::hash::HashAlgorithm;
+pub use self::cookie_jar::{CookieJar, CookieLimits};
+pub use self::download_cache::DownloadCache;
+pub use self::validators::Validators;
+pub use self::vcr::{Vcr, VcrMode};
 
 
 #[derive(Debug, Clone, Copy, PartialEq)]
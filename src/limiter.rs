@@ -0,0 +1,97 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Condvar, Mutex};
+use url::Url;
+
+/// Caps the number of requests in flight at once across all clones of a client.  Requests
+/// beyond the cap block until a slot frees up, and are admitted in the order they arrived.
+#[derive(Debug, Clone)]
+pub struct Limiter {
+    max: usize,
+    state: Arc<(Mutex<(u64, u64)>, Condvar)>,
+}
+
+/// Caps the number of requests in flight to each host, with a Limiter per host created upon
+/// the first request to it.  Shared across all clones of a client.
+#[derive(Debug, Clone)]
+pub struct HostLimiter {
+    max: usize,
+    hosts: Arc<Mutex<HashMap<String, Limiter>>>,
+}
+
+/// Slot held by an in-flight request, freed when dropped.
+pub struct LimiterPermit {
+    state: Arc<(Mutex<(u64, u64)>, Condvar)>,
+}
+
+impl Limiter {
+    /// Create limiter, a max of 0 is unlimited
+    pub fn new(max: usize) -> Self {
+        Self {
+            max,
+            state: Arc::new((Mutex::new((0, 0)), Condvar::new())),
+        }
+    }
+
+    /// Wait for a free slot.  Each caller takes a ticket, and ticket N is admitted once all but
+    /// max - 1 of the tickets before it have been released.
+    pub fn acquire(&self) -> Option<LimiterPermit> {
+        if self.max == 0 {
+            return None;
+        }
+
+        let (lock, cvar) = &*self.state;
+        let mut guard = lock.lock().unwrap_or_else(|e| e.into_inner());
+        let ticket = guard.0;
+        guard.0 += 1;
+        while ticket >= guard.1 + self.max as u64 {
+            guard = cvar.wait(guard).unwrap_or_else(|e| e.into_inner());
+        }
+
+        Some(LimiterPermit {
+            state: Arc::clone(&self.state),
+        })
+    }
+}
+
+impl Drop for LimiterPermit {
+    fn drop(&mut self) {
+        let (lock, cvar) = &*self.state;
+        let mut guard = lock.lock().unwrap_or_else(|e| e.into_inner());
+        guard.1 += 1;
+        cvar.notify_all();
+    }
+}
+
+impl HostLimiter {
+    /// Create limiter, a max of 0 is unlimited
+    pub fn new(max: usize) -> Self {
+        Self {
+            max,
+            hosts: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Wait for a free slot of the host of the URL
+    pub fn acquire(&self, url: &str) -> Option<LimiterPermit> {
+        if self.max == 0 {
+            return None;
+        }
+
+        let limiter = self
+            .hosts
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .entry(host_of(url))
+            .or_insert_with(|| Limiter::new(self.max))
+            .clone();
+        limiter.acquire()
+    }
+}
+
+/// Host of the URL that per host limits apply to, lowercased
+pub fn host_of(url: &str) -> String {
+    Url::parse(url)
+        .ok()
+        .and_then(|uri| uri.host_str().map(|host| host.to_lowercase()))
+        .unwrap_or_default()
+}
@@ -0,0 +1,50 @@
+use super::HttpHeaders;
+
+/// Accept-Language plus any other locale dependent headers, sent together so the locale a
+/// client claims is consistent, eg. with the user agent picked by HttpClientBuilder::browser().
+#[derive(Clone, Debug, PartialEq)]
+pub struct LocalePack {
+    pub accept_language: String,
+    pub headers: Vec<(String, String)>,
+}
+
+impl LocalePack {
+    /// Create pack sending the given Accept-Language header as is
+    pub fn new(accept_language: &str) -> Self {
+        Self {
+            accept_language: accept_language.to_string(),
+            headers: Vec::new(),
+        }
+    }
+
+    /// Create pack for a language tag the way browsers send it, eg. "de-DE" gives
+    /// "de-DE,de;q=0.9,en;q=0.8", falling back to English as most browsers do
+    pub fn from_tag(tag: &str) -> Self {
+        let tag = tag.trim().replace('_', "-");
+        let language = tag.split('-').next().unwrap_or("").to_lowercase();
+
+        let accept_language = match (language.as_str(), tag.contains('-')) {
+            ("en", true) => format!("{},en;q=0.9", tag),
+            ("en", false) | ("", _) => "en-US,en;q=0.9".to_string(),
+            (_, true) => format!("{},{};q=0.9,en;q=0.8", tag, language),
+            (_, false) => format!("{},en;q=0.8", language),
+        };
+        Self::new(&accept_language)
+    }
+
+    /// Add another header to send along, eg. a locale cookie or a Content-Language of uploads
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    /// Set the headers of the pack, replacing any of the same name
+    pub fn apply(&self, headers: &mut HttpHeaders) {
+        headers.delete_lower("accept-language");
+        headers.set("Accept-Language", &self.accept_language);
+        for (name, value) in self.headers.iter() {
+            headers.delete_lower(name);
+            headers.set(name, value);
+        }
+    }
+}
@@ -0,0 +1,61 @@
+use super::{HttpRequest, HttpResponse};
+use crate::error::Error;
+use std::time::Duration;
+
+/// Outcome of a request once it completed or failed, including all retries, as passed to the
+/// callback set via HttpClientBuilder::on_request_complete(), eg. to feed Prometheus counters
+#[derive(Clone, Debug, PartialEq)]
+pub struct RequestStats {
+    pub method: String,
+    pub url: String,
+    /// Status code of the final response, None if the request failed without one
+    pub status: Option<u16>,
+    /// Number of attempts made, 1 unless retried as per the retry policy
+    pub attempts: usize,
+    /// Duration of the whole request, including all attempts and the delays between them
+    pub elapsed: Duration,
+    /// Bytes written to the server by the final attempt
+    pub bytes_sent: u64,
+    /// Bytes read from the server by the final attempt
+    pub bytes_received: u64,
+    /// Error the request failed with, if any
+    pub error: Option<String>,
+}
+
+impl RequestStats {
+    /// Create from the result of a request
+    pub fn new(
+        req: &HttpRequest,
+        result: &Result<HttpResponse, Error>,
+        attempts: usize,
+        elapsed: Duration,
+    ) -> Self {
+        let (status, bytes_sent, bytes_received, error) = match result {
+            Ok(res) => (
+                Some(res.status_code()),
+                res.bytes_sent(),
+                res.bytes_received(),
+                None,
+            ),
+            Err(e @ Error::UnexpectedStatus(status, _)) => {
+                (Some(*status), 0, 0, Some(e.to_string()))
+            }
+            Err(e) => (None, 0, 0, Some(e.to_string())),
+        };
+        Self {
+            method: req.method.clone(),
+            url: req.url.clone(),
+            status,
+            attempts,
+            elapsed,
+            bytes_sent,
+            bytes_received,
+            error,
+        }
+    }
+
+    /// Number of retries, one less than the attempts
+    pub fn retries(&self) -> usize {
+        self.attempts.saturating_sub(1)
+    }
+}
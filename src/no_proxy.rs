@@ -0,0 +1,96 @@
+use std::net::IpAddr;
+
+/// Hosts which bypass the configured proxy, in the style of the NO_PROXY environment variable.
+/// Entries are exact hosts or IP addresses, domain suffixes where "example.com" and
+/// ".example.com" both match example.com and all its subdomains, CIDR ranges such as
+/// "10.0.0.0/8", or "*" to bypass the proxy entirely.  Ports are ignored.
+#[derive(Clone, Debug, Default)]
+pub struct NoProxy {
+    entries: Vec<String>,
+}
+
+impl NoProxy {
+    /// Create from list of entries
+    pub fn new(entries: &[&str]) -> Self {
+        let mut no_proxy = Self::default();
+        for entry in entries {
+            no_proxy.add(entry);
+        }
+        no_proxy
+    }
+
+    /// Create from a comma separated list, such as the value of the NO_PROXY environment variable
+    pub fn from_list(list: &str) -> Self {
+        Self::new(&list.split(',').collect::<Vec<&str>>())
+    }
+
+    /// Add entry
+    pub fn add(&mut self, entry: &str) {
+        let entry = entry.trim().to_lowercase();
+        if !entry.is_empty() {
+            self.entries.push(entry);
+        }
+    }
+
+    /// Whether or not any entries are defined
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Whether or not requests to host bypass the proxy
+    pub fn matches(&self, host: &str) -> bool {
+        let host = host
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .to_lowercase();
+        let ip = host.parse::<IpAddr>().ok();
+        self.entries
+            .iter()
+            .any(|entry| Self::entry_matches(entry, &host, ip))
+    }
+
+    // Check a single entry against host
+    fn entry_matches(entry: &str, host: &str, ip: Option<IpAddr>) -> bool {
+        if entry == "*" {
+            return true;
+        }
+
+        // CIDR range
+        if let Some((network, bits)) = entry.split_once('/') {
+            return match (ip, network.parse::<IpAddr>(), bits.parse::<u32>()) {
+                (Some(ip), Ok(network), Ok(bits)) => in_range(ip, network, bits),
+                _ => false,
+            };
+        }
+
+        // Exact IP, including the different forms of IPv6 addresses
+        let entry = entry.trim_start_matches('[');
+        let entry = match entry.split_once(']') {
+            Some((addr, _)) => addr,
+            None if entry.matches(':').count() == 1 => entry.split(':').next().unwrap_or(entry),
+            None => entry,
+        };
+        if let (Some(ip), Ok(entry_ip)) = (ip, entry.parse::<IpAddr>()) {
+            return ip == entry_ip;
+        }
+
+        // Domain and its subdomains
+        let domain = entry.trim_start_matches("*.").trim_start_matches('.');
+        host == domain || host.ends_with(&format!(".{}", domain))
+    }
+}
+
+/// Whether or not ip falls within the network of the given prefix length
+pub fn in_range(ip: IpAddr, network: IpAddr, bits: u32) -> bool {
+    match (ip, network) {
+        (IpAddr::V4(ip), IpAddr::V4(network)) if bits <= 32 => {
+            let mask = u32::MAX.checked_shl(32 - bits).unwrap_or(0);
+            u32::from(ip) & mask == u32::from(network) & mask
+        }
+        (IpAddr::V6(ip), IpAddr::V6(network)) if bits <= 128 => {
+            let mask = u128::MAX.checked_shl(128 - bits).unwrap_or(0);
+            u128::from(ip) & mask == u128::from(network) & mask
+        }
+        _ => false,
+    }
+}
@@ -0,0 +1,222 @@
+use super::{HttpClientConfig, HttpRequest, HttpResponse, HttpStream};
+use crate::body;
+use crate::conformance;
+use crate::connection::{self, Connection, ConnectionPool};
+use crate::error::Error;
+use crate::request::Timeouts;
+use crate::timings::{Phase, Timings};
+use crate::wire_log;
+use std::fmt;
+use std::io::BufReader;
+use std::time::{Duration, Instant};
+
+/// Single connection to an origin, opened via open_connection() of either client or wrapping an
+/// existing stream via from_stream(), over which requests are sent one after another with
+/// send_on().  The connection is never pooled or
+/// swapped for another, eg. to keep a session on the same backend replica behind a load
+/// balancer.  Redirects are not followed, and cookies set by responses are only kept within
+/// this connection's copy of the client's cookie jar.
+pub struct PinnedConnection {
+    config: HttpClientConfig,
+    origin: String,
+    conn: Option<Connection>,
+}
+
+impl fmt::Debug for PinnedConnection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("PinnedConnection")
+            .field("origin", &self.origin)
+            .field("open", &self.is_open())
+            .finish()
+    }
+}
+
+impl PinnedConnection {
+    /// Open new connection to the origin of the URL, eg. https://example.com:8443
+    pub fn open(config: &HttpClientConfig, origin: &str) -> Result<Self, Error> {
+        let req = HttpRequest::new("GET", origin, &vec![], &super::HttpBody::empty());
+        let (uri, port, _effective, _message) = req.prepare(config)?;
+
+        // A pool of its own always opens a new connection
+        let mut conn = ConnectionPool::default().checkout(
+            config,
+            &uri,
+            &port,
+            None,
+            &Timeouts::default(),
+            Instant::now(),
+            None,
+        )?;
+
+        // Complete the handshake of a resumed session, left pending to allow early data
+        let timeout = Duration::from_secs(config.timeout);
+        connection::write_early(&mut conn, &[], false, timeout, origin)?;
+
+        Ok(Self {
+            config: config.clone(),
+            origin: conn.key.clone(),
+            conn: Some(conn),
+        })
+    }
+
+    /// Wrap a stream already established to the origin of the URL, eg. a TcpStream accepted by
+    /// a listener or a TLS stream through a custom tunnel, to send requests over it without
+    /// connecting.  The stream is used as is, so for https it must already be encrypted.
+    pub fn from_stream<S: HttpStream + 'static>(
+        config: &HttpClientConfig,
+        origin: &str,
+        stream: S,
+    ) -> Result<Self, Error> {
+        let req = HttpRequest::new("GET", origin, &vec![], &super::HttpBody::empty());
+        let (uri, port, _effective, _message) = req.prepare(config)?;
+        let origin = format!(
+            "{}://{}:{}",
+            uri.scheme(),
+            uri.host_str().unwrap_or(""),
+            port
+        );
+
+        Ok(Self {
+            config: config.clone(),
+            origin: origin.clone(),
+            conn: Some(Connection::from_stream(origin, Box::new(stream))),
+        })
+    }
+
+    /// Origin the connection is open to, as scheme://host:port
+    pub fn origin(&self) -> &str {
+        &self.origin
+    }
+
+    /// Whether or not the connection is still open.  It is closed once a response says so,
+    /// the server closes it, or a request over it fails.
+    pub fn is_open(&self) -> bool {
+        self.conn.is_some()
+    }
+
+    /// Send request over this connection and read the full response.  Fails without sending
+    /// if the URL of the request is of another origin, or the connection has been closed.
+    pub fn send_on(&mut self, req: &HttpRequest) -> Result<HttpResponse, Error> {
+        // Prepare uri and http message, nothing is sent if the request is invalid
+        let (uri, port, effective, message) = req.prepare(&self.config)?;
+        let origin = format!(
+            "{}://{}:{}",
+            uri.scheme(),
+            uri.host_str().unwrap_or(""),
+            port
+        );
+        if origin != self.origin {
+            return Err(Error::Custom(format!(
+                "Request to {} can not be sent over connection to {}",
+                req.url, self.origin
+            )));
+        }
+        if self.config.strict {
+            let violations = conformance::check_request(&effective);
+            if !violations.is_empty() {
+                return Err(Error::Nonconformant(req.url.clone(), violations));
+            }
+        }
+        if effective.body.is_async() {
+            return Err(Error::Custom(body::ASYNC_ONLY.to_string()));
+        }
+        let mut conn = match self.conn.take() {
+            Some(r) => r,
+            None => {
+                return Err(Error::Custom(format!(
+                    "Connection to {} has been closed",
+                    self.origin
+                )))
+            }
+        };
+
+        // Keep connection only if the exchange completed and both sides agree
+        let result = self.exchange(&mut conn, req, &effective, &message);
+        if matches!(&result, Ok(res) if !conn.must_close && connection::is_reusable(res)) {
+            self.conn = Some(conn);
+        }
+        result
+    }
+
+    /// Take back the stream, eg. to speak another protocol over it after a 101 Switching
+    /// Protocols response.  Bytes the server sent past the last response are left buffered
+    /// within the reader.  None if the connection has been closed.
+    pub fn into_inner(mut self) -> Option<BufReader<Box<dyn HttpStream>>> {
+        self.conn.take().map(|conn| conn.reader)
+    }
+
+    /// Close the connection
+    pub fn close(mut self) {
+        if let Some(conn) = self.conn.take() {
+            let _ = conn.reader.into_inner().shutdown();
+        }
+    }
+
+    // Write request and read its response
+    fn exchange(
+        &mut self,
+        conn: &mut Connection,
+        req: &HttpRequest,
+        effective: &HttpRequest,
+        message: &[u8],
+    ) -> Result<HttpResponse, Error> {
+        // Apply timeouts of the request
+        let started = Instant::now();
+        let deadline = req.timeouts.total.map(|total| started + total);
+        let read_timeout = req
+            .timeouts
+            .read
+            .or(self.config.read_timeout.map(Duration::from_secs));
+        conn.started = started;
+        conn.timings = Timings::default();
+        conn.sent = 0;
+        conn.received = 0;
+        conn.set_timeouts(read_timeout, deadline)
+            .map_err(|_| Error::timeout(&req.url, Phase::Write))?;
+
+        // Send request
+        let expect_continue = if effective.headers.has_lower("expect") {
+            self.config.expect_continue.map(Duration::from_millis)
+        } else {
+            None
+        };
+        connection::write_request(conn, message, &effective.body, expect_continue, &req.url)?;
+        wire_log::request(&self.config, message);
+
+        // Read header
+        let mut res = HttpResponse::read_head_mode(conn, req, self.config.parse_mode)
+            .map_err(|e| e.with_timings(started, &conn.timings))?;
+        wire_log::response_head(&self.config, &res);
+        if self.config.strict {
+            let violations = conformance::check_response(&res);
+            if !violations.is_empty() {
+                return Err(Error::Nonconformant(req.url.clone(), violations));
+            }
+        }
+        if self.config.normalize_reason {
+            res.normalize_reason();
+        }
+        res.set_effective_request(effective);
+        res.set_alpn_protocol(conn.reader.get_ref().alpn_protocol());
+        res.set_tls_info(conn.reader.get_ref().tls_info());
+        if let Some(jar) = self.config.jar_mut(req.jar.as_deref()) {
+            match req.save_cookies {
+                true => jar.update_jar(&res.headers()),
+                false => jar.update_jar_unsaved(&res.headers()),
+            }
+        }
+
+        // Read body
+        res.read_body_hashed(conn, self.config.hash_body)
+            .map_err(|e| e.with_timings(started, &conn.timings))?;
+        wire_log::response_body(&self.config, &res);
+        res.transform_body(&self.config.body_transformers);
+        res.set_transfer(conn.sent, conn.received);
+        res.set_timings(Timings {
+            total: conn.started.elapsed(),
+            ..conn.timings
+        });
+
+        Ok(res)
+    }
+}
@@ -0,0 +1,61 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+/// Media type of problem details
+pub const CONTENT_TYPE: &str = "application/problem+json";
+
+/// Problem details of an error response, RFC 9457.  Members beyond the standard ones are kept
+/// within extensions, eg. a list of invalid fields.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Problem {
+    #[serde(rename = "type", default = "Problem::about_blank")]
+    pub problem_type: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<u16>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub instance: Option<String>,
+    #[serde(flatten)]
+    pub extensions: Map<String, Value>,
+}
+
+impl Problem {
+    /// Problem of the given status, with a short title of the problem type and a detail
+    /// explaining this occurrence of it.  An empty detail is left out.
+    pub fn new(status: u16, title: &str, detail: &str) -> Self {
+        Self {
+            problem_type: Self::about_blank(),
+            title: Some(title.to_string()),
+            status: Some(status),
+            detail: (!detail.is_empty()).then(|| detail.to_string()),
+            instance: None,
+            extensions: Map::new(),
+        }
+    }
+
+    /// Set URI identifying the problem type, defaults to about:blank
+    pub fn problem_type(mut self, uri: &str) -> Self {
+        self.problem_type = uri.to_string();
+        self
+    }
+
+    /// Set URI identifying this occurrence of the problem
+    pub fn instance(mut self, uri: &str) -> Self {
+        self.instance = Some(uri.to_string());
+        self
+    }
+
+    /// Add extension member, eg. extension("balance", json!(30))
+    pub fn extension(mut self, name: &str, value: Value) -> Self {
+        self.extensions.insert(name.to_string(), value);
+        self
+    }
+
+    // Default problem type, RFC 9457 section 4.2.1
+    fn about_blank() -> String {
+        "about:blank".to_string()
+    }
+}
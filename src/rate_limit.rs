@@ -0,0 +1,109 @@
+use super::HttpClientConfig;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Rate of a token bucket, refilled with requests_per_second tokens up to burst.  Each request
+/// takes one token, and waits for the next one once the bucket is empty.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RateLimit {
+    pub requests_per_second: f64,
+    pub burst: u32,
+}
+
+impl RateLimit {
+    /// Allow requests_per_second on average, with bursts of up to burst requests at once
+    pub fn new(requests_per_second: f64, burst: u32) -> Self {
+        Self {
+            requests_per_second,
+            burst: burst.max(1),
+        }
+    }
+
+    /// Allow one request per the given interval, without bursts
+    pub fn every(interval: Duration) -> Self {
+        Self::new(1.0 / interval.as_secs_f64().max(f64::MIN_POSITIVE), 1)
+    }
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    // Take a token, returning how long to wait until it is available.  Tokens are reserved
+    // right away, so concurrent requests are spaced out in the order they arrived.
+    fn reserve(&mut self, limit: &RateLimit, now: Instant) -> Duration {
+        let rate = limit.requests_per_second.max(f64::MIN_POSITIVE);
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * rate).min(limit.burst as f64) - 1.0;
+        self.updated = now;
+
+        match self.tokens >= 0.0 {
+            true => Duration::ZERO,
+            false => Duration::from_secs_f64(-self.tokens / rate),
+        }
+    }
+}
+
+/// Token buckets of a client, one across all requests and one per host, as per the rate limits
+/// of HttpClientBuilder.  Clones share the same buckets.
+#[derive(Debug, Clone, Default)]
+pub struct RateLimiter {
+    global: Option<RateLimit>,
+    per_host: Option<RateLimit>,
+    hosts: HashMap<String, RateLimit>,
+    state: Arc<Mutex<(Option<Bucket>, HashMap<String, Bucket>)>>,
+}
+
+impl RateLimiter {
+    /// Create limiter from the rate limits within config
+    pub fn new(config: &HttpClientConfig) -> Self {
+        Self {
+            global: config.rate_limit,
+            per_host: config.rate_limit_per_host,
+            hosts: config
+                .host_rate_limits
+                .iter()
+                .map(|(host, limit)| (host.to_lowercase(), *limit))
+                .collect(),
+            state: Arc::default(),
+        }
+    }
+
+    /// Whether or not any rate limit applies
+    pub fn is_enabled(&self) -> bool {
+        self.global.is_some() || self.per_host.is_some() || !self.hosts.is_empty()
+    }
+
+    /// Take a token for a request to host from the global bucket and that of the host, returning
+    /// how long to wait before sending it
+    pub fn reserve(&self, host: &str, now: Instant) -> Duration {
+        if !self.is_enabled() {
+            return Duration::ZERO;
+        }
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let (global, hosts) = &mut *state;
+        let new_bucket = |limit: &RateLimit| Bucket {
+            tokens: limit.burst as f64,
+            updated: now,
+        };
+
+        let mut delay = Duration::ZERO;
+        if let Some(limit) = &self.global {
+            delay = global
+                .get_or_insert_with(|| new_bucket(limit))
+                .reserve(limit, now);
+        }
+
+        let host = host.to_lowercase();
+        if let Some(limit) = self.hosts.get(&host).or(self.per_host.as_ref()) {
+            let bucket = hosts.entry(host).or_insert_with(|| new_bucket(limit));
+            delay = delay.max(bucket.reserve(limit, now));
+        }
+
+        delay
+    }
+}
@@ -0,0 +1,138 @@
+use super::{HttpHeaders, HttpRequest};
+use url::Url;
+
+pub const REDACTED: &str = "[REDACTED]";
+
+/// Names of headers, cookies and query parameters whose values are masked within diagnostic
+/// output such as to_curl().  Header names are case-insensitive, cookie and query parameter
+/// names are case-sensitive.
+#[derive(Clone, Debug)]
+pub struct Redaction {
+    pub headers: Vec<String>,
+    pub cookies: Vec<String>,
+    pub query_params: Vec<String>,
+}
+
+impl Default for Redaction {
+    /// Redacts the Authorization and Proxy-Authorization headers
+    fn default() -> Redaction {
+        Self {
+            headers: vec![
+                "authorization".to_string(),
+                "proxy-authorization".to_string(),
+            ],
+            cookies: Vec::new(),
+            query_params: Vec::new(),
+        }
+    }
+}
+
+impl Redaction {
+    /// Redact nothing, output is verbatim
+    pub fn none() -> Self {
+        Self {
+            headers: Vec::new(),
+            cookies: Vec::new(),
+            query_params: Vec::new(),
+        }
+    }
+
+    /// Redact value of header
+    pub fn header(mut self, name: &str) -> Self {
+        self.headers.push(name.to_lowercase());
+        self
+    }
+
+    /// Redact value of cookie within Cookie and Set-Cookie headers
+    pub fn cookie(mut self, name: &str) -> Self {
+        self.cookies.push(name.to_string());
+        self
+    }
+
+    /// Redact value of query string parameter
+    pub fn query_param(mut self, name: &str) -> Self {
+        self.query_params.push(name.to_string());
+        self
+    }
+
+    /// Get header value as it should be displayed
+    pub fn header_value(&self, key: &str, value: &str) -> String {
+        let lower = key.to_lowercase();
+        if self.headers.contains(&lower) {
+            return REDACTED.to_string();
+        } else if self.cookies.is_empty() || (lower != "cookie" && lower != "set-cookie") {
+            return value.to_string();
+        }
+
+        // Mask individual cookies
+        value
+            .split(';')
+            .map(|pair| match pair.split_once('=') {
+                Some((name, _)) if self.cookies.contains(&name.trim().to_string()) => {
+                    format!("{}={}", name, REDACTED)
+                }
+                _ => pair.to_string(),
+            })
+            .collect::<Vec<String>>()
+            .join(";")
+    }
+
+    /// Get URL as it should be displayed
+    pub fn url(&self, url: &str) -> String {
+        if self.query_params.is_empty() {
+            return url.to_string();
+        }
+        let mut uri = match Url::parse(url) {
+            Ok(r) => r,
+            Err(_) => return url.to_string(),
+        };
+        let query = match uri.query() {
+            Some(r) => r.to_string(),
+            None => return url.to_string(),
+        };
+
+        // Mask values, leaving the encoding of other parameters untouched
+        let pairs: Vec<String> = query
+            .split('&')
+            .map(|pair| match pair.split_once('=') {
+                Some((key, _))
+                    if self
+                        .query_params
+                        .iter()
+                        .any(|name| name == &Self::decode(key)) =>
+                {
+                    format!("{}={}", key, REDACTED)
+                }
+                _ => pair.to_string(),
+            })
+            .collect();
+        uri.set_query(Some(&pairs.join("&")));
+        uri.to_string()
+    }
+
+    // Decode query string parameter name
+    fn decode(key: &str) -> String {
+        urlencoding::decode(&key.replace('+', " "))
+            .map(|k| k.to_string())
+            .unwrap_or_else(|_| key.to_string())
+    }
+
+    /// Get headers as they should be displayed
+    pub fn headers(&self, headers: &HttpHeaders) -> HttpHeaders {
+        let mut redacted = HttpHeaders::new();
+        for (key, values) in headers.all().iter() {
+            for value in values {
+                redacted.add(key, &self.header_value(key, value));
+            }
+        }
+        redacted
+    }
+
+    /// Get copy of request with its URL and headers redacted
+    pub fn request(&self, req: &HttpRequest) -> HttpRequest {
+        let mut redacted = req.clone();
+        redacted.url = self.url(&req.url);
+        redacted.headers = self.headers(&req.headers);
+        redacted
+    }
+}
@@ -0,0 +1,117 @@
+use crate::clock::{Clock, SystemClock};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use url::Url;
+
+#[derive(Debug, Clone)]
+struct CacheEntry {
+    target: String,
+    expires: Instant,
+    last_used: u64,
+}
+
+/// Remembers targets of permanent 301 / 308 redirects, so later requests to the source URL go
+/// directly to the target.  Bounded to a maximum number of URLs, evicting the least recently
+/// used.  Clones share the same cache.
+#[derive(Debug, Clone)]
+pub struct RedirectCache {
+    capacity: usize,
+    ttl: Duration,
+    clock: Arc<dyn Clock>,
+    state: Arc<Mutex<(HashMap<String, CacheEntry>, u64)>>,
+}
+
+impl RedirectCache {
+    /// Create cache holding up to capacity URLs for ttl_seconds each.  A capacity of 0 disables it.
+    pub fn new(capacity: usize, ttl_seconds: u64) -> Self {
+        Self {
+            capacity,
+            ttl: Duration::from_secs(ttl_seconds),
+            clock: Arc::new(SystemClock),
+            state: Arc::new(Mutex::new((HashMap::new(), 0))),
+        }
+    }
+
+    /// Expire entries as per the given clock instead of the system clock
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Get cached target of URL, if any and not yet expired
+    pub fn get(&self, url: &str) -> Option<String> {
+        if self.capacity == 0 {
+            return None;
+        }
+        let mut state = self.state.lock().ok()?;
+        let (entries, clock) = &mut *state;
+
+        match entries.get_mut(url) {
+            Some(entry) if entry.expires > self.clock.instant() => {
+                *clock += 1;
+                entry.last_used = *clock;
+                Some(entry.target.clone())
+            }
+            Some(_) => {
+                entries.remove(url);
+                None
+            }
+            None => None,
+        }
+    }
+
+    /// Remember target of a permanent redirect
+    pub fn insert(&self, url: &str, target: &str) {
+        if self.capacity == 0 || url == target {
+            return;
+        }
+        let mut state = match self.state.lock() {
+            Ok(r) => r,
+            Err(_) => return,
+        };
+        let (entries, clock) = &mut *state;
+
+        // Evict least recently used
+        if entries.len() >= self.capacity && !entries.contains_key(url) {
+            let oldest = entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| key.clone());
+            if let Some(key) = oldest {
+                entries.remove(&key);
+            }
+        }
+
+        *clock += 1;
+        entries.insert(
+            url.to_string(),
+            CacheEntry {
+                target: target.to_string(),
+                expires: self.clock.instant() + self.ttl,
+                last_used: *clock,
+            },
+        );
+    }
+}
+
+/// Resolve Location header against the URL it was received from, as it may be relative
+pub fn resolve(base: &str, location: &str) -> String {
+    Url::parse(base)
+        .and_then(|uri| uri.join(location.trim()))
+        .map(|uri| uri.to_string())
+        .unwrap_or_else(|_| location.to_string())
+}
+
+/// Check whether following the redirect from url to location repeats a redirect already
+/// followed, and if so return the cycle of URLs, eg. [A, B, A].  Revisiting a URL alone is not a
+/// loop, as a login page may redirect back after setting a cookie.
+pub fn find_cycle(hops: &[(String, String)], url: &str, location: &str) -> Option<Vec<String>> {
+    let start = hops
+        .iter()
+        .position(|(from, to)| from == url && to == location)?;
+
+    let mut cycle = vec![hops[start].0.clone()];
+    cycle.extend(hops[start..].iter().map(|(_, to)| to.clone()));
+    Some(cycle)
+}
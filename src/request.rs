@@ -1,18 +1,37 @@
-use super::{HttpBody, HttpClientConfig, HttpHeaders, ProxyType};
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use super::{HttpBody, HttpClientConfig, HttpHeaders, ProxyType, Redaction};
+use crate::forwarded::{self, ClientInfo, TrustedProxies};
 use crate::error::Error;
 use url::Url;
 use std::io::{BufRead, BufReader, Read};
-use std::net::TcpStream;
+use std::net::{SocketAddr, TcpStream};
+use std::time::Duration;
 //use std::io::BufReader as TokioBufReader;
 use tokio::io::AsyncBufReadExt;
 use tokio::io::AsyncBufRead;
 
+/// Timeouts of a single request, overriding those of the client.  The total deadline spans
+/// connecting, redirects and reading the full body.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Timeouts {
+    pub connect: Option<Duration>,
+    pub read: Option<Duration>,
+    pub total: Option<Duration>,
+}
+
 #[derive(Clone, Debug)]
 pub struct HttpRequest {
     pub method: String,
     pub url: String,
     pub headers: HttpHeaders,
     pub body: HttpBody,
+    pub timeouts: Timeouts,
+    pub omit_query: Vec<String>,
+    pub sni: Option<String>,
+    pub save_cookies: bool,
+    pub expect_continue: bool,
+    pub jar: Option<String>,
+    pub peer_addr: Option<SocketAddr>,
 }
 
 impl HttpRequest {
@@ -22,21 +41,225 @@ impl HttpRequest {
             url: url.to_string(),
             headers: HttpHeaders::from_vec(&headers.iter().map(|s| s.to_string()).collect()),
             body: body.clone(),
+            timeouts: Timeouts::default(),
+            omit_query: Vec::new(),
+            sni: None,
+            save_cookies: true,
+            expect_continue: true,
+            jar: None,
+            peer_addr: None,
+        }
+    }
+
+    /// Clone request with a different URL
+    pub fn with_url(&self, url: &str) -> Self {
+        let mut req = self.clone();
+        req.url = url.to_string();
+        req
+    }
+
+    /// Clone request with a different method
+    pub fn with_method(&self, method: &str) -> Self {
+        let mut req = self.clone();
+        req.method = method.to_uppercase();
+        req
+    }
+
+    /// Clone request with header set, replacing any existing header of the same name
+    pub fn with_header(&self, key: &str, value: &str) -> Self {
+        let mut req = self.clone();
+        req.headers.delete_lower(key);
+        req.headers.set(key, value);
+        req
+    }
+
+    /// Clone request with header removed, case-insensitive
+    pub fn without_header(&self, key: &str) -> Self {
+        let mut req = self.clone();
+        req.headers.delete_lower(key);
+        req
+    }
+
+    /// Clone request with a different body
+    pub fn with_body(&self, body: &HttpBody) -> Self {
+        let mut req = self.clone();
+        req.body = body.clone();
+        req
+    }
+
+    /// Clone request with a deadline for the whole request, including connecting, redirects
+    /// and reading the body
+    pub fn with_timeout(&self, total: Duration) -> Self {
+        let mut req = self.clone();
+        req.timeouts.total = Some(total);
+        req
+    }
+
+    /// Clone request with a different connect timeout than the client's
+    pub fn with_connect_timeout(&self, connect: Duration) -> Self {
+        let mut req = self.clone();
+        req.timeouts.connect = Some(connect);
+        req
+    }
+
+    /// Clone request with a timeout on each individual read from the server
+    pub fn with_read_timeout(&self, read: Duration) -> Self {
+        let mut req = self.clone();
+        req.timeouts.read = Some(read);
+        req
+    }
+
+    /// Clone request with query parameter set, replacing any existing parameters of the same
+    /// name, including default query parameters of the client
+    pub fn with_query_param(&self, key: &str, value: &str) -> Self {
+        let mut req = self.clone();
+        if let Ok(mut uri) = Url::parse(&self.url) {
+            let pairs: Vec<(String, String)> = uri
+                .query_pairs()
+                .into_owned()
+                .filter(|(name, _)| name != key)
+                .collect();
+            uri.query_pairs_mut()
+                .clear()
+                .extend_pairs(pairs)
+                .append_pair(key, value);
+            req.url = uri.to_string();
+        }
+        req
+    }
+
+    /// Clone request without the client's default query parameter of the given name
+    pub fn without_default_query(&self, key: &str) -> Self {
+        let mut req = self.clone();
+        req.omit_query.push(key.to_string());
+        req
+    }
+
+    /// Clone request with a different Host header, while still connecting to the host within
+    /// the URL.  Useful for probing virtual hosts and testing CDN origins.
+    pub fn host_header(&self, host: &str) -> Self {
+        self.with_header("Host", host)
+    }
+
+    /// Clone request sending a different server name in the TLS handshake than the host of the
+    /// URL, which the certificate is verified against instead.  Useful for testing against IP
+    /// addresses with a certificate issued for a name.
+    pub fn with_sni(&self, server_name: &str) -> Self {
+        let mut req = self.clone();
+        req.sni = Some(server_name.to_string());
+        req
+    }
+
+    /// Clone request whose cookies update the in-memory jar without saving the jar file, leaving
+    /// them to the next save
+    pub fn without_cookie_save(&self) -> Self {
+        let mut req = self.clone();
+        req.save_cookies = false;
+        req
+    }
+
+    /// Clone request sent without Expect: 100-continue, even if the client is set to send it
+    pub fn without_expect_continue(&self) -> Self {
+        let mut req = self.without_header("Expect");
+        req.expect_continue = false;
+        req
+    }
+
+    /// Clone request sending and storing cookies within the client's cookie jar of the given
+    /// name, registered via HttpClientBuilder::named_jar() or add_jar() of the client, instead
+    /// of its default jar
+    pub fn use_jar(&self, name: &str) -> Self {
+        let mut req = self.clone();
+        req.jar = Some(name.to_string());
+        req
+    }
+
+    /// Clone request to be forwarded upstream on behalf of the incoming request, appending
+    /// the address of its peer to X-Forwarded-For, and setting X-Forwarded-Proto and
+    /// X-Forwarded-Host unless the incoming request already came through a proxy
+    pub fn with_forwarded(&self, incoming: &HttpRequest) -> Self {
+        let mut req = self.clone();
+        let mut chain = incoming.headers.get_lower_vec("x-forwarded-for");
+        if let Some(peer) = incoming.peer_addr {
+            chain.push(peer.ip().to_string());
         }
+        if !chain.is_empty() {
+            req = req.with_header("X-Forwarded-For", &chain.join(", "));
+        }
+
+        let proto = incoming.headers.get_lower("x-forwarded-proto").or_else(|| {
+            Url::parse(&incoming.url)
+                .ok()
+                .map(|uri| uri.scheme().to_string())
+        });
+        if let Some(proto) = proto {
+            req = req.with_header("X-Forwarded-Proto", &proto);
+        }
+        if let Some(host) = incoming
+            .headers
+            .get_lower("x-forwarded-host")
+            .or(incoming.headers.get_lower("host"))
+        {
+            req = req.with_header("X-Forwarded-Host", &host);
+        }
+        req
+    }
+
+    /// Originating client of a request received via build(), as per the Forwarded or
+    /// X-Forwarded-* headers if the peer is a trusted proxy, otherwise the peer itself
+    pub fn client_info(&self, trusted: &TrustedProxies) -> ClientInfo {
+        forwarded::client_info(&self.headers, self.peer_addr, trusted)
+    }
+
+    /// Clone request with basic HTTP authentication, replacing the client's Authorization
+    /// header for this request only
+    pub fn basic_auth(&self, user: &str, password: &str) -> Self {
+        let auth_userpass = format!("{}:{}", user, password);
+        self.with_header(
+            "Authorization",
+            &format!("Basic {}", STANDARD.encode(auth_userpass)),
+        )
+    }
+
+    /// Clone request with bearer token authentication, replacing the client's Authorization
+    /// header for this request only
+    pub fn bearer(&self, token: &str) -> Self {
+        self.with_header("Authorization", &format!("Bearer {}", token))
+    }
+
+    /// Whether or not method is idempotent, so the request may be sent again after a failure,
+    /// RFC 9110 section 9.2.2
+    pub fn is_idempotent(&self) -> bool {
+        let method = self.method.to_uppercase();
+        ["GET", "HEAD", "OPTIONS", "TRACE", "PUT", "DELETE"].contains(&method.as_str())
     }
 
     // Validate URL and scheme
-    pub fn prepare(&self, config: &HttpClientConfig) -> Result<(Url, u16, Vec<u8>), Error> {
-        // Parse url
-        let uri = match Url::parse(&self.url) {
+    pub fn prepare(
+        &self,
+        config: &HttpClientConfig,
+    ) -> Result<(Url, u16, HttpRequest, Vec<u8>), Error> {
+        // Parse url, resolved against the base URL of the client
+        let url = config.resolve_url(&self.url);
+        let uri = match Url::parse(&url) {
             Ok(r) => r,
             Err(_err) => {
-                return Err(Error::InvalidUri(self.url.clone()));
+                return Err(Error::InvalidUri(url));
             }
         };
 
+        let uri = self.merge_default_query(config, uri);
+
+        // Check cookie jar
+        if let Some(name) = &self.jar {
+            if !config.jars.contains_key(name) {
+                return Err(Error::UnknownJar(name.clone()));
+            }
+        }
+
         // Check scheme
-        if uri.scheme() != "http" && uri.scheme() != "https" {
+        let custom_port = config.schemes.get(uri.scheme());
+        if uri.scheme() != "http" && uri.scheme() != "https" && custom_port.is_none() {
             return Err(Error::ProtoNotSupported(uri.scheme().to_string()));
         }
 
@@ -46,86 +269,241 @@ impl HttpRequest {
             _port = 443;
         } else if uri.port().is_none() && uri.scheme() == "http" {
             _port = 80;
+        } else if uri.port().is_none() {
+            _port = *custom_port.unwrap();
         } else {
             _port = uri.port().unwrap();
         }
 
         // Generate message
-        let message = self.generate_raw(config, &uri);
+        let effective = self.effective(config, &uri);
+        let message = effective.generate_raw(config, &uri);
 
-        Ok((uri, _port, message))
+        Ok((uri, _port, effective, message))
     }
 
-    /// Generate raw HTTP message to be sent
-    fn generate_raw(&self, config: &HttpClientConfig, uri: &Url) -> Vec<u8> {
-        // Get target
-        let mut target = uri.path().to_string();
-        if let Some(query) = uri.query() {
-            target = format!("{}?{}", target, query);
-        }
-
-        // Modify target for proxy, if needed
-        if config.proxy_type != ProxyType::None {
-            target = format!(
-                "{}://{}{}",
-                uri.scheme(),
-                uri.host_str().unwrap(),
-                uri.path()
-            );
+    // Add default query parameters of the client, unless the URL already contains them or they
+    // are omitted for this request
+    fn merge_default_query(&self, config: &HttpClientConfig, mut uri: Url) -> Url {
+        let existing: Vec<String> = uri.query_pairs().map(|(key, _)| key.to_string()).collect();
+        let missing: Vec<&(String, String)> = config
+            .default_query
+            .iter()
+            .filter(|(key, _)| !existing.contains(key) && !self.omit_query.contains(key))
+            .collect();
+
+        if !missing.is_empty() {
+            let mut pairs = uri.query_pairs_mut();
+            for (key, value) in missing {
+                pairs.append_pair(key, value);
+            }
         }
+        uri
+    }
 
-        let mut lines = vec![
-            format!("{} {} HTTP/1.1", &self.method, target),
-            format!("Host: {}", uri.host_str().unwrap()),
-        ];
+    /// Get the request as it will be sent, with the user agent, client headers, cookies,
+    /// authentication and body headers merged in.  Request headers override client headers.
+    fn effective(&self, config: &HttpClientConfig, uri: &Url) -> HttpRequest {
+        let mut headers = HttpHeaders::new();
+        headers.set("Host", uri.host_str().unwrap());
 
         if let Some(ua) = &config.user_agent {
-            lines.push(format!("User-Agent: {}", ua));
+            headers.set("User-Agent", ua);
         }
 
         // HTTP client headers
         for (key, value) in config.headers.all().iter() {
-            lines.push(format!("{}: {}", key, value.join("; ")));
+            headers.set(key, &value.join("; "));
+        }
+
+        // Locale dependent headers
+        if let Some(pack) = config.locale_for(uri.host_str().unwrap_or("")) {
+            pack.apply(&mut headers);
+        }
+
+        // Proxy credentials are only for a HTTP proxy forwarding plain requests, never the server
+        if !(config.proxy_for(uri) == ProxyType::HTTP && uri.scheme() == "http") {
+            headers.delete_lower("proxy-authorization");
         }
 
         // Cookie header
-        if let Some(cookie_hdr) = config.cookie.get_http_header(uri) {
-            lines.push(format!("Cookie: {}", cookie_hdr));
+        if let Some(cookie_hdr) = config
+            .jar(self.jar.as_deref())
+            .and_then(|jar| jar.get_http_header(uri))
+        {
+            headers.set("Cookie", &cookie_hdr);
         }
 
         // POST headers
-        if !self.body.files().is_empty() && !self.headers.has_lower("content-type") {
-            lines.push(format!(
-                "Content-type: multipart/form-data; boundary={}",
-                self.body.boundary()
-            ));
+        if self.body.is_multipart() && !self.headers.has_lower("content-type") {
+            headers.set(
+                "Content-type",
+                &format!("multipart/form-data; boundary={}", self.body.boundary()),
+            );
+        } else if let Some(content_type) = self.body.content_type() {
+            if !self.headers.has_lower("content-type") {
+                headers.set("Content-type", &content_type);
+            }
         } else if self.body.is_form_post() && !self.headers.has_lower("content-type") {
-            lines.push("Content-type: application/x-www-form-urlencoded".to_string());
+            headers.set("Content-type", "application/x-www-form-urlencoded");
         }
 
-        // Format post body, if needed
-        let mut post_body: Vec<u8> = Vec::new();
-        if self.body.is_form_post() {
-            post_body = self.body.format();
-            lines.push(format!("Content-length: {}", post_body.len()));
+        if let Some(file_path) = self.body.file() {
+            if !self.headers.has_lower("content-type") {
+                headers.set("Content-type", &HttpBody::guess_mime(&file_path));
+            }
+            headers.set("Content-length", &self.body.content_length().to_string());
+        } else if self.body.is_chunked() {
+            headers.set("Transfer-Encoding", "chunked");
+        } else if self.body.is_form_post() || self.body.is_reader() {
+            headers.set("Content-length", &self.body.content_length().to_string());
+        }
+
+        // Headers implied by the body
+        for (key, value) in self.body.headers().iter() {
+            headers.set(key, value);
+        }
+
+        // Expect 100-continue
+        let has_body = self.body.is_chunked() || self.body.content_length() > 0;
+        if config.expect_continue.is_some() && has_body && self.expect_continue {
+            headers.set("Expect", "100-continue");
         }
 
         // HTTP request headers
         for (key, value) in self.headers.all().iter() {
-            lines.push(format!("{}: {}", key, value.join("; ")));
+            headers.delete_lower(key);
+            headers.set(key, &value.join("; "));
+        }
+
+        Self {
+            method: self.method.clone(),
+            url: uri.to_string(),
+            headers,
+            body: self.body.clone(),
+            timeouts: self.timeouts,
+            omit_query: self.omit_query.clone(),
+            sni: self.sni.clone(),
+            save_cookies: self.save_cookies,
+            expect_continue: self.expect_continue,
+            jar: self.jar.clone(),
+            peer_addr: self.peer_addr,
+        }
+    }
+
+    /// Generate raw HTTP message to be sent
+    fn generate_raw(&self, config: &HttpClientConfig, uri: &Url) -> Vec<u8> {
+        // Get target
+        let mut target = uri.path().to_string();
+        if let Some(query) = uri.query() {
+            target = format!("{}?{}", target, query);
+        }
+
+        // Plain requests forwarded by a HTTP proxy use the absolute URL as target, all others
+        // reach the server through a tunnel
+        if config.proxy_for(uri) == ProxyType::HTTP && uri.scheme() == "http" {
+            let mut absolute = uri.clone();
+            absolute.set_fragment(None);
+            target = absolute.to_string();
+        }
+
+        let mut lines = vec![format!("{} {} HTTP/1.1", &self.method, target)];
+        if let Some(host) = self.headers.get_lower_line("host") {
+            lines.push(format!("Host: {}", host));
+        }
+
+        // Headers, sorted so generated requests are reproducible
+        let mut headers: Vec<(String, Vec<String>)> = self.headers.all().into_iter().collect();
+        headers.sort();
+        for (key, value) in headers.iter() {
+            if key.to_lowercase() != "host" {
+                lines.push(format!("{}: {}", key, value.join("; ")));
+            }
         }
         lines.push("\r\n".to_string());
 
-        // Add body
+        // Format post body, if needed
+        let mut post_body: Vec<u8> = Vec::new();
+        if self.body.is_form_post() {
+            post_body = self.body.format();
+        }
+
+        // Add body, files are streamed separately via HttpBody::write_stream()
         let mut message = lines.join("\r\n").as_bytes().to_vec();
         message.extend(post_body);
-        message.extend_from_slice("\r\n".as_bytes());
 
         message
     }
 
+    /// Format request as a curl command, useful for reproducing issues outside of atlas-http.
+    /// Authorization headers are redacted, use to_curl_redacted(&Redaction::none()) for the
+    /// verbatim request.
+    pub fn to_curl(&self) -> String {
+        self.to_curl_redacted(&Redaction::default())
+    }
+
+    /// Format request as a curl command, masking values as per the redaction rules
+    pub fn to_curl_redacted(&self, redaction: &Redaction) -> String {
+        let quote = |s: &str| format!("'{}'", s.replace('\'', "'\\''"));
+        let req = redaction.request(self);
+        let mut parts = vec!["curl".to_string(), "-X".to_string(), req.method.clone()];
+
+        // Headers, curl generates its own multipart boundary
+        let is_multipart = req.body.is_multipart();
+        for (key, value) in req.headers.all().iter() {
+            let lower = key.to_lowercase();
+            if lower == "content-length" || (is_multipart && lower == "content-type") {
+                continue;
+            }
+            parts.push("-H".to_string());
+            parts.push(quote(&format!("{}: {}", key, value.join("; "))));
+        }
+
+        // Body
+        if is_multipart {
+            let headers = |key: &str| -> String {
+                req.body
+                    .part_headers(key)
+                    .iter()
+                    .map(|(name, value)| format!(";headers=\"{}: {}\"", name, value))
+                    .collect()
+            };
+            for (key, value) in req.body.params().iter() {
+                parts.push("-F".to_string());
+                parts.push(quote(&format!("{}={}{}", key, value, headers(key))));
+            }
+            for (key, filepath) in req.body.files().iter() {
+                parts.push("-F".to_string());
+                parts.push(quote(&format!("{}=@{}{}", key, filepath, headers(key))));
+            }
+            for (key, file) in req.body.file_bytes().iter() {
+                parts.push("-F".to_string());
+                parts.push(quote(&format!(
+                    "{}=@{};type={}{}",
+                    key,
+                    file.filename,
+                    file.mime,
+                    headers(key)
+                )));
+            }
+        } else if let Some(file_path) = req.body.file() {
+            parts.push("--data-binary".to_string());
+            parts.push(quote(&format!("@{}", file_path)));
+        } else if req.body.is_reader() {
+            parts.push("--data-binary".to_string());
+            parts.push("@-".to_string());
+        } else if req.body.is_form_post() {
+            parts.push("--data-binary".to_string());
+            parts.push(quote(&String::from_utf8_lossy(&req.body.format())));
+        }
+
+        parts.push(quote(&req.url));
+        parts.join(" ")
+    }
+
     /// Build from buf reader
     pub fn build(stream: &mut TcpStream) -> Result<Self, Error> {
+        let peer_addr = stream.peer_addr().ok();
 
         // Get first line
         let mut reader = BufReader::new(stream);
@@ -172,13 +550,21 @@ impl HttpRequest {
             method,
             url: format!("http://127.0.0.1{}", path),
             headers,
-            body
+            body,
+            timeouts: Timeouts::default(),
+            omit_query: Vec::new(),
+            sni: None,
+            save_cookies: true,
+            expect_continue: true,
+            jar: None,
+            peer_addr,
         })
 
     }
 
     /// Build request from stream asynchronously
     pub async fn build_async(stream: &mut tokio::net::TcpStream) -> Result<Self, Error> {
+        let peer_addr = stream.peer_addr().ok();
 
         // Read into buffer
         //let (reader, mut writer) = tokio::io::split(stream);
@@ -232,11 +618,27 @@ impl HttpRequest {
             method,
             url: format!("http://127.0.0.1{}", path),
             headers,
-            body
+            body,
+            timeouts: Timeouts::default(),
+            omit_query: Vec::new(),
+            sni: None,
+            save_cookies: true,
+            expect_continue: true,
+            jar: None,
+            peer_addr,
         })
 
     }
 
+    /// Whether or not method is a valid token as per RFC 9110 section 9.1, eg. PATCH, TRACE or
+    /// extension methods such as PROPFIND
+    pub fn is_valid_method(method: &str) -> bool {
+        !method.is_empty()
+            && method
+                .bytes()
+                .all(|b| b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b))
+    }
+
     /// Parse first line
     pub fn parse_first_line(first_line: &str) -> Result<(String, String), Error> {
 
@@ -246,7 +648,7 @@ impl HttpRequest {
             return Err(Error::Custom("Invalid first line.".to_string()));
         } else if !parts[2].starts_with("HTTP/") {
             return Err(Error::Custom("Invalid first line.".to_string()));
-        } else if !vec!["GET","POST","PUT","DELETE","HEAD","OPTIONS"].contains(&parts[0].to_uppercase().as_str()) {
+        } else if !Self::is_valid_method(parts[0]) {
             return Err(Error::Custom("Invalid first line.".to_string()));
         }
 
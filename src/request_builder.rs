@@ -0,0 +1,134 @@
+use super::{HttpBody, HttpClient, HttpRequest, HttpResponse, HttpSyncClient};
+use crate::error::Error;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// Request built up fluently via request() of either client and sent with send(), eg.
+/// client.request("POST", url).header("Accept", "application/json").json(&value).send().
+/// Errors such as an invalid method are reported by send().
+pub struct RequestBuilder<C> {
+    client: C,
+    req: HttpRequest,
+    error: Option<Error>,
+}
+
+impl<C> RequestBuilder<C> {
+    pub fn new(client: C, method: &str, url: &str) -> Self {
+        let error = match HttpRequest::is_valid_method(method) {
+            true => None,
+            false => Some(Error::Custom(format!("Invalid HTTP method '{}'", method))),
+        };
+
+        Self {
+            client,
+            req: HttpRequest::new(method, url, &Vec::new(), &HttpBody::empty()),
+            error,
+        }
+    }
+
+    /// Set header, replacing any existing header of the same name
+    pub fn header(mut self, key: &str, value: &str) -> Self {
+        self.req = self.req.with_header(key, value);
+        self
+    }
+
+    /// Set headers, eg. vec!["Content-Type: application/json"]
+    pub fn headers(mut self, headers: &Vec<&str>) -> Self {
+        for line in headers {
+            if let Some((key, value)) = line.split_once(':') {
+                self.req = self.req.with_header(key.trim(), value.trim());
+            }
+        }
+        self
+    }
+
+    /// Set query parameter, replacing any existing parameters of the same name
+    pub fn query(mut self, key: &str, value: &str) -> Self {
+        self.req = self.req.with_query_param(key, value);
+        self
+    }
+
+    /// Set body
+    pub fn body(mut self, body: &HttpBody) -> Self {
+        self.req.body = body.clone();
+        self
+    }
+
+    /// Set body by serializing value as JSON, sent with Content-Type: application/json
+    pub fn json<T: Serialize>(mut self, value: &T) -> Self {
+        match HttpBody::from_json(value) {
+            Ok(body) => self.req.body = body,
+            Err(e) => self.error = self.error.or(Some(e)),
+        }
+        self
+    }
+
+    /// Set body to URL encoded form parameters
+    pub fn form(mut self, params: &HashMap<&str, &str>) -> Self {
+        self.req.body = HttpBody::from_map(params);
+        self
+    }
+
+    /// Set deadline for the whole request, including connecting, redirects and reading the body
+    pub fn timeout(mut self, total: Duration) -> Self {
+        self.req.timeouts.total = Some(total);
+        self
+    }
+
+    /// Set connect timeout, overriding the client's
+    pub fn connect_timeout(mut self, connect: Duration) -> Self {
+        self.req.timeouts.connect = Some(connect);
+        self
+    }
+
+    /// Set timeout on each individual read from the server
+    pub fn read_timeout(mut self, read: Duration) -> Self {
+        self.req.timeouts.read = Some(read);
+        self
+    }
+
+    /// Use basic HTTP authentication, replacing the client's Authorization header
+    pub fn basic_auth(mut self, user: &str, password: &str) -> Self {
+        self.req = self.req.basic_auth(user, password);
+        self
+    }
+
+    /// Use bearer token authentication, replacing the client's Authorization header
+    pub fn bearer(mut self, token: &str) -> Self {
+        self.req = self.req.bearer(token);
+        self
+    }
+
+    /// Get the request without sending it, eg. for send_all() or to_curl()
+    pub fn build(self) -> Result<HttpRequest, Error> {
+        match self.error {
+            Some(e) => Err(e),
+            None => Ok(self.req),
+        }
+    }
+}
+
+impl<'a> RequestBuilder<&'a mut HttpSyncClient> {
+    /// Send request via the client
+    pub fn send(self) -> Result<HttpResponse, Error> {
+        let client = self.client;
+        let req = match self.error {
+            Some(e) => return Err(e),
+            None => self.req,
+        };
+        client.send(&req)
+    }
+}
+
+impl<'a> RequestBuilder<&'a mut HttpClient> {
+    /// Send request via the client
+    pub async fn send(self) -> Result<HttpResponse, Error> {
+        let client = self.client;
+        let req = match self.error {
+            Some(e) => return Err(e),
+            None => self.req,
+        };
+        client.send(&req).await
+    }
+}
@@ -0,0 +1,167 @@
+use crate::clock::{Clock, SystemClock};
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Resolves host names to socket addresses before connecting, eg. to plug in a caching or DNS
+/// over HTTPS resolver, or fixed addresses within tests.  Set via HttpClientBuilder::resolver().
+pub trait Resolver: fmt::Debug + Send + Sync {
+    /// Resolve host, without any surrounding brackets for IPv6 addresses, into one or more
+    /// addresses with the given port.  Addresses are tried in order until a connection succeeds.
+    fn resolve(&self, host: &str, port: u16) -> io::Result<Vec<SocketAddr>>;
+
+    /// Same as resolve(), plus the TTL of the records if known, which CachingResolver honors.
+    /// Defaults to resolve() with an unknown TTL.
+    fn resolve_with_ttl(
+        &self,
+        host: &str,
+        port: u16,
+    ) -> io::Result<(Vec<SocketAddr>, Option<Duration>)> {
+        Ok((self.resolve(host, port)?, None))
+    }
+}
+
+impl<R: Resolver + ?Sized> Resolver for Arc<R> {
+    fn resolve(&self, host: &str, port: u16) -> io::Result<Vec<SocketAddr>> {
+        (**self).resolve(host, port)
+    }
+
+    fn resolve_with_ttl(
+        &self,
+        host: &str,
+        port: u16,
+    ) -> io::Result<(Vec<SocketAddr>, Option<Duration>)> {
+        (**self).resolve_with_ttl(host, port)
+    }
+}
+
+/// Which resolved addresses are connected to, and in which order
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IpFamily {
+    /// Addresses in the order resolved
+    Any,
+    /// IPv4 addresses before IPv6 ones
+    PreferIpv4,
+    /// IPv6 addresses before IPv4 ones
+    PreferIpv6,
+    /// IPv4 addresses only
+    Ipv4Only,
+}
+
+impl IpFamily {
+    /// Order and filter resolved addresses
+    pub fn apply(&self, mut addrs: Vec<SocketAddr>) -> Vec<SocketAddr> {
+        match self {
+            IpFamily::Any => {}
+            IpFamily::PreferIpv4 => addrs.sort_by_key(|addr| addr.is_ipv6()),
+            IpFamily::PreferIpv6 => addrs.sort_by_key(|addr| addr.is_ipv4()),
+            IpFamily::Ipv4Only => addrs.retain(|addr| addr.is_ipv4()),
+        }
+        addrs
+    }
+}
+
+/// Resolver of the operating system, via getaddrinfo() or equivalent
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemResolver;
+
+impl Resolver for SystemResolver {
+    fn resolve(&self, host: &str, port: u16) -> io::Result<Vec<SocketAddr>> {
+        Ok((host, port).to_socket_addrs()?.collect())
+    }
+}
+
+/// Caches the results of another resolver, for the TTL of the records or the default TTL if
+/// unknown as with the system resolver.  Once max_entries host / port pairs are cached, expired
+/// entries and then those closest to expiring are evicted.
+#[derive(Debug)]
+pub struct CachingResolver {
+    inner: Box<dyn Resolver>,
+    ttl: Duration,
+    max_entries: usize,
+    clock: Arc<dyn Clock>,
+    cache: Mutex<HashMap<(String, u16), (Vec<SocketAddr>, Instant)>>,
+}
+
+impl CachingResolver {
+    /// Cache results of the given resolver
+    pub fn new<R: Resolver + 'static>(inner: R, ttl: Duration, max_entries: usize) -> Self {
+        Self {
+            inner: Box::new(inner),
+            ttl,
+            max_entries,
+            clock: Arc::new(SystemClock),
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Expire cached results as per the given clock instead of the system clock
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Number of cached host / port pairs, including expired ones not yet evicted
+    pub fn len(&self) -> usize {
+        self.cache.lock().unwrap().len()
+    }
+
+    /// Whether or not the cache is empty
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Remove all cached results
+    pub fn clear(&self) {
+        self.cache.lock().unwrap().clear();
+    }
+}
+
+impl Resolver for CachingResolver {
+    fn resolve(&self, host: &str, port: u16) -> io::Result<Vec<SocketAddr>> {
+        Ok(self.resolve_with_ttl(host, port)?.0)
+    }
+
+    fn resolve_with_ttl(
+        &self,
+        host: &str,
+        port: u16,
+    ) -> io::Result<(Vec<SocketAddr>, Option<Duration>)> {
+        let key = (host.to_lowercase(), port);
+        let now = self.clock.instant();
+
+        // Check cache
+        if let Some((addrs, expires)) = self.cache.lock().unwrap().get(&key) {
+            if *expires > now {
+                return Ok((addrs.clone(), Some(*expires - now)));
+            }
+        }
+
+        // Resolve, lock is not held so other hosts resolve concurrently
+        let (addrs, ttl) = self.inner.resolve_with_ttl(host, port)?;
+        let ttl = ttl.unwrap_or(self.ttl);
+        if addrs.is_empty() || ttl.is_zero() || self.max_entries == 0 {
+            return Ok((addrs, Some(ttl)));
+        }
+
+        // Make room, expired entries first
+        let mut cache = self.cache.lock().unwrap();
+        cache.retain(|_, (_, expires)| *expires > now);
+        while cache.len() >= self.max_entries && !cache.contains_key(&key) {
+            let soonest = cache
+                .iter()
+                .min_by_key(|(_, (_, expires))| *expires)
+                .map(|(name, _)| name.clone());
+            match soonest {
+                Some(name) => cache.remove(&name),
+                None => break,
+            };
+        }
+        cache.insert(key, (addrs.clone(), now + ttl));
+
+        Ok((addrs, Some(ttl)))
+    }
+}
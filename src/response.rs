@@ -1,8 +1,55 @@
 #![allow(clippy::large_enum_variant)]
 
 use super::{HttpHeaders, HttpRequest};
-use crate::error::{Error, InvalidFirstLineError, InvalidResponseError};
-use std::io::BufRead;
+use crate::codec;
+use crate::connection::TlsInfo;
+use crate::error::{Error, InvalidFirstLineError, InvalidResponseError, JsonError};
+use crate::hash::HashAlgorithm;
+use crate::http_date;
+use crate::problem::{self, Problem};
+use crate::timings::{Phase, Timings};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::io::{BufRead, ErrorKind, Read, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+use url::Url;
+
+/// User defined transformation of the response body, executed after decompression and charset
+/// decoding.  Receives the response headers and body, and returns the new body.
+pub type BodyTransformer = fn(&HttpHeaders, Vec<u8>) -> Vec<u8>;
+
+/// Returned by the on_headers callback to decide whether or not the response body is read
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HeaderDecision {
+    Continue,
+    Abort,
+}
+
+/// Outcome of a Prometheus remote-write request, as per the protocol's retry semantics
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RemoteWriteOutcome {
+    /// 2xx, samples were written
+    Accepted,
+    /// 429 or 5xx, the same request should be retried with backoff
+    Retry,
+    /// Any other status, samples were rejected and must not be retried
+    Rejected,
+}
+
+const LF_ONLY: &str = "Header lines terminated by LF only";
+
+/// How strictly response headers are parsed
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ParseMode {
+    /// Reject obsolete line folding, whitespace before the colon and lines without a colon
+    Strict,
+    /// Unfold obsolete line folding, tolerate stray whitespace and skip lines without a colon
+    Standard,
+    /// Same as Standard, but also accept malformed status lines such as a missing reason phrase,
+    /// blank lines preceding them, and ambiguous Content-Length / Transfer-Encoding framing
+    Lenient,
+}
 
 #[derive(Clone, Debug)]
 pub struct HttpResponse {
@@ -10,10 +57,224 @@ pub struct HttpResponse {
     status_code: u16,
     reason: String,
     headers: HttpHeaders,
-    body: String,
+    body: Vec<u8>,
+    request: Option<HttpRequest>,
+    alpn_protocol: Option<String>,
+    tls_info: Option<TlsInfo>,
+    body_hash: Option<String>,
+    warnings: Vec<String>,
+    must_close: bool,
+    received: Option<SystemTime>,
+    redirects: Vec<RedirectHop>,
+    bytes_sent: u64,
+    bytes_received: u64,
+    timings: Timings,
+}
+
+/// Redirect followed on the way to the final response, with the cookies it set
+#[derive(Clone, Debug, PartialEq)]
+pub struct RedirectHop {
+    pub url: String,
+    pub status: u16,
+    pub location: String,
+    pub set_cookie: Vec<String>,
+}
+
+impl RedirectHop {
+    /// Get value of the cookie of the given name set by this hop, if any
+    pub fn cookie(&self, name: &str) -> Option<String> {
+        self.set_cookie.iter().find_map(|line| {
+            let pair = line.split(';').next()?;
+            let (key, value) = pair.split_once('=')?;
+            (key.trim() == name).then(|| value.trim().to_string())
+        })
+    }
+}
+
+/// Builder for responses, used by mock transports and server handlers
+#[derive(Clone, Debug)]
+pub struct HttpResponseBuilder {
+    version: String,
+    status_code: u16,
+    reason: Option<String>,
+    headers: HttpHeaders,
+    body: Vec<u8>,
+}
+
+impl Default for HttpResponseBuilder {
+    fn default() -> HttpResponseBuilder {
+        Self::new()
+    }
+}
+
+impl HttpResponseBuilder {
+    pub fn new() -> Self {
+        Self {
+            version: "1.1".to_string(),
+            status_code: 200,
+            reason: None,
+            headers: HttpHeaders::new(),
+            body: Vec::new(),
+        }
+    }
+
+    /// Set HTTP status code
+    pub fn status(mut self, status: u16) -> Self {
+        self.status_code = status;
+        self
+    }
+
+    /// Set reason phrase, defaults to the standard phrase of the status code
+    pub fn reason(mut self, reason: &str) -> Self {
+        self.reason = Some(reason.to_string());
+        self
+    }
+
+    /// Set protocol version, defaults to 1.1
+    pub fn version(mut self, version: &str) -> Self {
+        self.version = version.to_string();
+        self
+    }
+
+    /// Add header
+    pub fn header(mut self, key: &str, value: &str) -> Self {
+        self.headers.add(key, value);
+        self
+    }
+
+    /// Set body from bytes
+    pub fn body_bytes(mut self, body: &[u8]) -> Self {
+        self.body = body.to_vec();
+        self
+    }
+
+    /// Set body from str
+    pub fn body(mut self, body: &str) -> Self {
+        self.body = body.as_bytes().to_vec();
+        self
+    }
+
+    /// Finish building, adding Content-Length header if not already present
+    pub fn build(mut self) -> HttpResponse {
+        if !self.headers.has_lower("content-length") {
+            self.headers
+                .set("Content-Length", &self.body.len().to_string());
+        }
+
+        HttpResponse {
+            version: self.version,
+            status_code: self.status_code,
+            reason: self
+                .reason
+                .unwrap_or_else(|| HttpResponse::reason_phrase(self.status_code).to_string()),
+            headers: self.headers,
+            body: self.body,
+            request: None,
+            alpn_protocol: None,
+            tls_info: None,
+            body_hash: None,
+            warnings: Vec::new(),
+            must_close: false,
+            received: None,
+            redirects: Vec::new(),
+            bytes_sent: 0,
+            bytes_received: 0,
+            timings: Timings::default(),
+        }
+    }
 }
 
 impl HttpResponse {
+    /// Instantiate response builder
+    pub fn builder() -> HttpResponseBuilder {
+        HttpResponseBuilder::new()
+    }
+
+    /// 200 OK response with value serialized as JSON body.  Returns a 500 response with the
+    /// error message if value can not be serialized.
+    pub fn ok_json<T: Serialize>(value: &T) -> Self {
+        match serde_json::to_vec(value) {
+            Ok(body) => Self::builder()
+                .header("Content-Type", "application/json")
+                .body_bytes(&body)
+                .build(),
+            Err(e) => Self::builder()
+                .status(500)
+                .header("Content-Type", "text/plain")
+                .body(&e.to_string())
+                .build(),
+        }
+    }
+
+    /// 404 Not Found response
+    pub fn not_found() -> Self {
+        Self::builder()
+            .status(404)
+            .header("Content-Type", "text/plain")
+            .body("Not Found")
+            .build()
+    }
+
+    /// 302 Found response redirecting to url
+    pub fn redirect(url: &str) -> Self {
+        Self::builder().status(302).header("Location", url).build()
+    }
+
+    /// Error response with problem details of RFC 9457 as application/problem+json body, eg.
+    /// problem(403, "Insufficient credit", "Your balance is 30, but that costs 50")
+    pub fn problem(status: u16, title: &str, detail: &str) -> Self {
+        Self::from_problem(&Problem::new(status, title, detail))
+    }
+
+    /// Error response with the given problem details, for problems with a type, instance or
+    /// extension members.  The status defaults to 500 if the problem has none.
+    pub fn from_problem(problem: &Problem) -> Self {
+        let status = problem.status.unwrap_or(500);
+        let body = serde_json::to_vec(problem).unwrap_or_default();
+        Self::builder()
+            .status(status)
+            .header("Content-Type", problem::CONTENT_TYPE)
+            .body_bytes(&body)
+            .build()
+    }
+
+    /// Standard reason phrase of HTTP status code, empty if unknown
+    pub fn reason_phrase(status: u16) -> &'static str {
+        match status {
+            100 => "Continue",
+            101 => "Switching Protocols",
+            200 => "OK",
+            201 => "Created",
+            202 => "Accepted",
+            204 => "No Content",
+            206 => "Partial Content",
+            301 => "Moved Permanently",
+            302 => "Found",
+            303 => "See Other",
+            304 => "Not Modified",
+            307 => "Temporary Redirect",
+            308 => "Permanent Redirect",
+            400 => "Bad Request",
+            401 => "Unauthorized",
+            403 => "Forbidden",
+            404 => "Not Found",
+            405 => "Method Not Allowed",
+            409 => "Conflict",
+            410 => "Gone",
+            413 => "Content Too Large",
+            415 => "Unsupported Media Type",
+            422 => "Unprocessable Content",
+            425 => "Too Early",
+            429 => "Too Many Requests",
+            500 => "Internal Server Error",
+            501 => "Not Implemented",
+            502 => "Bad Gateway",
+            503 => "Service Unavailable",
+            504 => "Gateway Timeout",
+            _ => "",
+        }
+    }
+
     /// Instantiate response with minimal properties
     pub fn new(status: &u16, headers: &Vec<String>, body: &String) -> Self {
         Self::new_full(
@@ -38,7 +299,18 @@ impl HttpResponse {
             status_code: *status,
             reason: reason.clone(),
             headers: headers.clone(),
-            body: body.trim().trim_end_matches('0').to_string(),
+            body: body.trim().trim_end_matches('0').as_bytes().to_vec(),
+            request: None,
+            alpn_protocol: None,
+            tls_info: None,
+            body_hash: None,
+            warnings: Vec::new(),
+            must_close: false,
+            received: None,
+            redirects: Vec::new(),
+            bytes_sent: 0,
+            bytes_received: 0,
+            timings: Timings::default(),
         }
     }
 
@@ -57,6 +329,14 @@ impl HttpResponse {
         self.reason.clone()
     }
 
+    /// Replace reason message with the standard reason phrase of the status code, if known
+    pub fn normalize_reason(&mut self) {
+        let reason = Self::reason_phrase(self.status_code);
+        if !reason.is_empty() {
+            self.reason = reason.to_string();
+        }
+    }
+
     /// Get http headers
     pub fn headers(&self) -> HttpHeaders {
         self.headers.clone()
@@ -64,9 +344,176 @@ impl HttpResponse {
 
     /// Get body of response
     pub fn body(&self) -> String {
+        String::from_utf8_lossy(&self.body).to_string()
+    }
+
+    /// Get body of response as bytes
+    pub fn body_bytes(&self) -> Vec<u8> {
         self.body.clone()
     }
 
+    /// Deserialize body as JSON, ignoring a leading UTF-8 byte order mark and surrounding
+    /// whitespace.  On failure the error includes the raw body.
+    pub fn json<T: DeserializeOwned>(&self) -> Result<T, Error> {
+        let body = self
+            .body
+            .strip_prefix(b"\xEF\xBB\xBF")
+            .unwrap_or(&self.body);
+        serde_json::from_slice(body.trim_ascii()).map_err(|e| {
+            Error::Json(JsonError {
+                url: self
+                    .request
+                    .as_ref()
+                    .map(|req| req.url.clone())
+                    .unwrap_or_default(),
+                error: e.to_string(),
+                body: self.body.clone(),
+            })
+        })
+    }
+
+    /// Parse problem details of RFC 9457 if the body is application/problem+json, None
+    /// otherwise
+    pub fn as_problem(&self) -> Option<Problem> {
+        let content_type = self.headers.get_lower("content-type")?;
+        let media_type = content_type.split(';').next().unwrap_or("").trim();
+        if !media_type.eq_ignore_ascii_case(problem::CONTENT_TYPE) {
+            return None;
+        }
+        self.json().ok()
+    }
+
+    /// Check outcome of a Prometheus remote-write request sent with HttpBody::from_protobuf()
+    pub fn remote_write_outcome(&self) -> RemoteWriteOutcome {
+        match self.status_code {
+            200..=299 => RemoteWriteOutcome::Accepted,
+            429 | 500..=599 => RemoteWriteOutcome::Retry,
+            _ => RemoteWriteOutcome::Rejected,
+        }
+    }
+
+    /// Get the request that was actually sent, after client headers, cookies, authentication
+    /// and redirects were applied.  None if the response was not received by a client.
+    pub fn effective_request(&self) -> Option<HttpRequest> {
+        self.request.clone()
+    }
+
+    /// Set the request that produced this response
+    pub fn set_effective_request(&mut self, req: &HttpRequest) {
+        self.request = Some(req.clone());
+    }
+
+    /// Get scheme of the URL the response was received from, eg. whether HttpClient::get_auto()
+    /// fell back to http.  None if the response was not received by a client.
+    pub fn scheme(&self) -> Option<String> {
+        let req = self.request.as_ref()?;
+        Url::parse(&req.url)
+            .ok()
+            .map(|uri| uri.scheme().to_string())
+    }
+
+    /// Get time the response header was received, None if the response was not received by a client
+    pub fn received_at(&self) -> Option<SystemTime> {
+        self.received
+    }
+
+    /// Get time within the Date header, None if missing or invalid
+    pub fn date(&self) -> Option<SystemTime> {
+        http_date::parse(&self.headers.get_lower_line("date")?)
+    }
+
+    /// Get difference between the clock of the server and the local clock in seconds, positive if
+    /// the server is ahead.  Based on the Date header, so only accurate to about a second.  Apply
+    /// to timestamps via http_date::skewed(SystemTime::now(), skew), eg. before SigV4::sign_at().
+    pub fn clock_skew(&self) -> Option<i64> {
+        let secs = |time: SystemTime| {
+            time.duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs() as i64)
+                .unwrap_or(0)
+        };
+        Some(secs(self.date()?) - secs(self.received?))
+    }
+
+    /// Get redirects followed before this response, in order, including the Set-Cookie headers
+    /// of each such as session cookies set by an intermediate 302 of a login flow
+    pub fn redirects(&self) -> Vec<RedirectHop> {
+        self.redirects.clone()
+    }
+
+    /// Set redirects followed before this response
+    pub fn set_redirects(&mut self, redirects: Vec<RedirectHop>) {
+        self.redirects = redirects;
+    }
+
+    /// Get protocol negotiated via ALPN during the TLS handshake, if any
+    pub fn alpn_protocol(&self) -> Option<String> {
+        self.alpn_protocol.clone()
+    }
+
+    /// Set protocol negotiated via ALPN
+    pub fn set_alpn_protocol(&mut self, protocol: Option<String>) {
+        self.alpn_protocol = protocol;
+    }
+
+    /// Get version, cipher suite and certificate chain of the TLS session the response was
+    /// received over, None for plain HTTP
+    pub fn tls_info(&self) -> Option<TlsInfo> {
+        self.tls_info.clone()
+    }
+
+    /// Set details of the TLS session
+    pub fn set_tls_info(&mut self, info: Option<TlsInfo>) {
+        self.tls_info = info;
+    }
+
+    /// Get hex digest of the body, computed while it was read if enabled via
+    /// HttpClientBuilder::hash_body()
+    pub fn body_hash(&self) -> Option<String> {
+        self.body_hash.clone()
+    }
+
+    /// Set hex digest of the body
+    pub fn set_body_hash(&mut self, digest: Option<String>) {
+        self.body_hash = digest;
+    }
+
+    /// Bytes written to the server for the request, including the header and any redirects
+    /// followed, 0 unless received by one of the clients
+    pub fn bytes_sent(&self) -> u64 {
+        self.bytes_sent
+    }
+
+    /// Bytes read from the server for the response as transferred, including the header and
+    /// any redirects followed, 0 unless received by one of the clients
+    pub fn bytes_received(&self) -> u64 {
+        self.bytes_received
+    }
+
+    /// Set bytes written to and read from the server
+    pub fn set_transfer(&mut self, sent: u64, received: u64) {
+        self.bytes_sent = sent;
+        self.bytes_received = received;
+    }
+
+    /// Get durations of the DNS lookup, connect and TLS handshake of the connection the
+    /// response was received over, plus time to first byte and total time from the start of the
+    /// request until the body was read, including any redirects followed.  DNS, connect and TLS
+    /// are None on reused connections, and all are zero unless received by one of the clients.
+    pub fn timings(&self) -> Timings {
+        self.timings
+    }
+
+    /// Set durations of the phases of the request
+    pub fn set_timings(&mut self, timings: Timings) {
+        self.timings = timings;
+    }
+
+    /// Get problems tolerated while parsing the response, such as folded headers or a
+    /// malformed status line in lenient mode
+    pub fn warnings(&self) -> Vec<String> {
+        self.warnings.clone()
+    }
+
     /// Get the raw response including headers and body
     pub fn raw(&self) -> String {
         let headers_str = self
@@ -79,7 +526,11 @@ impl HttpResponse {
 
         let res = format!(
             "HTTP/{} {} {}\r\n{}\n\n{}\n\n",
-            self.version, self.status_code, self.reason, &headers_str, self.body
+            self.version,
+            self.status_code,
+            self.reason,
+            &headers_str,
+            self.body()
         );
         res.to_string()
     }
@@ -90,61 +541,371 @@ impl HttpResponse {
         req: &HttpRequest,
         dest_file: &str,
     ) -> Result<Self, Error> {
-        // Get first line
+        let mut res = Self::read_head(reader, req)?;
+        if dest_file.is_empty() {
+            res.read_body(reader)?;
+        }
+        Ok(res)
+    }
+
+    /// Read first line and headers of response, leaving the body unread within the reader
+    pub fn read_head(reader: &mut dyn BufRead, req: &HttpRequest) -> Result<Self, Error> {
+        Self::read_head_mode(reader, req, ParseMode::Standard)
+    }
+
+    /// Same as read_head(), but with the given header parsing mode.  Interim 1xx responses, such
+    /// as a 100 Continue arriving after the body was sent, are skipped, except for 101 Switching
+    /// Protocols.
+    pub fn read_head_mode(
+        reader: &mut dyn BufRead,
+        req: &HttpRequest,
+        mode: ParseMode,
+    ) -> Result<Self, Error> {
+        loop {
+            let res = Self::read_single_head(reader, req, mode)?;
+            if !(100..200).contains(&res.status_code) || res.status_code == 101 {
+                return Ok(res);
+            }
+        }
+    }
+
+    // Read a single status line and header, final or interim
+    fn read_single_head(
+        reader: &mut dyn BufRead,
+        req: &HttpRequest,
+        mode: ParseMode,
+    ) -> Result<Self, Error> {
+        let read_err = |e: std::io::Error| read_error(&req.url, Phase::Header, e);
+        let malformed = |line: &str| Error::MalformedHeader(req.url.clone(), line.to_string());
+
+        let mut warnings = Vec::new();
+
+        // Get first line, lenient mode skips any blank lines preceding it
         let mut first_line = String::new();
-        match reader.read_line(&mut first_line) {
-            Ok(_) => {}
-            Err(e) => {
-                return Err(Error::NoRead(InvalidResponseError {
-                    url: req.url.clone(),
-                    response: e.to_string(),
-                }));
+        loop {
+            first_line.clear();
+            if read_line_latin1(reader, &mut first_line, &mut warnings).map_err(read_err)? == 0
+                || mode != ParseMode::Lenient
+                || !first_line.trim().is_empty()
+            {
+                break;
             }
-        };
+        }
 
         // Parse first line
-        let (version, status, reason) = Self::parse_first_line(&first_line, req)?;
+        let (version, status, reason) = match Self::parse_first_line(&first_line, req) {
+            Ok(r) => r,
+            Err(e) if mode == ParseMode::Lenient => {
+                match Self::parse_first_line_lenient(&first_line) {
+                    Some(r) => {
+                        warnings.push(format!("Malformed status line: {}", first_line.trim()));
+                        r
+                    }
+                    None => return Err(e),
+                }
+            }
+            Err(e) => return Err(e),
+        };
 
         // Get headers
-        let mut header_lines = Vec::new();
+        let mut header_lines: Vec<String> = Vec::new();
         loop {
             let mut line = String::new();
-            match reader.read_line(&mut line) {
-                Ok(_) => {}
-                Err(e) => {
-                    return Err(Error::NoRead(InvalidResponseError {
-                        url: req.url.clone(),
-                        response: e.to_string(),
-                    }));
-                }
-            };
-
+            read_line_latin1(reader, &mut line, &mut warnings).map_err(read_err)?;
+            let is_lf_only = line.ends_with('\n') && !line.ends_with("\r\n");
+            if is_lf_only && !warnings.iter().any(|w| w == LF_ONLY) {
+                warnings.push(LF_ONLY.to_string());
+            }
+            let line = line.trim_end_matches(['\r', '\n']);
             if line.trim().is_empty() {
                 break;
             }
-            header_lines.push(line.trim().to_string());
+
+            // Obsolete line folding continues the previous header, RFC 9112 section 5.2
+            if line.starts_with(' ') || line.starts_with('\t') {
+                match header_lines.last_mut() {
+                    Some(prev) if mode != ParseMode::Strict => {
+                        warnings.push(format!("Unfolded obsolete line folding: {}", line.trim()));
+                        prev.push(' ');
+                        prev.push_str(line.trim());
+                        continue;
+                    }
+                    _ => return Err(malformed(line)),
+                }
+            }
+
+            // Split name from value, tolerating whitespace around the colon
+            let (name, value) = match line.split_once(':') {
+                Some(r) => r,
+                None if mode == ParseMode::Strict => return Err(malformed(line)),
+                None => {
+                    warnings.push(format!("Skipped header line without colon: {}", line));
+                    continue;
+                }
+            };
+            if name.trim().is_empty() || (mode == ParseMode::Strict && name != name.trim()) {
+                return Err(malformed(line));
+            }
+            header_lines.push(format!("{}: {}", name.trim(), value.trim()));
         }
-        let headers = HttpHeaders::from_vec(&header_lines);
 
-        // Chunked transfer encoding
-        if headers.has_lower("transfer-encoding")
-            && headers.get_lower("transfer-encoding").unwrap().as_str() == "chunked"
-        {
-            let mut _tmp = String::new();
-            reader.read_line(&mut _tmp).unwrap();
+        // Conflicting Content-Length and Transfer-Encoding headers make the end of the body
+        // ambiguous, allowing responses to be smuggled through shared connections, RFC 9112
+        // section 6.3.  Lenient mode instead drops the Content-Length and closes the connection.
+        let is_named = |line: &String, header: &str| {
+            line.split_once(':')
+                .map(|(name, _)| name.eq_ignore_ascii_case(header))
+                .unwrap_or(false)
+        };
+        let is_length = |line: &String| is_named(line, "content-length");
+        let lengths: Vec<String> = header_lines
+            .iter()
+            .filter(|line| is_length(line))
+            .filter_map(|line| line.split_once(':'))
+            .flat_map(|(_, value)| value.split(',').map(|v| v.trim().to_string()))
+            .collect();
+        let has_encoding = header_lines
+            .iter()
+            .any(|line| is_named(line, "transfer-encoding"));
+        let ambiguous = |reason: String| Error::AmbiguousFraming(req.url.clone(), reason);
+
+        let mut must_close = false;
+        if !lengths.is_empty() && has_encoding {
+            if mode != ParseMode::Lenient {
+                return Err(ambiguous(
+                    "both Content-Length and Transfer-Encoding present".to_string(),
+                ));
+            }
+            warnings.push("Ignored Content-Length as Transfer-Encoding is present".to_string());
+            header_lines.retain(|line| !is_length(line));
+            must_close = true;
+        } else if lengths.iter().any(|length| *length != lengths[0]) {
+            if mode != ParseMode::Lenient {
+                return Err(ambiguous(format!(
+                    "conflicting Content-Length {}",
+                    lengths.join(", ")
+                )));
+            }
+            warnings.push(format!(
+                "Ignored conflicting Content-Length: {}",
+                lengths.join(", ")
+            ));
+            header_lines.retain(|line| !is_length(line));
+            must_close = true;
+        } else if lengths.len() > 1 {
+            if mode == ParseMode::Strict {
+                return Err(ambiguous(format!(
+                    "repeated Content-Length {}",
+                    lengths.join(", ")
+                )));
+            }
+            warnings.push(format!(
+                "Merged repeated Content-Length: {}",
+                lengths.join(", ")
+            ));
+            header_lines.retain(|line| !is_length(line));
+            header_lines.push(format!("Content-Length: {}", lengths[0]));
         }
+        let mut headers = HttpHeaders::from_vec(&header_lines);
 
-        // Get body
-        let mut body = String::new();
-        if dest_file.is_empty() {
-            reader.read_to_string(&mut body);
+        // Invalid Content-Length, body is read until the connection closes
+        if let Some(length) = headers.get_lower_line("content-length") {
+            if length.trim().parse::<u64>().is_err() {
+                if mode == ParseMode::Strict {
+                    return Err(malformed(&format!("Content-Length: {}", length)));
+                }
+                warnings.push(format!("Ignored invalid Content-Length: {}", length));
+                headers.delete_lower("content-length");
+            }
         }
 
         // Get response
-        let res = Self::new_full(&status, &headers, &body, &version, &reason);
+        let mut res = Self::new_full(&status, &headers, &String::new(), &version, &reason);
+        res.request = Some(req.clone());
+        res.warnings = warnings;
+        res.must_close = must_close;
+        res.received = Some(SystemTime::now());
         Ok(res)
     }
 
+    /// Whether or not the connection must be closed after this response, as its framing was
+    /// ambiguous and only leniently accepted
+    pub fn must_close(&self) -> bool {
+        self.must_close
+    }
+
+    /// Whether or not a body follows the header, false for HEAD requests and 1xx, 204 and 304
+    /// responses
+    pub fn has_body(&self) -> bool {
+        let is_head = self
+            .request
+            .as_ref()
+            .map(|req| req.method == "HEAD")
+            .unwrap_or(false);
+        !(is_head || self.status_code < 200 || self.status_code == 204 || self.status_code == 304)
+    }
+
+    /// Read body of response from the reader, removing chunked framing and content encoding, and
+    /// converting text to UTF-8.
+    pub fn read_body(&mut self, reader: &mut dyn BufRead) -> Result<(), Error> {
+        self.read_body_hashed(reader, None)
+    }
+
+    /// Same as read_body(), but also hashes the decompressed body while it is being read.  The
+    /// digest is available via body_hash().
+    pub fn read_body_hashed(
+        &mut self,
+        reader: &mut dyn BufRead,
+        algorithm: Option<HashAlgorithm>,
+    ) -> Result<(), Error> {
+        if !self.has_body() {
+            return Ok(());
+        }
+
+        let mut body = Vec::new();
+        let mut body_reader = codec::body_reader(reader, &self.headers, algorithm, true);
+        if let Err(e) = body_reader.read_to_end(&mut body) {
+            let url = self
+                .request
+                .as_ref()
+                .map(|req| req.url.clone())
+                .unwrap_or_default();
+            return Err(read_error(&url, Phase::Body, e));
+        }
+
+        self.body_hash = body_reader.digest();
+        self.body = body;
+        Ok(())
+    }
+
+    /// Stream body of response from the reader into writer, removing chunked framing and content
+    /// encoding, and converting text to UTF-8 if charset is set.  Transformers require the full
+    /// body, so it is buffered in memory if any are given.
+    pub fn write_body(
+        &mut self,
+        reader: &mut dyn BufRead,
+        writer: &mut dyn Write,
+        algorithm: Option<HashAlgorithm>,
+        charset: bool,
+        transformers: &[BodyTransformer],
+    ) -> Result<(), Error> {
+        if !self.has_body() {
+            return Ok(());
+        }
+        let url = self
+            .request
+            .as_ref()
+            .map(|req| req.url.clone())
+            .unwrap_or_default();
+        let write_err = |e: std::io::Error| Error::NoWrite(e.to_string());
+
+        let mut body_reader = codec::body_reader(reader, &self.headers, algorithm, charset);
+        let mut body: Vec<u8> = Vec::new();
+        let mut buffer = [0u8; 2048];
+        loop {
+            let bytes_read = match body_reader.read(&mut buffer) {
+                Ok(r) => r,
+                Err(e) => return Err(read_error(&url, Phase::Body, e)),
+            };
+
+            if bytes_read == 0 {
+                break;
+            }
+
+            if transformers.is_empty() {
+                writer.write_all(&buffer[..bytes_read]).map_err(write_err)?;
+            } else {
+                body.extend_from_slice(&buffer[..bytes_read]);
+            }
+        }
+
+        // Transformers require the full body
+        if !transformers.is_empty() {
+            body = transform(&self.headers, body, transformers);
+            writer.write_all(&body).map_err(write_err)?;
+        }
+        self.body_hash = body_reader.digest();
+
+        Ok(())
+    }
+
+    /// Stream body of response from the reader into an async writer, as per write_body()
+    pub async fn write_body_async<R, W>(
+        &mut self,
+        reader: R,
+        writer: &mut W,
+        algorithm: Option<HashAlgorithm>,
+        charset: bool,
+        transformers: &[BodyTransformer],
+    ) -> Result<(), Error>
+    where
+        R: BufRead + Send,
+        W: AsyncWrite + Unpin + Send,
+    {
+        if !self.has_body() {
+            return Ok(());
+        }
+        let url = self
+            .request
+            .as_ref()
+            .map(|req| req.url.clone())
+            .unwrap_or_default();
+        let write_err = |e: std::io::Error| Error::NoWrite(e.to_string());
+
+        let mut body_reader = codec::body_reader(reader, &self.headers, algorithm, charset);
+        let mut body: Vec<u8> = Vec::new();
+        let mut buffer = [0u8; 2048];
+        loop {
+            let bytes_read = match body_reader.read(&mut buffer) {
+                Ok(r) => r,
+                Err(e) => return Err(read_error(&url, Phase::Body, e)),
+            };
+
+            if bytes_read == 0 {
+                break;
+            }
+
+            if transformers.is_empty() {
+                writer
+                    .write_all(&buffer[..bytes_read])
+                    .await
+                    .map_err(write_err)?;
+            } else {
+                body.extend_from_slice(&buffer[..bytes_read]);
+            }
+        }
+
+        // Transformers require the full body
+        if !transformers.is_empty() {
+            body = transform(&self.headers, body, transformers);
+            writer.write_all(&body).await.map_err(write_err)?;
+        }
+        writer.flush().await.map_err(write_err)?;
+        self.body_hash = body_reader.digest();
+
+        Ok(())
+    }
+
+    /// Pass body through user defined transformers, in order
+    pub fn transform_body(&mut self, transformers: &[BodyTransformer]) {
+        self.body = transform(&self.headers, std::mem::take(&mut self.body), transformers);
+    }
+
+    // Parse malformed first line, only requiring a three digit status code
+    fn parse_first_line_lenient(first_line: &str) -> Option<(String, u16, String)> {
+        let parts: Vec<&str> = first_line.split_whitespace().collect();
+        let is_status = |s: &str| s.len() == 3 && s.chars().all(|c| c.is_ascii_digit());
+        if parts.len() < 2 || !is_status(parts[1]) {
+            return None;
+        }
+
+        Some((
+            parts[0].trim_start_matches("HTTP/").to_string(),
+            parts[1].parse::<u16>().unwrap(),
+            parts[2..].join(" "),
+        ))
+    }
+
     /// Parse first line
     pub fn parse_first_line(
         first_line: &str,
@@ -153,12 +914,14 @@ impl HttpResponse {
         // Parse first line
         let mut is_valid = true;
         let parts = first_line
+            .trim_end_matches(['\r', '\n'])
             .trim_start_matches("HTTP/")
-            .split(' ')
+            .splitn(3, ' ')
             .collect::<Vec<&str>>();
+        let status = parts.get(1).copied().unwrap_or("");
         if !["1.0", "1.1", "2", "3"].contains(&parts[0]) {
             is_valid = false;
-        } else if parts[1].len() != 3 || !parts[1].chars().all(|c| c.is_ascii_digit()) {
+        } else if status.len() != 3 || !status.chars().all(|c| c.is_ascii_digit()) {
             is_valid = false;
         }
 
@@ -172,8 +935,49 @@ impl HttpResponse {
 
         Ok((
             parts[0].to_string(),
-            parts[1].parse::<u16>().unwrap(),
-            parts[2].to_string(),
+            status.parse::<u16>().unwrap(),
+            parts.get(2).copied().unwrap_or("").to_string(),
         ))
     }
 }
+
+// Read line of the response header into buf, decoding it as Latin-1 if it is not valid UTF-8,
+// as sent by some appliances within reason phrases and header values
+fn read_line_latin1(
+    reader: &mut dyn BufRead,
+    buf: &mut String,
+    warnings: &mut Vec<String>,
+) -> std::io::Result<usize> {
+    let mut bytes = Vec::new();
+    let len = reader.read_until(b'\n', &mut bytes)?;
+    match String::from_utf8(bytes) {
+        Ok(line) => buf.push_str(&line),
+        Err(e) => {
+            let line: String = e.into_bytes().iter().map(|b| *b as char).collect();
+            warnings.push(format!(
+                "Decoded non UTF-8 line as Latin-1: {}",
+                line.trim_end()
+            ));
+            buf.push_str(&line);
+        }
+    }
+    Ok(len)
+}
+
+// Convert error while reading the given phase of the response, distinguishing timeouts
+fn read_error(url: &str, phase: Phase, e: std::io::Error) -> Error {
+    match e.kind() {
+        ErrorKind::WouldBlock | ErrorKind::TimedOut => Error::timeout(url, phase),
+        _ => Error::NoRead(InvalidResponseError {
+            url: url.to_string(),
+            response: e.to_string(),
+        }),
+    }
+}
+
+// Pass body through user defined transformers, in order, as the last step of the body pipeline
+fn transform(headers: &HttpHeaders, body: Vec<u8>, transformers: &[BodyTransformer]) -> Vec<u8> {
+    transformers
+        .iter()
+        .fold(body, |body, transformer| transformer(headers, body))
+}
@@ -0,0 +1,94 @@
+use super::HttpResponse;
+use crate::error::{Error, ProxyFailure};
+use crate::timings::Phase;
+use rand::{thread_rng, Rng};
+use std::time::Duration;
+
+/// Decides whether or not the outcome of an attempt should be retried
+pub type RetryPredicate = fn(&Result<HttpResponse, Error>) -> bool;
+
+/// When and how often failed requests are retried.  The delay before each retry doubles,
+/// starting at base_delay and capped at max_delay.  With jitter, each delay is randomized
+/// between half and all of its value so that clients retrying together spread out.
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    pub attempts: usize,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+    pub jitter: bool,
+    pub retry_on: RetryPredicate,
+}
+
+impl Default for RetryPolicy {
+    /// Three attempts, starting at 200ms with jitter, retrying connect failures and 502 / 503 / 504
+    fn default() -> RetryPolicy {
+        Self {
+            attempts: 3,
+            base_delay: Duration::from_millis(200),
+            max_delay: Duration::from_secs(10),
+            jitter: true,
+            retry_on: Self::is_retryable,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Default policy with the given total number of attempts, including the first
+    pub fn new(attempts: usize) -> Self {
+        Self {
+            attempts,
+            ..Default::default()
+        }
+    }
+
+    /// Set delay before the first retry, and the maximum any delay grows to
+    pub fn backoff(mut self, base_delay: Duration, max_delay: Duration) -> Self {
+        self.base_delay = base_delay;
+        self.max_delay = max_delay;
+        self
+    }
+
+    /// Enable or disable jitter
+    pub fn jitter(mut self, jitter: bool) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Set predicate deciding which outcomes are retried
+    pub fn retry_on(mut self, retry_on: RetryPredicate) -> Self {
+        self.retry_on = retry_on;
+        self
+    }
+
+    /// Default predicate, retries connect failures and 502 / 503 / 504 responses
+    pub fn is_retryable(result: &Result<HttpResponse, Error>) -> bool {
+        match result {
+            Ok(res) => [502, 503, 504].contains(&res.status_code()),
+            Err(Error::NoConnect(_)) => true,
+            Err(Error::Timeout(err)) => err.phase == Phase::Connect,
+            Err(Error::Proxy(err)) => err.failure == ProxyFailure::Connect,
+            Err(_) => false,
+        }
+    }
+
+    /// Get delay before retrying, None once all attempts are used.  Attempt starts at 1 for the
+    /// first request.
+    pub fn delay(&self, attempt: usize, result: &Result<HttpResponse, Error>) -> Option<Duration> {
+        if attempt >= self.attempts || !(self.retry_on)(result) {
+            return None;
+        }
+
+        let exp = (attempt - 1).min(31) as u32;
+        let delay = self
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(exp))
+            .min(self.max_delay);
+        if !self.jitter {
+            return Some(delay);
+        }
+        let millis = delay.as_millis() as u64;
+        Some(Duration::from_millis(
+            thread_rng().gen_range(millis / 2..=millis),
+        ))
+    }
+}
@@ -0,0 +1,248 @@
+use super::{HttpBody, HttpRequest, HttpResponse, SigV4};
+use crate::error::Error;
+use crate::sigv4::uri_encode;
+
+/// Endpoint and credentials of an S3-compatible object store, such as AWS S3 or MinIO.
+/// Buckets are addressed path-style (https://endpoint/bucket/key) unless virtual_hosted() is
+/// set (https://bucket.endpoint/key).
+#[derive(Clone, Debug)]
+pub struct S3 {
+    pub endpoint: String,
+    pub signer: SigV4,
+    pub virtual_hosted: bool,
+}
+
+/// Object within a bucket, as returned by list_objects()
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct S3Object {
+    pub key: String,
+    pub size: u64,
+    pub etag: String,
+    pub last_modified: String,
+}
+
+/// Single page of a ListObjectsV2 response
+#[derive(Clone, Debug, Default)]
+pub struct ListObjects {
+    pub objects: Vec<S3Object>,
+    pub prefixes: Vec<String>,
+    pub is_truncated: bool,
+    pub continuation_token: Option<String>,
+}
+
+impl S3 {
+    /// Create for endpoint such as http://localhost:9000, MinIO accepts any region
+    pub fn new(endpoint: &str, access_key: &str, secret_key: &str, region: &str) -> Self {
+        Self {
+            endpoint: endpoint.trim_end_matches('/').to_string(),
+            signer: SigV4::s3(access_key, secret_key, region),
+            virtual_hosted: false,
+        }
+    }
+
+    /// Address buckets via virtual hosted-style URLs, as preferred by AWS
+    pub fn virtual_hosted(mut self) -> Self {
+        self.virtual_hosted = true;
+        self
+    }
+
+    /// Get URL of an object, or of the bucket itself if key is blank
+    pub fn url(&self, bucket: &str, key: &str) -> String {
+        let key = uri_encode(key.trim_start_matches('/'), false);
+        if !self.virtual_hosted {
+            return format!("{}/{}/{}", self.endpoint, bucket, key);
+        }
+
+        match self.endpoint.split_once("://") {
+            Some((scheme, host)) => format!("{}://{}.{}/{}", scheme, bucket, host, key),
+            None => format!("{}.{}/{}", bucket, self.endpoint, key),
+        }
+    }
+
+    /// Signed GetObject request
+    pub fn get_object_request(&self, bucket: &str, key: &str) -> Result<HttpRequest, Error> {
+        let mut req = HttpRequest::new("GET", &self.url(bucket, key), &vec![], &HttpBody::empty());
+        self.signer.sign(&mut req)?;
+        Ok(req)
+    }
+
+    /// Signed PutObject request.  Bodies streamed from a file keep their Content-Length, and
+    /// the Content-Type defaults to application/octet-stream.
+    pub fn put_object_request(
+        &self,
+        bucket: &str,
+        key: &str,
+        body: &HttpBody,
+    ) -> Result<HttpRequest, Error> {
+        let mut req = HttpRequest::new("PUT", &self.url(bucket, key), &vec![], body);
+        if body.file().is_none() && body.content_type().is_none() {
+            req.headers.set("Content-Type", "application/octet-stream");
+        }
+        if body.content_length() == 0 && !body.is_chunked() {
+            req.headers.set("Content-Length", "0");
+        }
+        self.signer.sign(&mut req)?;
+        Ok(req)
+    }
+
+    /// Signed ListObjectsV2 request for keys starting with prefix, continuing from a previous
+    /// truncated page if a token is given
+    pub fn list_objects_request(
+        &self,
+        bucket: &str,
+        prefix: &str,
+        continuation_token: Option<&str>,
+    ) -> Result<HttpRequest, Error> {
+        let mut url = format!("{}?list-type=2", self.url(bucket, ""));
+        if let Some(token) = continuation_token {
+            url.push_str(&format!("&continuation-token={}", uri_encode(token, true)));
+        }
+        if !prefix.is_empty() {
+            url.push_str(&format!("&prefix={}", uri_encode(prefix, true)));
+        }
+
+        let mut req = HttpRequest::new("GET", &url, &vec![], &HttpBody::empty());
+        self.signer.sign(&mut req)?;
+        Ok(req)
+    }
+
+    /// Check response is successful, returning the S3 error code and message if not
+    pub fn check(res: HttpResponse) -> Result<HttpResponse, Error> {
+        if (200..300).contains(&res.status_code()) {
+            return Ok(res);
+        }
+
+        let url = res
+            .effective_request()
+            .map(|req| req.url)
+            .unwrap_or_default();
+        let body = res.body();
+        let code = xml_values(&body, "Code").pop().unwrap_or_default();
+        if code.is_empty() {
+            return Err(Error::UnexpectedStatus(res.status_code(), url));
+        }
+        let message = xml_values(&body, "Message").pop().unwrap_or_default();
+        Err(Error::Custom(format!(
+            "S3 request to {} failed with {}: {}",
+            url, code, message
+        )))
+    }
+}
+
+impl ListObjects {
+    /// Parse XML body of a ListObjectsV2 response
+    pub fn from_response(res: &HttpResponse) -> Result<Self, Error> {
+        let body = res.body();
+        if !body.contains("<ListBucketResult") {
+            let url = res
+                .effective_request()
+                .map(|req| req.url)
+                .unwrap_or_default();
+            return Err(Error::Custom(format!(
+                "Invalid ListObjectsV2 response from {}",
+                url
+            )));
+        }
+
+        let objects = xml_elements(&body, "Contents")
+            .iter()
+            .map(|contents| S3Object {
+                key: xml_values(contents, "Key").pop().unwrap_or_default(),
+                size: xml_values(contents, "Size")
+                    .pop()
+                    .and_then(|size| size.parse::<u64>().ok())
+                    .unwrap_or(0),
+                etag: xml_values(contents, "ETag").pop().unwrap_or_default(),
+                last_modified: xml_values(contents, "LastModified")
+                    .pop()
+                    .unwrap_or_default(),
+            })
+            .collect();
+
+        let prefixes = xml_elements(&body, "CommonPrefixes")
+            .iter()
+            .filter_map(|common| xml_values(common, "Prefix").pop())
+            .collect();
+
+        Ok(Self {
+            objects,
+            prefixes,
+            is_truncated: xml_values(&body, "IsTruncated").pop().as_deref() == Some("true"),
+            continuation_token: xml_values(&body, "NextContinuationToken").pop(),
+        })
+    }
+}
+
+/// Get unescaped contents of all elements with the given tag name.  Only handles the simple,
+/// attribute-less XML returned by S3.
+pub fn xml_values(xml: &str, tag: &str) -> Vec<String> {
+    xml_elements(xml, tag)
+        .iter()
+        .map(|value| xml_unescape(value))
+        .collect()
+}
+
+// Get raw contents of all elements with the given tag name
+fn xml_elements<'a>(xml: &'a str, tag: &str) -> Vec<&'a str> {
+    let open = format!("<{}>", tag);
+    let close = format!("</{}>", tag);
+
+    let mut elements = Vec::new();
+    let mut rest = xml;
+    while let Some(start) = rest.find(&open) {
+        rest = &rest[start + open.len()..];
+        let end = match rest.find(&close) {
+            Some(r) => r,
+            None => break,
+        };
+        elements.push(&rest[..end]);
+        rest = &rest[end + close.len()..];
+    }
+    elements
+}
+
+// Unescape XML entities
+fn xml_unescape(value: &str) -> String {
+    if !value.contains('&') {
+        return value.to_string();
+    }
+
+    let mut unescaped = String::new();
+    let mut rest = value;
+    while let Some(pos) = rest.find('&') {
+        unescaped.push_str(&rest[..pos]);
+        rest = &rest[pos..];
+        let end = match rest.find(';') {
+            Some(r) => r,
+            None => break,
+        };
+
+        let entity = &rest[1..end];
+        let decoded = match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ if entity.starts_with("#x") => u32::from_str_radix(&entity[2..], 16)
+                .ok()
+                .and_then(char::from_u32),
+            _ if entity.starts_with('#') => {
+                entity[1..].parse::<u32>().ok().and_then(char::from_u32)
+            }
+            _ => None,
+        };
+        match decoded {
+            Some(c) => {
+                unescaped.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                unescaped.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    unescaped.push_str(rest);
+    unescaped
+}
@@ -0,0 +1,222 @@
+use super::{HttpClientConfig, HttpStream, TlsInfo};
+use crate::clock::Clock;
+use crate::connector::Connector;
+use crate::error::Error;
+use crate::timings::{Phase, Timings};
+use std::io::{self, ErrorKind, Read, Write};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use url::Url;
+
+/// Wraps another connector to simulate a bad network within tests, eg. to check how an
+/// application copes with timeouts and retries.  Adds latency to connecting and to each
+/// response, caps throughput, and resets connections after a number of bytes were received.
+/// Delays are waited out via the clock of the client, so a MockClock skips them.  Clones share
+/// the same connection count.
+#[derive(Debug, Clone)]
+pub struct SimulatedNetwork {
+    inner: Arc<dyn Connector>,
+    latency: Duration,
+    bandwidth: Option<u64>,
+    reset_after: Option<u64>,
+    reset_connections: Option<usize>,
+    connections: Arc<AtomicUsize>,
+}
+
+impl SimulatedNetwork {
+    /// Wrap the given connector, eg. DefaultConnector, without any impairments yet
+    pub fn new<C: Connector + 'static>(inner: C) -> Self {
+        Self {
+            inner: Arc::new(inner),
+            latency: Duration::ZERO,
+            bandwidth: None,
+            reset_after: None,
+            reset_connections: None,
+            connections: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    /// Round trip time, waited before connecting and before the first byte of each response
+    pub fn latency(mut self, latency: Duration) -> Self {
+        self.latency = latency;
+        self
+    }
+
+    /// Cap throughput of each connection in either direction to bytes per second
+    pub fn bandwidth(mut self, bytes_per_second: u64) -> Self {
+        self.bandwidth = Some(bytes_per_second.max(1));
+        self
+    }
+
+    /// Reset connections once the given number of bytes were received over them, failing the
+    /// read with ErrorKind::ConnectionReset
+    pub fn reset_after(mut self, bytes: u64) -> Self {
+        self.reset_after = Some(bytes);
+        self
+    }
+
+    /// Only reset the first count connections opened, so retries eventually succeed.  All
+    /// connections are reset by default.
+    pub fn reset_connections(mut self, count: usize) -> Self {
+        self.reset_connections = Some(count);
+        self
+    }
+
+    /// Number of connections opened so far
+    pub fn connections(&self) -> usize {
+        self.connections.load(Ordering::SeqCst)
+    }
+}
+
+impl Connector for SimulatedNetwork {
+    fn connect(
+        &self,
+        config: &HttpClientConfig,
+        uri: &Url,
+        port: u16,
+        sni: Option<&str>,
+        timeout: Duration,
+        timings: &mut Timings,
+    ) -> Result<Box<dyn HttpStream>, Error> {
+        // Handshake takes a round trip, give up once the connect timeout passes
+        if !timeout.is_zero() && self.latency > timeout {
+            std::thread::sleep(config.clock.wait(timeout));
+            return Err(Error::timeout(uri.as_str(), Phase::Connect));
+        }
+        std::thread::sleep(config.clock.wait(self.latency));
+
+        let stream = self
+            .inner
+            .connect(config, uri, port, sni, timeout, timings)?;
+        timings.connect = timings.connect.map(|connect| connect + self.latency);
+        let index = self.connections.fetch_add(1, Ordering::SeqCst);
+        let resets = self.reset_connections.map_or(true, |count| index < count);
+
+        Ok(Box::new(SimulatedStream {
+            inner: stream,
+            clock: Arc::clone(&config.clock),
+            latency: self.latency,
+            bandwidth: self.bandwidth,
+            reset_after: self.reset_after.filter(|_| resets),
+            read_timeout: Mutex::new(None),
+            received: 0,
+            awaiting: false,
+        }))
+    }
+}
+
+// Stream impaired as per the SimulatedNetwork it was opened by
+struct SimulatedStream {
+    inner: Box<dyn HttpStream>,
+    clock: Arc<dyn Clock>,
+    latency: Duration,
+    bandwidth: Option<u64>,
+    reset_after: Option<u64>,
+    read_timeout: Mutex<Option<Duration>>,
+    received: u64,
+    awaiting: bool,
+}
+
+impl SimulatedStream {
+    // Wait the time it takes to transfer bytes at the capped throughput
+    fn throttle(&self, bytes: usize) {
+        if let Some(bandwidth) = self.bandwidth {
+            let delay = Duration::from_secs_f64(bytes as f64 / bandwidth as f64);
+            std::thread::sleep(self.clock.wait(delay));
+        }
+    }
+
+    // Largest number of bytes to transfer at once, so throughput stays even
+    fn chunk(&self, len: usize) -> usize {
+        match self.bandwidth {
+            Some(bandwidth) => len.min((bandwidth / 10).max(1) as usize),
+            None => len,
+        }
+    }
+}
+
+impl Read for SimulatedStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        // First byte of the response arrives a round trip after the request was sent
+        if self.awaiting {
+            let timeout = *self.read_timeout.lock().unwrap();
+            if let Some(timeout) = timeout.filter(|timeout| self.latency > *timeout) {
+                std::thread::sleep(self.clock.wait(timeout));
+                return Err(ErrorKind::TimedOut.into());
+            }
+            std::thread::sleep(self.clock.wait(self.latency));
+            self.awaiting = false;
+        }
+
+        // Reset mid-stream
+        let mut len = self.chunk(buf.len());
+        if let Some(reset_after) = self.reset_after {
+            if self.received >= reset_after {
+                return Err(io::Error::new(
+                    ErrorKind::ConnectionReset,
+                    "simulated connection reset",
+                ));
+            }
+            len = len.min((reset_after - self.received) as usize);
+        }
+
+        let n = self.inner.read(&mut buf[..len])?;
+        self.received += n as u64;
+        self.throttle(n);
+        Ok(n)
+    }
+}
+
+impl Write for SimulatedStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = self.chunk(buf.len());
+        let n = self.inner.write(&buf[..len])?;
+        self.throttle(n);
+        self.awaiting = true;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl HttpStream for SimulatedStream {
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        *self.read_timeout.lock().unwrap() = timeout;
+        self.inner.set_read_timeout(timeout)
+    }
+
+    fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        self.inner.set_write_timeout(timeout)
+    }
+
+    fn alpn_protocol(&self) -> Option<String> {
+        self.inner.alpn_protocol()
+    }
+
+    fn tls_info(&self) -> Option<TlsInfo> {
+        self.inner.tls_info()
+    }
+
+    fn is_alive(&self) -> bool {
+        self.inner.is_alive()
+    }
+
+    fn shutdown(&mut self) -> io::Result<()> {
+        self.inner.shutdown()
+    }
+
+    fn send_early_data(&mut self, data: &[u8]) -> io::Result<bool> {
+        self.inner.send_early_data(data)
+    }
+
+    fn is_handshaking(&self) -> bool {
+        self.inner.is_handshaking()
+    }
+
+    fn complete_handshake(&mut self) -> io::Result<()> {
+        self.inner.complete_handshake()
+    }
+}
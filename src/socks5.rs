@@ -1,7 +1,7 @@
 use super::HttpClientConfig;
-use crate::error::Error;
+use crate::error::{Error, ProxyFailure};
 use std::io::{Read, Write};
-use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
+use std::net::{SocketAddr, TcpStream};
 use url::Url;
 
 /// Connect to SOCKS5 proxy
@@ -11,69 +11,92 @@ pub fn connect(
     uri: &Url,
     port: &u16,
 ) -> Result<(), Error> {
+    let fail = |failure: ProxyFailure, message: &str| {
+        Error::proxy(
+            &format!("{}:{}", config.proxy_host, config.proxy_port),
+            &format!("{}:{}", uri.host_str().unwrap_or(""), port),
+            failure,
+            message,
+        )
+    };
+
     // Hello
-    self::hello(sock, config)?;
+    self::hello(sock, config, &fail)?;
 
     // Send request to connect
-    self::request(sock, uri, port)?;
+    self::request(sock, config, uri, port, &fail)?;
 
     Ok(())
 }
 
 /// Send hello to SOCKS5 proxy
-fn hello(sock: &mut TcpStream, config: &HttpClientConfig) -> Result<(), Error> {
-    // Send greeting
-    sock.write_all(&[0x05, 0x01, 0x00]).unwrap();
-    sock.flush().unwrap();
+fn hello(
+    sock: &mut TcpStream,
+    config: &HttpClientConfig,
+    fail: &dyn Fn(ProxyFailure, &str) -> Error,
+) -> Result<(), Error> {
+    let io_err = |e: std::io::Error| fail(ProxyFailure::Protocol, &e.to_string());
+
+    // Send greeting, offering username / password authentication if credentials are defined
+    let greeting: &[u8] = if config.proxy_user.is_empty() {
+        &[0x05, 0x01, 0x00]
+    } else {
+        &[0x05, 0x02, 0x00, 0x02]
+    };
+    sock.write_all(greeting).map_err(io_err)?;
+    sock.flush().map_err(io_err)?;
 
     // Read response
     let mut buffer = [0u8; 2];
-    sock.read(&mut buffer).unwrap();
+    sock.read_exact(&mut buffer).map_err(io_err)?;
 
     // Check response
-    if buffer[1] == 0xFF {
-        return Err(Error::Custom(
-            "SOCKS5 gave invalid response after initial greeting, no auth methods available."
-                .to_string(),
-        ));
-    } else if buffer[1] == 0x02 {
-        self::authenticate(sock, config)?;
-        return Err( Error::Custom("Authentication required, but not developed in for atlas-http.  Please raise issue on Github and bug developer, https://github.com/mdizak/rust-atlas-http".to_string()) );
+    match buffer[1] {
+        0x00 => Ok(()),
+        0x02 => self::authenticate(sock, config, fail),
+        0xFF => Err(fail(
+            ProxyFailure::Auth,
+            "no acceptable authentication method, proxy user / pass may be required",
+        )),
+        method => Err(fail(
+            ProxyFailure::Protocol,
+            &format!("unsupported authentication method {:#04x}", method),
+        )),
     }
-
-    Ok(())
 }
 
 /// Authenticate
-fn authenticate(sock: &mut TcpStream, config: &HttpClientConfig) -> Result<(), Error> {
+fn authenticate(
+    sock: &mut TcpStream,
+    config: &HttpClientConfig,
+    fail: &dyn Fn(ProxyFailure, &str) -> Error,
+) -> Result<(), Error> {
+    let io_err = |e: std::io::Error| fail(ProxyFailure::Protocol, &e.to_string());
+
     // Start request
     let mut request = vec![0x01];
 
     // Username
     request.push(config.proxy_user.len() as u8);
-    for c in config.proxy_user.chars() {
-        request.push(c as u8);
-    }
+    request.extend_from_slice(config.proxy_user.as_bytes());
 
     // Password
     request.push(config.proxy_password.len() as u8);
-    for c in config.proxy_password.chars() {
-        request.push(c as u8);
-    }
+    request.extend_from_slice(config.proxy_password.as_bytes());
 
     // Send request
-    sock.write_all(&request).unwrap();
-    sock.flush().unwrap();
+    sock.write_all(&request).map_err(io_err)?;
+    sock.flush().map_err(io_err)?;
 
     // Read response
     let mut buffer = [0u8; 2];
-    sock.read(&mut buffer).unwrap();
+    sock.read_exact(&mut buffer).map_err(io_err)?;
 
     // Check response
     if buffer[1] != 0x00 {
-        return Err(Error::Custom(
-            "SOCKS5 proxy authentication failed.  Please check proxy user / pass, and try again."
-                .to_string(),
+        return Err(fail(
+            ProxyFailure::Auth,
+            "authentication failed, please check proxy user / pass",
         ));
     }
 
@@ -81,60 +104,81 @@ fn authenticate(sock: &mut TcpStream, config: &HttpClientConfig) -> Result<(), E
 }
 
 /// Send request to connect to remote server
-fn request(sock: &mut TcpStream, uri: &Url, port: &u16) -> Result<(), Error> {
-    // Get addr
-    let hostname = format!("{}:{}", uri.host_str().unwrap(), port);
-    let mut address = hostname.to_socket_addrs().unwrap();
-    let addr = address.next().unwrap();
+fn request(
+    sock: &mut TcpStream,
+    config: &HttpClientConfig,
+    uri: &Url,
+    port: &u16,
+    fail: &dyn Fn(ProxyFailure, &str) -> Error,
+) -> Result<(), Error> {
+    let io_err = |e: std::io::Error| fail(ProxyFailure::Protocol, &e.to_string());
+
+    // Get addr, leaving resolution to the proxy if it fails locally
+    let host = uri.host_str().unwrap_or("");
+    let addr = config
+        .resolver
+        .resolve(host.trim_start_matches('[').trim_end_matches(']'), *port)
+        .ok()
+        .and_then(|addrs| addrs.first().copied());
 
     // Set request
     let mut request = vec![0x05, 0x01, 0x00];
 
     // Append IP address to request
-    if let SocketAddr::V6(h) = addr {
-        request.push(0x04);
-        for byte in h.ip().octets() {
-            request.push(byte);
+    match addr {
+        Some(SocketAddr::V6(h)) => {
+            request.push(0x04);
+            request.extend_from_slice(&h.ip().octets());
         }
-    } else if let SocketAddr::V4(h) = addr {
-        request.push(0x01);
-        for byte in h.ip().octets() {
-            request.push(byte);
+        Some(SocketAddr::V4(h)) => {
+            request.push(0x01);
+            request.extend_from_slice(&h.ip().octets());
         }
-    } else {
-        let host = uri.host_str().unwrap();
-        request.push(0x03);
-        request.push(host.len() as u8);
-
-        for c in host.chars() {
-            request.push(c as u8);
+        None => {
+            request.push(0x03);
+            request.push(host.len() as u8);
+            request.extend_from_slice(host.as_bytes());
         }
     }
 
     // Add port
-    request.push((addr.port() >> 8) as u8);
-    request.push((addr.port() & 0x00FF) as u8);
+    request.extend_from_slice(&port.to_be_bytes());
 
     // Send request
-    sock.write_all(&request).unwrap();
-    sock.flush().unwrap();
+    sock.write_all(&request).map_err(io_err)?;
+    sock.flush().map_err(io_err)?;
 
     // Read response
-    let mut buffer = [0u8; 10];
-    sock.read(&mut buffer).unwrap();
-
-    // Ipv6, get rid of extra bytes
-    if buffer[3] == 0x04 {
-        let mut tmp_buffer = [0u8; 12];
-        sock.read(&mut tmp_buffer).unwrap();
-    }
+    let mut buffer = [0u8; 4];
+    sock.read_exact(&mut buffer).map_err(io_err)?;
 
     // Check response
     if buffer[1] != 0x00 {
-        return Err(Error::Custom(
-            "Invalid response from SOCKS5 proxy after 'connect' command.".to_string(),
+        let failure = ProxyFailure::from_socks5_reply(buffer[1]);
+        return Err(fail(
+            failure,
+            &format!("{} (SOCKS5 reply {:#04x})", failure, buffer[1]),
         ));
     }
 
+    // Get rid of the bound address
+    let remaining = match buffer[3] {
+        0x01 => 6,
+        0x04 => 18,
+        0x03 => {
+            let mut len = [0u8; 1];
+            sock.read_exact(&mut len).map_err(io_err)?;
+            len[0] as usize + 2
+        }
+        atyp => {
+            return Err(fail(
+                ProxyFailure::Protocol,
+                &format!("invalid address type {:#04x} in reply", atyp),
+            ))
+        }
+    };
+    let mut tmp_buffer = vec![0u8; remaining];
+    sock.read_exact(&mut tmp_buffer).map_err(io_err)?;
+
     Ok(())
 }
@@ -0,0 +1,59 @@
+use super::HttpResponse;
+use std::fmt;
+use std::io::{BufRead, BufReader, Read};
+
+/// Response whose body is read incrementally as it arrives, such as server-sent events or other
+/// long-lived chunked streams.  Reads return as soon as any data is available and the body is
+/// never buffered in full, so body transformers and hashing are not applied.  Chunked framing
+/// and content encoding are removed.
+pub struct ResponseStream {
+    response: HttpResponse,
+    reader: BufReader<Box<dyn Read + Send>>,
+    permit: Option<Box<dyn Send + Sync>>,
+}
+
+impl fmt::Debug for ResponseStream {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ResponseStream")
+            .field("response", &self.response)
+            .finish()
+    }
+}
+
+impl ResponseStream {
+    /// Create stream over the body of a response
+    pub fn new(response: HttpResponse, reader: Box<dyn Read + Send>) -> Self {
+        Self {
+            response,
+            reader: BufReader::new(reader),
+            permit: None,
+        }
+    }
+
+    /// Hold the in-flight slot of the request until the stream is dropped
+    pub fn with_permit<P: Send + Sync + 'static>(mut self, permit: P) -> Self {
+        self.permit = Some(Box::new(permit));
+        self
+    }
+
+    /// Get response, with status and headers but without a body
+    pub fn response(&self) -> &HttpResponse {
+        &self.response
+    }
+}
+
+impl Read for ResponseStream {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.reader.read(buf)
+    }
+}
+
+impl BufRead for ResponseStream {
+    fn fill_buf(&mut self) -> std::io::Result<&[u8]> {
+        self.reader.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.reader.consume(amt)
+    }
+}
@@ -0,0 +1,59 @@
+use std::fmt;
+use std::time::Duration;
+
+/// Phase of a request, as reported by Error::Timeout
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Phase {
+    Dns,
+    Connect,
+    Tls,
+    Write,
+    Ttfb,
+    Header,
+    Body,
+}
+
+/// Durations of the phases of a request.  DNS, connect and TLS are the time taken by each step
+/// and are None on reused connections, while ttfb and total are measured from the start of the
+/// request.  Phases not yet reached when a request fails are left as None.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Timings {
+    pub dns: Option<Duration>,
+    pub connect: Option<Duration>,
+    pub tls: Option<Duration>,
+    pub ttfb: Option<Duration>,
+    pub total: Duration,
+}
+
+impl fmt::Display for Phase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Phase::Dns => "DNS lookup",
+            Phase::Connect => "connect",
+            Phase::Tls => "TLS handshake",
+            Phase::Write => "request write",
+            Phase::Ttfb => "wait for first byte",
+            Phase::Header => "response header",
+            Phase::Body => "response body",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl fmt::Display for Timings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let phase = |duration: Option<Duration>| match duration {
+            Some(duration) => format!("{:?}", duration),
+            None => "-".to_string(),
+        };
+        write!(
+            f,
+            "dns: {}, connect: {}, tls: {}, ttfb: {}, total: {:?}",
+            phase(self.dns),
+            phase(self.connect),
+            phase(self.tls),
+            phase(self.ttfb),
+            self.total
+        )
+    }
+}
@@ -0,0 +1,142 @@
+use super::{HttpRequest, HttpResponse, HttpStream};
+use crate::error::Error;
+use std::fmt;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::time::Duration;
+
+/// Connection switched to another protocol after a 101 Switching Protocols response, obtained
+/// via upgrade() of either client.  Reads and writes go straight to the stream, over TLS and
+/// through any proxy the request went through.  Bytes the server sent right after the response
+/// header are read first.
+pub struct Upgraded {
+    response: HttpResponse,
+    reader: BufReader<Box<dyn HttpStream>>,
+    permit: Option<Box<dyn Send + Sync>>,
+}
+
+impl fmt::Debug for Upgraded {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Upgraded")
+            .field("response", &self.response)
+            .finish()
+    }
+}
+
+impl Upgraded {
+    /// Add headers asking to switch to protocol, eg. "websocket", leaving any Connection header
+    /// options of the request in place
+    pub fn request(req: &HttpRequest, protocol: &str) -> HttpRequest {
+        let mut options = vec!["Upgrade".to_string()];
+        if let Some(connection) = req.headers.get_lower("connection") {
+            options.extend(
+                connection
+                    .split(',')
+                    .map(|option| option.trim().to_string())
+                    .filter(|option| {
+                        !option.is_empty()
+                            && !["upgrade", "close", "keep-alive"]
+                                .contains(&option.to_lowercase().as_str())
+                    }),
+            );
+        }
+        req.with_header("Connection", &options.join(", "))
+            .with_header("Upgrade", protocol)
+    }
+
+    /// Take over stream the response was read from, failing unless the server switched to
+    /// protocol.  Read timeouts are cleared, as upgraded connections are often idle for long.
+    pub fn new(
+        url: &str,
+        protocol: &str,
+        response: HttpResponse,
+        reader: BufReader<Box<dyn HttpStream>>,
+    ) -> Result<Self, Error> {
+        if response.status_code() != 101 {
+            return Err(Error::UnexpectedStatus(
+                response.status_code(),
+                url.to_string(),
+            ));
+        }
+
+        // Server names the protocol switched to, RFC 9110 section 7.8
+        let switched = response.headers().get_lower("upgrade").unwrap_or_default();
+        let name = |proto: &str| proto.split('/').next().unwrap_or("").trim().to_lowercase();
+        if name(&switched) != name(protocol) {
+            return Err(Error::Custom(format!(
+                "Server at {} switched to protocol '{}' instead of '{}'",
+                url, switched, protocol
+            )));
+        }
+
+        reader.get_ref().set_read_timeout(None).map_err(Error::Io)?;
+        Ok(Self {
+            response,
+            reader,
+            permit: None,
+        })
+    }
+
+    /// Hold the in-flight slot of the request until the connection is dropped
+    pub fn with_permit<P: Send + Sync + 'static>(mut self, permit: P) -> Self {
+        self.permit = Some(Box::new(permit));
+        self
+    }
+
+    /// Get the 101 response, eg. to check a Sec-WebSocket-Accept header
+    pub fn response(&self) -> &HttpResponse {
+        &self.response
+    }
+
+    /// Protocol switched to, as named by the Upgrade header of the response
+    pub fn protocol(&self) -> String {
+        self.response
+            .headers()
+            .get_lower("upgrade")
+            .unwrap_or_default()
+    }
+
+    /// Set timeout of reads, None blocks indefinitely
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> Result<(), Error> {
+        self.reader
+            .get_ref()
+            .set_read_timeout(timeout)
+            .map_err(Error::Io)
+    }
+
+    /// Close the connection, eg. sending a TLS close_notify
+    pub fn shutdown(self) -> Result<(), Error> {
+        self.reader.into_inner().shutdown().map_err(Error::Io)
+    }
+
+    /// Split into the response and the stream, with bytes already received buffered within the
+    /// reader
+    pub fn into_parts(self) -> (HttpResponse, BufReader<Box<dyn HttpStream>>) {
+        (self.response, self.reader)
+    }
+}
+
+impl Read for Upgraded {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.reader.read(buf)
+    }
+}
+
+impl BufRead for Upgraded {
+    fn fill_buf(&mut self) -> std::io::Result<&[u8]> {
+        self.reader.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.reader.consume(amt)
+    }
+}
+
+impl Write for Upgraded {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.reader.get_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.reader.get_mut().flush()
+    }
+}
@@ -0,0 +1,125 @@
+use super::{HttpBody, HttpRequest, HttpResponse};
+use crate::error::Error;
+use crate::redirect_cache;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::time::Duration;
+
+/// Default size of each chunk of a resumable upload, a multiple of the 256 KiB granularity
+/// Google Cloud Storage and similar services require
+pub const DEFAULT_CHUNK_SIZE: usize = 8 * 1024 * 1024;
+
+/// State of a resumable upload as reported by the server
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UploadStatus {
+    /// Upload finished, the response carries the uploaded object
+    Complete,
+    /// More data is expected, starting at the given offset
+    Incomplete(u64),
+}
+
+/// Request initiating a resumable upload session of total bytes, whose URL is returned within
+/// the Location header of the response
+pub fn initiate_request(url: &str, total: u64, content_type: &str) -> HttpRequest {
+    let headers = vec![
+        format!("X-Upload-Content-Length: {}", total),
+        format!("X-Upload-Content-Type: {}", content_type),
+    ];
+    HttpRequest::new(
+        "POST",
+        url,
+        &headers.iter().map(|h| h.as_str()).collect(),
+        &HttpBody::from_raw_str(""),
+    )
+}
+
+/// Request uploading chunk starting at offset to the session URL
+pub fn chunk_request(session_url: &str, chunk: &[u8], offset: u64, total: u64) -> HttpRequest {
+    let range = match chunk.is_empty() {
+        true => format!("Content-Range: bytes */{}", total),
+        false => format!(
+            "Content-Range: bytes {}-{}/{}",
+            offset,
+            offset + chunk.len() as u64 - 1,
+            total
+        ),
+    };
+    HttpRequest::new(
+        "PUT",
+        session_url,
+        &vec![range.as_str(), "Content-Type: application/octet-stream"],
+        &HttpBody::from_raw(chunk),
+    )
+}
+
+/// Request asking the server how much of the upload it has received, after a failed chunk
+pub fn status_request(session_url: &str, total: u64) -> HttpRequest {
+    let range = format!("Content-Range: bytes */{}", total);
+    HttpRequest::new(
+        "PUT",
+        session_url,
+        &vec![range.as_str()],
+        &HttpBody::from_raw_str(""),
+    )
+}
+
+/// Get URL of the upload session from the response to the initiating request
+pub fn session_url(url: &str, res: &HttpResponse) -> Result<String, Error> {
+    if !(200..300).contains(&res.status_code()) {
+        return Err(Error::UnexpectedStatus(res.status_code(), url.to_string()));
+    }
+    match res.headers().get_lower("location") {
+        Some(location) => Ok(redirect_cache::resolve(url, &location)),
+        None => Err(Error::Custom(format!(
+            "No Location header with the upload session URL from {}",
+            url
+        ))),
+    }
+}
+
+/// Get status of the upload from the response to a chunk or status request.  308 Resume
+/// Incomplete responses carry the bytes received so far within the Range header, eg. bytes=0-1023.
+pub fn status(url: &str, res: &HttpResponse) -> Result<UploadStatus, Error> {
+    match res.status_code() {
+        200..=299 => Ok(UploadStatus::Complete),
+        308 => {
+            let received = res
+                .headers()
+                .get_lower("range")
+                .and_then(|range| {
+                    let (_, end) = range.trim().strip_prefix("bytes=")?.split_once('-')?;
+                    end.trim().parse::<u64>().ok()
+                })
+                .map(|end| end + 1)
+                .unwrap_or(0);
+            Ok(UploadStatus::Incomplete(received))
+        }
+        status => Err(Error::UnexpectedStatus(status, url.to_string())),
+    }
+}
+
+/// Whether or not a chunk failing with the error is worth retrying.  Client errors other than
+/// 408 and 429 mean the session is gone or the request is invalid.
+pub fn is_retryable(err: &Error) -> bool {
+    match err {
+        Error::UnexpectedStatus(status, _) => *status >= 500 || *status == 408 || *status == 429,
+        Error::FileNotExists(_) | Error::FileNotCreated(_) => false,
+        _ => true,
+    }
+}
+
+/// Read chunk of up to size bytes starting at offset
+pub fn read_chunk(fh: &mut File, offset: u64, size: usize) -> Result<Vec<u8>, Error> {
+    let mut chunk = Vec::with_capacity(size);
+    fh.seek(SeekFrom::Start(offset)).map_err(Error::Io)?;
+    fh.by_ref()
+        .take(size as u64)
+        .read_to_end(&mut chunk)
+        .map_err(Error::Io)?;
+    Ok(chunk)
+}
+
+/// Delay before retrying a failed chunk, doubling from 250ms up to 16s
+pub fn retry_delay(failures: usize) -> Duration {
+    Duration::from_millis(250 << failures.saturating_sub(1).min(6))
+}
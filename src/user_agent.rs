@@ -1,6 +1,12 @@
 use rand::seq::SliceRandom;
+use rand::Rng;
 
 pub fn random() -> String {
+    random_with(&mut rand::thread_rng())
+}
+
+/// Pick random user agent using the given RNG
+pub fn random_with<R: Rng>(rng: &mut R) -> String {
     // Define random agents
     let user_agents = vec![ 
         "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/91.0.4472.124 Safari/537.36",
@@ -26,8 +32,7 @@ pub fn random() -> String {
     ];
 
     // Get random user agent
-    let mut rng = rand::thread_rng();
-    let ua = user_agents.choose(&mut rng).unwrap();
+    let ua = user_agents.choose(rng).unwrap();
 
     ua.to_string()
 }
@@ -0,0 +1,104 @@
+use super::HttpResponse;
+use crate::error::{Error, FileNotCreatedError};
+use std::fs;
+use std::path::Path;
+
+/// Validators of a downloaded file, recorded within a sidecar file next to it so later
+/// downloads can check whether the remote file has changed.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Validators {
+    pub etag: String,
+    pub last_modified: String,
+    pub content_length: String,
+}
+
+impl Validators {
+    /// Get validators from the headers of a response
+    pub fn from_response(res: &HttpResponse) -> Self {
+        let headers = res.headers();
+        Self {
+            etag: headers.get_lower("etag").unwrap_or_default(),
+            last_modified: headers.get_lower("last-modified").unwrap_or_default(),
+            content_length: headers.get_lower("content-length").unwrap_or_default(),
+        }
+    }
+
+    /// Path of the sidecar file of a downloaded file
+    pub fn sidecar_path(dest_file: &str) -> String {
+        format!("{}.meta", dest_file)
+    }
+
+    /// Load validators recorded for a downloaded file, None if either the file or its sidecar
+    /// does not exist
+    pub fn load(dest_file: &str) -> Option<Self> {
+        if !Path::new(dest_file).exists() {
+            return None;
+        }
+        let contents = fs::read_to_string(Self::sidecar_path(dest_file)).ok()?;
+
+        let mut validators = Self::default();
+        for line in contents.lines() {
+            let (key, value) = match line.split_once(':') {
+                Some(r) => r,
+                None => continue,
+            };
+            match key.trim().to_lowercase().as_str() {
+                "etag" => validators.etag = value.trim().to_string(),
+                "last-modified" => validators.last_modified = value.trim().to_string(),
+                "content-length" => validators.content_length = value.trim().to_string(),
+                _ => {}
+            }
+        }
+        Some(validators)
+    }
+
+    /// Save validators to the sidecar file of a downloaded file
+    pub fn save(&self, dest_file: &str) -> Result<(), Error> {
+        let sidecar = Self::sidecar_path(dest_file);
+        let contents = format!(
+            "ETag: {}\nLast-Modified: {}\nContent-Length: {}\n",
+            self.etag, self.last_modified, self.content_length
+        );
+        if let Err(e) = fs::write(&sidecar, contents) {
+            return Err(Error::FileNotCreated(FileNotCreatedError {
+                filename: sidecar,
+                error: e.to_string(),
+            }));
+        }
+        Ok(())
+    }
+
+    /// Whether or not an ETag or Last-Modified date was recorded, allowing a conditional GET
+    pub fn is_conditional(&self) -> bool {
+        !self.etag.is_empty() || !self.last_modified.is_empty()
+    }
+
+    /// Conditional request headers to only download the file if it has changed
+    pub fn conditional_headers(&self) -> Vec<String> {
+        let mut headers = Vec::new();
+        if !self.etag.is_empty() {
+            headers.push(format!("If-None-Match: {}", self.etag));
+        }
+        if !self.last_modified.is_empty() {
+            headers.push(format!("If-Modified-Since: {}", self.last_modified));
+        }
+        headers
+    }
+
+    /// Whether or not the response to a HEAD request describes the same file.  Only validators
+    /// present on both sides are compared, and at least one must be.
+    pub fn matches(&self, res: &HttpResponse) -> bool {
+        let remote = Self::from_response(res);
+        let pairs = [
+            (&self.etag, &remote.etag),
+            (&self.last_modified, &remote.last_modified),
+            (&self.content_length, &remote.content_length),
+        ];
+        let compared: Vec<bool> = pairs
+            .iter()
+            .filter(|(local, remote)| !local.is_empty() && !remote.is_empty())
+            .map(|(local, remote)| local == remote)
+            .collect();
+        !compared.is_empty() && compared.iter().all(|equal| *equal)
+    }
+}
@@ -0,0 +1,370 @@
+use super::{HttpClientConfig, HttpStream, TlsInfo};
+use crate::connector::{Connector, DefaultConnector};
+use crate::error::Error;
+use crate::timings::Timings;
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::{self, ErrorKind, Read, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use url::Url;
+
+/// Whether a Vcr records exchanges with real servers or replays those of its cassette
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum VcrMode {
+    /// Send requests to the servers, saving each exchange to the cassette
+    Record,
+    /// Answer requests from the cassette without connecting, failing those not recorded
+    Replay,
+    /// Replay if the cassette file exists, otherwise record it
+    Auto,
+}
+
+/// Records exchanges with real servers to a cassette file, and replays them on later runs for
+/// deterministic integration tests.  Requests are matched on method, URL and body, and repeated
+/// requests get their responses in the order recorded.  Set via HttpClientBuilder::connector(),
+/// and use HttpClientBuilder::seed() so multipart boundaries are the same on every run.
+#[derive(Debug, Clone)]
+pub struct Vcr {
+    path: String,
+    mode: VcrMode,
+    inner: Arc<dyn Connector>,
+    cassette: Arc<Mutex<Cassette>>,
+}
+
+// Contents of a cassette file
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Cassette {
+    interactions: Vec<Interaction>,
+    #[serde(skip)]
+    replayed: Vec<bool>,
+}
+
+// Single request and its raw response.  Bodies and responses not valid UTF-8 are base64 encoded.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Interaction {
+    method: String,
+    url: String,
+    #[serde(default)]
+    body: String,
+    response: String,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    base64: bool,
+}
+
+impl Vcr {
+    /// Open cassette at path in the given mode, loading the interactions of an existing file
+    pub fn new(path: &str, mode: VcrMode) -> Result<Self, Error> {
+        let exists = Path::new(path).exists();
+        let mode = match mode {
+            VcrMode::Auto if exists => VcrMode::Replay,
+            VcrMode::Auto => VcrMode::Record,
+            other => other,
+        };
+
+        // Load cassette
+        let mut cassette = Cassette::default();
+        if mode == VcrMode::Replay {
+            if !exists {
+                return Err(Error::FileNotExists(path.to_string()));
+            }
+            let contents = fs::read_to_string(path).map_err(Error::Io)?;
+            cassette = serde_json::from_str(&contents)
+                .map_err(|e| Error::Custom(format!("Invalid cassette {}, {}", path, e)))?;
+            cassette.replayed = vec![false; cassette.interactions.len()];
+        }
+
+        Ok(Self {
+            path: path.to_string(),
+            mode,
+            inner: Arc::new(DefaultConnector),
+            cassette: Arc::new(Mutex::new(cassette)),
+        })
+    }
+
+    /// Connect with the given connector while recording, instead of DefaultConnector
+    pub fn wrap<C: Connector + 'static>(mut self, inner: C) -> Self {
+        self.inner = Arc::new(inner);
+        self
+    }
+
+    /// Mode in effect, Record or Replay
+    pub fn mode(&self) -> VcrMode {
+        self.mode
+    }
+
+    /// Number of interactions within the cassette
+    pub fn len(&self) -> usize {
+        self.cassette.lock().unwrap().interactions.len()
+    }
+
+    /// Whether or not the cassette is empty
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl Connector for Vcr {
+    fn connect(
+        &self,
+        config: &HttpClientConfig,
+        uri: &Url,
+        port: u16,
+        sni: Option<&str>,
+        timeout: Duration,
+        timings: &mut Timings,
+    ) -> Result<Box<dyn HttpStream>, Error> {
+        // Requests are sent in origin-form, so are recorded under the origin connected to
+        let default_port = if uri.scheme() == "https" { 443 } else { 80 };
+        let origin = match port == default_port {
+            true => format!("{}://{}", uri.scheme(), uri.host_str().unwrap_or("")),
+            false => format!(
+                "{}://{}:{}",
+                uri.scheme(),
+                uri.host_str().unwrap_or(""),
+                port
+            ),
+        };
+
+        let stream: Box<dyn HttpStream> = match self.mode {
+            VcrMode::Replay => Box::new(ReplayStream {
+                origin,
+                cassette: Arc::clone(&self.cassette),
+                request: Vec::new(),
+                response: io::Cursor::new(Vec::new()),
+            }),
+            _ => Box::new(RecordStream {
+                inner: self
+                    .inner
+                    .connect(config, uri, port, sni, timeout, timings)?,
+                origin,
+                path: self.path.clone(),
+                cassette: Arc::clone(&self.cassette),
+                request: Vec::new(),
+                response: Vec::new(),
+            }),
+        };
+        Ok(stream)
+    }
+}
+
+// Split raw request into method, URL and body
+fn parse_request(origin: &str, request: &[u8]) -> Option<(String, String, Vec<u8>)> {
+    let end = request.windows(4).position(|w| w == b"\r\n\r\n")?;
+    let head = String::from_utf8_lossy(&request[..end]);
+    let mut parts = head.lines().next()?.split(' ');
+    let (method, target) = (parts.next()?, parts.next()?);
+
+    // Targets of requests through a HTTP proxy are absolute already
+    let url = match target.starts_with("http://") || target.starts_with("https://") {
+        true => target.to_string(),
+        false => format!("{}{}", origin, target),
+    };
+    Some((method.to_string(), url, request[end + 4..].to_vec()))
+}
+
+// Encode bytes as text, base64 if not valid UTF-8
+fn encode(bytes: &[u8], base64: bool) -> String {
+    match base64 {
+        true => STANDARD.encode(bytes),
+        false => String::from_utf8_lossy(bytes).to_string(),
+    }
+}
+
+// Decode text of the cassette
+fn decode(text: &str, base64: bool) -> Vec<u8> {
+    match base64 {
+        true => STANDARD.decode(text).unwrap_or_default(),
+        false => text.as_bytes().to_vec(),
+    }
+}
+
+// Stream to a real server, saving each request and the response read after it
+struct RecordStream {
+    inner: Box<dyn HttpStream>,
+    origin: String,
+    path: String,
+    cassette: Arc<Mutex<Cassette>>,
+    request: Vec<u8>,
+    response: Vec<u8>,
+}
+
+impl RecordStream {
+    // Save the exchange so far to the cassette file
+    fn finish(&mut self) -> io::Result<()> {
+        let request = std::mem::take(&mut self.request);
+        let response = std::mem::take(&mut self.response);
+        let (method, url, body) = match parse_request(&self.origin, &request) {
+            Some(r) if !response.is_empty() => r,
+            _ => return Ok(()),
+        };
+
+        let base64 = std::str::from_utf8(&body).is_err() || std::str::from_utf8(&response).is_err();
+        let mut cassette = self.cassette.lock().unwrap();
+        cassette.interactions.push(Interaction {
+            method,
+            url,
+            body: encode(&body, base64),
+            response: encode(&response, base64),
+            base64,
+        });
+
+        let contents = serde_json::to_string_pretty(&*cassette).map_err(io::Error::other)?;
+        fs::write(&self.path, contents)
+    }
+}
+
+impl Read for RecordStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.response.extend_from_slice(&buf[..n]);
+        Ok(n)
+    }
+}
+
+impl Write for RecordStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // Next request over a kept alive connection
+        if !self.response.is_empty() {
+            self.finish()?;
+        }
+        let n = self.inner.write(buf)?;
+        self.request.extend_from_slice(&buf[..n]);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl Drop for RecordStream {
+    fn drop(&mut self) {
+        let _ = self.finish();
+    }
+}
+
+impl HttpStream for RecordStream {
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        self.inner.set_read_timeout(timeout)
+    }
+
+    fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        self.inner.set_write_timeout(timeout)
+    }
+
+    fn alpn_protocol(&self) -> Option<String> {
+        self.inner.alpn_protocol()
+    }
+
+    fn tls_info(&self) -> Option<TlsInfo> {
+        self.inner.tls_info()
+    }
+
+    fn is_alive(&self) -> bool {
+        self.inner.is_alive()
+    }
+
+    fn shutdown(&mut self) -> io::Result<()> {
+        let _ = self.finish();
+        self.inner.shutdown()
+    }
+
+    fn send_early_data(&mut self, data: &[u8]) -> io::Result<bool> {
+        if !self.response.is_empty() {
+            self.finish()?;
+        }
+        let sent = self.inner.send_early_data(data)?;
+        if sent {
+            self.request.extend_from_slice(data);
+        }
+        Ok(sent)
+    }
+
+    fn is_handshaking(&self) -> bool {
+        self.inner.is_handshaking()
+    }
+
+    fn complete_handshake(&mut self) -> io::Result<()> {
+        self.inner.complete_handshake()
+    }
+}
+
+// In-memory stream answering each request written with the response recorded for it
+struct ReplayStream {
+    origin: String,
+    cassette: Arc<Mutex<Cassette>>,
+    request: Vec<u8>,
+    response: io::Cursor<Vec<u8>>,
+}
+
+impl ReplayStream {
+    // Find response to the request written, the first one not yet replayed or else the last
+    fn lookup(&mut self) -> io::Result<Vec<u8>> {
+        let not_found = |what: String| io::Error::new(ErrorKind::NotFound, what);
+        let (method, url, body) = parse_request(&self.origin, &self.request)
+            .ok_or_else(|| not_found("Incomplete request written during replay".to_string()))?;
+
+        let mut cassette = self.cassette.lock().unwrap();
+        let matches: Vec<usize> = cassette
+            .interactions
+            .iter()
+            .enumerate()
+            .filter(|(_, i)| {
+                i.method.eq_ignore_ascii_case(&method)
+                    && i.url == url
+                    && decode(&i.body, i.base64) == body
+            })
+            .map(|(index, _)| index)
+            .collect();
+        let index = matches
+            .iter()
+            .find(|index| !cassette.replayed[**index])
+            .or(matches.last())
+            .copied()
+            .ok_or_else(|| not_found(format!("No interaction recorded for {} {}", method, url)))?;
+
+        cassette.replayed[index] = true;
+        let interaction = &cassette.interactions[index];
+        Ok(decode(&interaction.response, interaction.base64))
+    }
+}
+
+impl Read for ReplayStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if !self.request.is_empty() {
+            // Let the body follow a header expecting 100-continue
+            let head = String::from_utf8_lossy(&self.request).to_lowercase();
+            let response =
+                match head.ends_with("\r\n\r\n") && head.contains("\r\nexpect: 100-continue") {
+                    true => b"HTTP/1.1 100 Continue\r\n\r\n".to_vec(),
+                    false => {
+                        let response = self.lookup()?;
+                        self.request.clear();
+                        response
+                    }
+                };
+            self.response = io::Cursor::new(response);
+        }
+        self.response.read(buf)
+    }
+}
+
+impl Write for ReplayStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.request.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl HttpStream for ReplayStream {
+    fn set_read_timeout(&self, _timeout: Option<Duration>) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,135 @@
+use super::{HttpClientConfig, HttpResponse, Redaction};
+use url::Url;
+
+/// Default number of body bytes logged per request or response
+pub const DEFAULT_BODY_LIMIT: usize = 1024;
+
+/// Wire log callback writing to stderr
+pub fn stderr(line: &str) {
+    eprintln!("{}", line);
+}
+
+/// Log exact request bytes as sent, head plus any body sent along with it.  Bodies streamed
+/// from files or readers are not logged.
+pub fn request(config: &HttpClientConfig, message: &[u8]) {
+    let callback = match config.wire_log {
+        Some(r) => r,
+        None => return,
+    };
+    let end = message
+        .windows(4)
+        .position(|w| w == b"\r\n\r\n")
+        .unwrap_or(message.len());
+    let head = String::from_utf8_lossy(&message[..end]);
+    let redaction = redaction(config);
+
+    let mut lines = Vec::new();
+    for (index, line) in head.split("\r\n").enumerate() {
+        lines.push(match index {
+            0 => format!("> {}", first_line(&redaction, line)),
+            _ => format!("> {}", header_line(&redaction, line)),
+        });
+    }
+    lines.push(">".to_string());
+    if let Some(body) = message.get(end + 4..).filter(|body| !body.is_empty()) {
+        lines.push(format!("> {}", truncate(body, config.wire_log_body_limit)));
+    }
+    callback(&lines.join("\n"));
+}
+
+/// Log status line and header of a response as received
+pub fn response_head(config: &HttpClientConfig, res: &HttpResponse) {
+    let callback = match config.wire_log {
+        Some(r) => r,
+        None => return,
+    };
+    let redaction = redaction(config);
+
+    let mut lines = vec![format!(
+        "< HTTP/{} {} {}",
+        res.version(),
+        res.status_code(),
+        res.reason()
+    )];
+    for (key, values) in res.headers().all().iter() {
+        for value in values {
+            lines.push(format!("< {}: {}", key, redaction.header_value(key, value)));
+        }
+    }
+    lines.push("<".to_string());
+    callback(&lines.join("\n"));
+}
+
+/// Log body of a response once read, after content decoding
+pub fn response_body(config: &HttpClientConfig, res: &HttpResponse) {
+    if let Some(callback) = config.wire_log {
+        let body = res.body_bytes();
+        if !body.is_empty() {
+            callback(&format!(
+                "< {}",
+                truncate(&body, config.wire_log_body_limit)
+            ));
+        }
+    }
+}
+
+// Redaction rules of the client, plus cookies which are always masked within the wire log
+fn redaction(config: &HttpClientConfig) -> Redaction {
+    let mut redaction = config.redaction.clone();
+    for name in [
+        "authorization",
+        "proxy-authorization",
+        "cookie",
+        "set-cookie",
+    ] {
+        if !redaction.headers.iter().any(|header| header == name) {
+            redaction = redaction.header(name);
+        }
+    }
+    redaction
+}
+
+// Request line with query string parameters masked
+fn first_line(redaction: &Redaction, line: &str) -> String {
+    let mut parts: Vec<&str> = line.splitn(3, ' ').collect();
+    let target = match parts.get(1) {
+        Some(r) if !redaction.query_params.is_empty() && r.contains('?') => r.to_string(),
+        _ => return line.to_string(),
+    };
+
+    // Targets in origin form are resolved against a placeholder to be parsed
+    let redacted = match Url::parse(&target) {
+        Ok(_) => redaction.url(&target),
+        Err(_) => redaction
+            .url(&format!("http://localhost{}", target))
+            .trim_start_matches("http://localhost")
+            .to_string(),
+    };
+    parts[1] = &redacted;
+    parts.join(" ")
+}
+
+// Header line with its value masked if needed
+fn header_line(redaction: &Redaction, line: &str) -> String {
+    match line.split_once(':') {
+        Some((key, value)) => format!("{}: {}", key, redaction.header_value(key, value.trim())),
+        None => line.to_string(),
+    }
+}
+
+// Body as text, cut off after limit bytes
+fn truncate(body: &[u8], limit: usize) -> String {
+    let shown = &body[..body.len().min(limit)];
+    let text = match std::str::from_utf8(shown) {
+        Ok(r) => r,
+        // Tolerate a character cut in half by the limit
+        Err(e) if e.error_len().is_none() => {
+            std::str::from_utf8(&shown[..e.valid_up_to()]).unwrap_or("")
+        }
+        Err(_) => return format!("[{} bytes of binary data]", body.len()),
+    };
+    match body.len() > text.len() {
+        true => format!("{}... [{} more bytes]", text, body.len() - text.len()),
+        false => text.to_string(),
+    }
+}